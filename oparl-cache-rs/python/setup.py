@@ -0,0 +1,9 @@
+from setuptools import setup
+from setuptools_rust import Binding, RustExtension
+
+setup(
+    name="oparl-cache",
+    version="0.1.0",
+    rust_extensions=[RustExtension("oparl_cache", "Cargo.toml", binding=Binding.RustCPython)],
+    zip_safe=False,
+)
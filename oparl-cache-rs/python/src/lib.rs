@@ -0,0 +1,177 @@
+//! Python bindings for the OParl cache
+//!
+//! ```python
+//! import oparl_cache
+//!
+//! storage = oparl_cache.FileStorage("oparl/schema", "cache")
+//! storage.cache(oparl_cache.CommonServer("https://example.com/oparl/v1.0"), progress=print)
+//! paper = storage.get("https://example.com/oparl/v1.0/paper/1")
+//! ```
+
+#[macro_use]
+extern crate cpython;
+extern crate json;
+extern crate oparl_cache;
+
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use cpython::{PyDict, PyErr, PyList, PyObject, PyResult, Python, PythonObject, ToPyObject};
+use cpython::exc::{KeyError, RuntimeError, ValueError};
+use json::JsonValue;
+use oparl_cache::{Cacher, IntoUrl, Progress, Storage, Url};
+
+py_module_initializer!(oparl_cache, initoparl_cache, PyInit_oparl_cache, |py, m| {
+    m.add(py, "__doc__", "Downloads OParl servers into a file based cache")?;
+    m.add_class::<FileStorage>(py)?;
+    m.add_class::<CommonServer>(py)?;
+    Ok(())
+});
+
+fn runtime_error(py: Python, err: Box<Error>) -> PyErr {
+    PyErr::new::<RuntimeError, _>(py, err.to_string())
+}
+
+fn parse_url(py: Python, url: &str) -> PyResult<Url> {
+    url.into_url().map_err(|err| PyErr::new::<ValueError, _>(py, format!("Invalid url {}: {}", url, err)))
+}
+
+/// Converts json to the corresponding python objects, i.e. objects become dicts
+fn to_python(py: Python, value: &JsonValue) -> PyResult<PyObject> {
+    if let Some(string) = value.as_str() {
+        return Ok(string.to_py_object(py).into_object());
+    }
+
+    Ok(match *value {
+        JsonValue::Null => py.None(),
+        JsonValue::Boolean(boolean) => boolean.to_py_object(py).into_object(),
+        JsonValue::Number(_) => {
+            let float = value.as_f64().unwrap_or(0.0);
+            match value.as_i64() {
+                Some(integer) if integer as f64 == float => integer.to_py_object(py).into_object(),
+                _ => float.to_py_object(py).into_object(),
+            }
+        }
+        JsonValue::Array(ref members) => {
+            let mut items = vec![];
+            for member in members {
+                items.push(to_python(py, member)?);
+            }
+            PyList::new(py, &items).into_object()
+        }
+        JsonValue::Object(_) => {
+            let dict = PyDict::new(py);
+            for (key, member) in value.entries() {
+                dict.set_item(py, key, to_python(py, member)?)?;
+            }
+            dict.into_object()
+        }
+        _ => py.None(),
+    })
+}
+
+/// Converts the progress of a sync run to a dict with an `event` key
+fn progress_dict(py: Python, progress: Progress) -> PyResult<PyDict> {
+    let dict = PyDict::new(py);
+    match progress {
+        Progress::ListStarted(url) => {
+            dict.set_item(py, "event", "list_started")?;
+            dict.set_item(py, "url", url.as_str())?;
+        }
+        Progress::ListFinished(report) => {
+            dict.set_item(py, "event", "list_finished")?;
+            dict.set_item(py, "url", &report.url)?;
+            dict.set_item(py, "failed", report.failed)?;
+            dict.set_item(py, "objects", report.objects)?;
+            dict.set_item(py, "invalid_objects", report.invalid_objects)?;
+            dict.set_item(py, "errors", report.errors.clone())?;
+        }
+    }
+    Ok(dict)
+}
+
+py_class!(class CommonServer |py| {
+    data entrypoint: Url;
+
+    def __new__(_cls, entrypoint: &str) -> PyResult<CommonServer> {
+        CommonServer::create_instance(py, parse_url(py, entrypoint)?)
+    }
+
+    @property def entrypoint(&self) -> PyResult<String> {
+        Ok(self.entrypoint(py).to_string())
+    }
+});
+
+py_class!(class FileStorage |py| {
+    data storage: oparl_cache::FileStorage<'static>;
+
+    def __new__(_cls, schema_dir: &str, cache_dir: &str) -> PyResult<FileStorage> {
+        let storage = oparl_cache::FileStorage::new(Path::new(schema_dir), PathBuf::from(cache_dir))
+            .map_err(|err| runtime_error(py, err))?;
+        FileStorage::create_instance(py, storage)
+    }
+
+    /// Downloads the server into the cache or updates the cache. `progress` is called with a
+    /// dict whenever a list is started or finished. If it raises an exception, it isn't called
+    /// anymore and the exception is raised once the sync finished
+    def cache(&self, server: CommonServer, progress: Option<PyObject> = None) -> PyResult<PyObject> {
+        let mut storage = self.storage(py).clone();
+        let callback_error: Arc<Mutex<Option<PyErr>>> = Arc::new(Mutex::new(None));
+        if let Some(callback) = progress {
+            let callback_error = callback_error.clone();
+            storage.set_progress_callback(move |progress| {
+                // The lists are loaded in worker threads
+                let gil = Python::acquire_gil();
+                let py = gil.python();
+                let mut first_error = callback_error.lock().unwrap();
+                if first_error.is_some() {
+                    return;
+                }
+                let result = progress_dict(py, progress).and_then(|x| callback.call(py, (x,), None));
+                if let Err(err) = result {
+                    *first_error = Some(err);
+                }
+            });
+        }
+
+        let server = oparl_cache::CommonServer::new(server.entrypoint(py).clone());
+        let result = {
+            let storage = &storage;
+            py.allow_threads(move || storage.cache(server).map_err(|err| err.to_string()))
+        };
+        if let Some(err) = callback_error.lock().unwrap().take() {
+            return Err(err);
+        }
+        result.map_err(|err| PyErr::new::<RuntimeError, _>(py, err))?;
+        Ok(py.None())
+    }
+
+    /// Returns the cached object or list with the url as dict or list
+    def get(&self, url: &str) -> PyResult<PyObject> {
+        let object = self.storage(py)
+            .get(&parse_url(py, url)?)
+            .map_err(|err| PyErr::new::<KeyError, _>(py, format!("{} is not cached: {}", url, err)))?;
+        to_python(py, &object)
+    }
+
+    /// Returns the entrypoints of the cached servers
+    def cached_servers(&self) -> PyResult<Vec<String>> {
+        let servers = self.storage(py)
+            .get_cached_servers()
+            .map_err(|err| runtime_error(py, From::from(err)))?;
+        Ok(servers.into_iter().map(|x| x.to_string()).collect())
+    }
+
+    /// Returns the cached objects of the server, optionally only those with the given type such
+    /// as `Paper`
+    def objects(&self, entrypoint: &str, type_filter: Option<String> = None) -> PyResult<PyList> {
+        let entrypoint = parse_url(py, entrypoint)?;
+        let mut objects = vec![];
+        for object in self.storage(py).iter_objects(&entrypoint, type_filter.as_ref().map(|x| x.as_str())) {
+            let object = object.map_err(|err| runtime_error(py, err))?;
+            objects.push(to_python(py, &object)?);
+        }
+        Ok(PyList::new(py, &objects))
+    }
+});
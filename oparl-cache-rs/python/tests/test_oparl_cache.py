@@ -0,0 +1,93 @@
+"""Tests for the Python bindings. Run them after `python setup.py develop` with
+`python -m unittest discover tests` from the python folder"""
+
+import json
+import os
+import shutil
+import tempfile
+import threading
+import unittest
+from http.server import BaseHTTPRequestHandler, HTTPServer
+from urllib.parse import urlparse
+
+import oparl_cache
+
+SCHEMA_DIR = os.path.join(os.path.dirname(__file__), "..", "..", "oparl", "schema")
+
+
+class OParlHandler(BaseHTTPRequestHandler):
+    """Serves a system with a single body, ignoring the filter parameters"""
+
+    def do_GET(self):
+        base = "http://{}:{}".format(*self.server.server_address)
+        responses = {
+            "/oparl": {
+                "id": base + "/oparl",
+                "type": "https://schema.oparl.org/1.0/System",
+                "body": base + "/oparl/body",
+            },
+            "/oparl/body": {
+                "data": [{
+                    "id": base + "/oparl/body/1",
+                    "type": "https://schema.oparl.org/1.0/Body",
+                    "name": "Example City",
+                }],
+                "links": {},
+            },
+        }
+        response = responses.get(urlparse(self.path).path)
+        if response is None:
+            self.send_error(404)
+            return
+        body = json.dumps(response).encode("utf-8")
+        self.send_response(200)
+        self.send_header("Content-Type", "application/json")
+        self.send_header("Content-Length", str(len(body)))
+        self.end_headers()
+        self.wfile.write(body)
+
+    def log_message(self, *args):
+        pass
+
+
+class FileStorageTest(unittest.TestCase):
+    def setUp(self):
+        self.server = HTTPServer(("127.0.0.1", 0), OParlHandler)
+        threading.Thread(target=self.server.serve_forever, daemon=True).start()
+        self.entrypoint = "http://127.0.0.1:{}/oparl".format(self.server.server_address[1])
+        self.cache_dir = tempfile.mkdtemp()
+        self.storage = oparl_cache.FileStorage(SCHEMA_DIR, self.cache_dir)
+
+    def tearDown(self):
+        self.server.shutdown()
+        self.server.server_close()
+        shutil.rmtree(self.cache_dir)
+
+    def test_cache(self):
+        events = []
+        self.storage.cache(oparl_cache.CommonServer(self.entrypoint), progress=events.append)
+
+        self.assertIn("list_started", [x["event"] for x in events])
+        finished = [x for x in events if x["event"] == "list_finished"]
+        self.assertEqual([x["failed"] for x in finished], [False])
+        self.assertEqual(self.storage.cached_servers(), [self.entrypoint])
+        self.assertEqual(self.storage.get(self.entrypoint + "/body/1")["name"], "Example City")
+        self.assertEqual([x["id"] for x in self.storage.objects(self.entrypoint, "Body")],
+                         [self.entrypoint + "/body/1"])
+
+    def test_progress_exception(self):
+        def progress(event):
+            raise ValueError("Stop")
+
+        with self.assertRaises(ValueError):
+            self.storage.cache(oparl_cache.CommonServer(self.entrypoint), progress=progress)
+
+    def test_errors(self):
+        with self.assertRaises(KeyError):
+            self.storage.get(self.entrypoint + "/body/2")
+        with self.assertRaises(ValueError):
+            oparl_cache.CommonServer("not a url")
+
+
+if __name__ == "__main__":
+    unittest.main()
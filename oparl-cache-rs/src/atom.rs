@@ -0,0 +1,334 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::error::Error;
+use std::fs::{File, create_dir_all};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use chrono::{DateTime, Utc};
+use json::JsonValue;
+use reqwest::{IntoUrl, Url};
+
+use files::checksum;
+use ical::url_slug;
+use schema::split_type_url;
+use storage::Storage;
+
+/// The number of papers in a feed if no limit is given
+pub const DEFAULT_FEED_LIMIT: usize = 50;
+
+/// The file extension of the exported feeds
+pub const FEED_EXTENSION: &'static str = ".xml";
+
+/// The `<updated>` of papers without `modified` and `created` and of empty feeds, as Atom
+/// requires a time for both
+const FALLBACK_UPDATED: &'static str = "1970-01-01T00:00:00+00:00";
+
+/// The papers a feed covers
+#[derive(Clone, Debug, PartialEq)]
+pub enum FeedSelection {
+    /// The papers of the body with this id
+    Body(String),
+    /// The papers under the direction of or consulted by the organization with this id
+    Organization(String),
+    /// The papers with this keyword, compared case insensitively
+    Keyword(String),
+}
+
+/// The cached papers together with the organizations consulting them and the ids of the bodies
+/// and the organizations that have feeds
+struct Papers {
+    papers: Vec<JsonValue>,
+    consulted_by: BTreeMap<String, BTreeSet<String>>,
+    bodies: Vec<String>,
+    organizations: Vec<String>,
+}
+
+impl Papers {
+    /// Loads the papers of the servers with the given entrypoints in a single walk per server.
+    /// Objects shared by several servers are only read once
+    fn load<S: Storage>(storage: &S, entrypoints: &[Url]) -> Result<Papers, Box<Error>> {
+        let mut papers = vec![];
+        let mut consulted_by = BTreeMap::new();
+        let mut bodies = vec![];
+        let mut organizations = vec![];
+        let mut seen = HashSet::new();
+
+        for entrypoint in entrypoints {
+            for object in storage.iter_objects_of(entrypoint, &["Paper", "Consultation", "Body", "Organization"]) {
+                let object = object?;
+                if !seen.insert(object["id"].to_string()) {
+                    continue;
+                }
+                match split_type_url(&object["type"].to_string())?.1.as_str() {
+                    "Paper" => papers.push(object),
+                    "Consultation" => {
+                        if let Some(paper) = object["paper"].as_str() {
+                            let consulting = consulted_by.entry(paper.to_string()).or_insert_with(BTreeSet::new);
+                            for organization in object["organization"].members() {
+                                consulting.insert(organization.to_string());
+                            }
+                        }
+                    }
+                    "Body" => bodies.push(object["id"].to_string()),
+                    _ => organizations.push(object["id"].to_string()),
+                }
+            }
+        }
+
+        Ok(Papers {
+            papers: papers,
+            consulted_by: consulted_by,
+            bodies: bodies,
+            organizations: organizations,
+        })
+    }
+
+    fn matches(&self, paper: &JsonValue, selection: &FeedSelection) -> bool {
+        match *selection {
+            FeedSelection::Body(ref body) => paper["body"] == body.as_str(),
+            FeedSelection::Organization(ref organization) => {
+                paper["underDirectionOf"].members().any(|x| *x == organization.as_str()) ||
+                self.consulted_by
+                    .get(&paper["id"].to_string())
+                    .map_or(false, |x| x.contains(organization))
+            }
+            FeedSelection::Keyword(ref keyword) => {
+                paper["keyword"]
+                    .members()
+                    .filter_map(|x| x.as_str())
+                    .any(|x| x.to_lowercase() == keyword.to_lowercase())
+            }
+        }
+    }
+}
+
+/// Returns the time a paper was last changed in RFC 3339
+fn updated(paper: &JsonValue) -> Option<String> {
+    ["modified", "created"]
+        .iter()
+        .filter_map(|&x| paper[x].as_str())
+        .filter_map(|x| DateTime::parse_from_rfc3339(x).ok())
+        .map(|x| x.with_timezone(&Utc).to_rfc3339())
+        .next()
+}
+
+/// Escapes text for xml content and attributes
+fn escape_xml(text: &str) -> String {
+    text.replace("&", "&amp;")
+        .replace("<", "&lt;")
+        .replace(">", "&gt;")
+        .replace("\"", "&quot;")
+        .replace("'", "&apos;")
+}
+
+fn entry<S: Storage>(storage: &S, output: &mut String, paper: &JsonValue) {
+    let id = paper["id"].to_string();
+    let title = match (paper["reference"].as_str(), paper["name"].as_str()) {
+        (Some(reference), Some(name)) => format!("{}: {}", reference, name),
+        (None, Some(name)) => name.to_string(),
+        (Some(reference), None) => reference.to_string(),
+        (None, None) => id.clone(),
+    };
+
+    output.push_str("  <entry>\n");
+    output.push_str(&format!("    <id>{}</id>\n", escape_xml(&id)));
+    output.push_str(&format!("    <title>{}</title>\n", escape_xml(&title)));
+    let updated = updated(paper).unwrap_or_else(|| FALLBACK_UPDATED.to_string());
+    output.push_str(&format!("    <updated>{}</updated>\n", updated));
+    if let Some(created) = paper["created"].as_str().and_then(|x| DateTime::parse_from_rfc3339(x).ok()) {
+        output.push_str(&format!("    <published>{}</published>\n", created.with_timezone(&Utc).to_rfc3339()));
+    }
+    let link = paper["web"].as_str().unwrap_or(&id).to_string();
+    output.push_str(&format!("    <link rel=\"alternate\" href=\"{}\"/>\n", escape_xml(&link)));
+
+    let files = paper["mainFile"].as_str().into_iter().chain(paper["auxiliaryFile"].members().filter_map(|x| x.as_str()));
+    for file_id in files {
+        let file = match file_id.into_url().ok().and_then(|x| storage.get(&x).ok()) {
+            Some(file) => file,
+            None => continue,
+        };
+        let href = match file["accessUrl"].as_str() {
+            Some(href) => href,
+            None => continue,
+        };
+        let mut attributes = format!("rel=\"enclosure\" href=\"{}\"", escape_xml(href));
+        if let Some(mime_type) = file["mimeType"].as_str() {
+            attributes += &format!(" type=\"{}\"", escape_xml(mime_type));
+        }
+        if let Some(name) = file["name"].as_str().or(file["fileName"].as_str()) {
+            attributes += &format!(" title=\"{}\"", escape_xml(name));
+        }
+        if let Some(size) = file["size"].as_u64() {
+            attributes += &format!(" length=\"{}\"", size);
+        }
+        output.push_str(&format!("    <link {}/>\n", attributes));
+    }
+
+    if let Some(paper_type) = paper["paperType"].as_str() {
+        output.push_str(&format!("    <category term=\"{}\"/>\n", escape_xml(paper_type)));
+    }
+    for keyword in paper["keyword"].members().filter_map(|x| x.as_str()) {
+        output.push_str(&format!("    <category term=\"{}\"/>\n", escape_xml(keyword)));
+    }
+    output.push_str("  </entry>\n");
+}
+
+/// Renders an Atom feed with the latest changed papers of `selection`, newest first
+fn render_feed<S: Storage>(storage: &S,
+                           papers: &Papers,
+                           feed_id: &str,
+                           title: &str,
+                           selection: &FeedSelection,
+                           limit: usize)
+                           -> String {
+    let mut selected = papers.papers.iter().filter(|x| papers.matches(x, selection)).collect::<Vec<_>>();
+    // RFC 3339 in UTC sorts chronologically
+    selected.sort_by(|a, b| updated(b).cmp(&updated(a)));
+    selected.truncate(limit);
+
+    let feed_updated = selected.first()
+        .and_then(|x| updated(x))
+        .unwrap_or_else(|| FALLBACK_UPDATED.to_string());
+
+    let mut output = String::new();
+    output.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    output.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    output.push_str(&format!("  <id>{}</id>\n", escape_xml(feed_id)));
+    output.push_str(&format!("  <title>{}</title>\n", escape_xml(title)));
+    output.push_str(&format!("  <updated>{}</updated>\n", feed_updated));
+    output.push_str("  <author><name>OParl Cache</name></author>\n");
+    for paper in selected {
+        entry(storage, &mut output, paper);
+    }
+    output.push_str("</feed>\n");
+    output
+}
+
+/// Returns the title and the id of the feed of `selection`
+fn feed_metadata<S: Storage>(storage: &S, selection: &FeedSelection) -> (String, String) {
+    let name_of = |id: &str| {
+        id.into_url()
+            .ok()
+            .and_then(|x| storage.get(&x).ok())
+            .and_then(|x| x["name"].as_str().map(String::from))
+            .unwrap_or_else(|| id.to_string())
+    };
+
+    match *selection {
+        FeedSelection::Body(ref id) => (format!("Papers of {}", name_of(id)), id.clone()),
+        FeedSelection::Organization(ref id) => (format!("Papers of {}", name_of(id)), id.clone()),
+        FeedSelection::Keyword(ref keyword) => {
+            (format!("Papers about {}", keyword), format!("urn:oparl-cache:keyword:{}", keyword.to_lowercase()))
+        }
+    }
+}
+
+/// Renders the feed of the new and changed papers of `selection` in the cached servers with the
+/// given entrypoints
+pub fn paper_feed<S: Storage>(storage: &S,
+                              entrypoints: &[Url],
+                              selection: &FeedSelection,
+                              limit: usize)
+                              -> Result<String, Box<Error>> {
+    let papers = Papers::load(storage, entrypoints)?;
+    let (title, feed_id) = feed_metadata(storage, selection);
+    Ok(render_feed(storage, &papers, &feed_id, &title, selection, limit))
+}
+
+/// Keeps the papers of a set of servers in memory for rendering feeds on request. They are
+/// loaded on the first request and again once one of the servers was synced, as reported by
+/// `Storage::last_updated`
+pub struct FeedIndex {
+    entrypoints: Vec<Url>,
+    loaded: Mutex<Option<(Vec<Option<SystemTime>>, Arc<Papers>)>>,
+}
+
+impl FeedIndex {
+    /// Creates an index of the papers of the servers with the given entrypoints
+    pub fn new(entrypoints: Vec<Url>) -> FeedIndex {
+        FeedIndex {
+            entrypoints: entrypoints,
+            loaded: Mutex::new(None),
+        }
+    }
+
+    /// Returns the loaded papers, reloading them if a server was synced since
+    fn papers<S: Storage>(&self, storage: &S) -> Result<Arc<Papers>, Box<Error>> {
+        let updated = self.entrypoints.iter().map(|x| storage.last_updated(x)).collect::<Vec<_>>();
+        let mut loaded = self.loaded.lock().unwrap();
+        if let Some((ref known, ref papers)) = *loaded {
+            if *known == updated {
+                return Ok(papers.clone());
+            }
+        }
+
+        let papers = Arc::new(Papers::load(storage, &self.entrypoints)?);
+        *loaded = Some((updated, papers.clone()));
+        Ok(papers)
+    }
+
+    /// Renders the feed of the new and changed papers of `selection` like `paper_feed`
+    pub fn feed<S: Storage>(&self,
+                            storage: &S,
+                            selection: &FeedSelection,
+                            limit: usize)
+                            -> Result<String, Box<Error>> {
+        let papers = self.papers(storage)?;
+        let (title, feed_id) = feed_metadata(storage, selection);
+        Ok(render_feed(storage, &papers, &feed_id, &title, selection, limit))
+    }
+}
+
+/// Writes a feed for every cached body, organization and keyword of the server to `dir`, e.g.
+/// `body-oparl-body-1.xml` or `keyword-budget.xml`. Keywords with the same file name get the first
+/// eight hex digits of their SHA1 hash appended, e.g. `keyword-budget-2018-1a2b3c4d.xml`. Returns the
+/// number of feeds
+pub fn export_feeds<S: Storage>(storage: &S,
+                                entrypoint: &Url,
+                                dir: &Path,
+                                limit: usize)
+                                -> Result<usize, Box<Error>> {
+    create_dir_all(dir)?;
+    let papers = Papers::load(storage, &[entrypoint.clone()])?;
+
+    let mut selections = vec![];
+    for id in &papers.bodies {
+        selections.push((format!("body-{}", url_slug(&id.as_str().into_url()?)), FeedSelection::Body(id.clone())));
+    }
+    for id in &papers.organizations {
+        selections.push((format!("organization-{}", url_slug(&id.as_str().into_url()?)),
+                         FeedSelection::Organization(id.clone())));
+    }
+
+    let keywords = papers.papers
+        .iter()
+        .flat_map(|x| x["keyword"].members().filter_map(|x| x.as_str()).map(|x| x.to_lowercase()))
+        .collect::<BTreeSet<_>>();
+    let mut by_slug = BTreeMap::new();
+    for keyword in keywords {
+        let slug = keyword.chars().map(|x| if x.is_alphanumeric() { x } else { '-' }).collect::<String>();
+        by_slug.entry(slug).or_insert_with(Vec::new).push(keyword);
+    }
+    for (slug, keywords) in by_slug {
+        // Keywords like "Budget 2018" and "budget/2018" share a slug and are told apart by a hash
+        let unique = keywords.len() == 1;
+        for keyword in keywords {
+            let name = if unique {
+                format!("keyword-{}", slug)
+            } else {
+                format!("keyword-{}-{}", slug, &checksum(keyword.as_bytes())[..8])
+            };
+            selections.push((name, FeedSelection::Keyword(keyword)));
+        }
+    }
+
+    for &(ref name, ref selection) in &selections {
+        let (title, feed_id) = feed_metadata(storage, selection);
+        let feed = render_feed(storage, &papers, &feed_id, &title, selection, limit);
+        File::create(dir.join(name.clone() + FEED_EXTENSION))?.write_all(feed.as_bytes())?;
+    }
+
+    Ok(selections.len())
+}
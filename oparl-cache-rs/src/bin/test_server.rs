@@ -0,0 +1,66 @@
+//! Serves a synthetic OParl 1.0 dataset for end-to-end tests
+//! Use `cargo run --bin oparl-test-server -- --help` to get information on the available options
+
+extern crate oparl_cache;
+#[macro_use]
+extern crate clap;
+
+use std::io::stdin;
+use std::sync::{Arc, RwLock};
+
+use oparl_cache::synthetic::{DatasetConfig, Mutations, SyntheticDataset, serve};
+
+fn main() {
+    let matches = clap_app!(OParl_Test_Server =>
+        (about: "Serves a synthetic OParl server. Press enter to mutate the papers.")
+        (@arg listen: --listen +takes_value "The address to listen on [default: 127.0.0.1:8080]")
+        (@arg bodies: --bodies +takes_value "The number of bodies [default: 1]")
+        (@arg papers: --papers +takes_value "The number of papers per body [default: 20]")
+        (@arg meetings: --meetings +takes_value "The number of meetings per body [default: 10]")
+        (@arg persons: --persons +takes_value "The number of persons per body [default: 10]")
+        (@arg files: --files +takes_value "The number of files per body [default: 20]")
+        (@arg page_size: --("page-size") +takes_value "The number of objects per list page [default: 5]")
+        (@arg added: --added +takes_value "The number of papers added per mutation [default: 1]")
+        (@arg modified: --modified +takes_value "The number of papers renamed per mutation [default: 1]")
+        (@arg deleted: --deleted +takes_value "The number of papers deleted per mutation [default: 0]")
+    )
+        .get_matches();
+
+    let default = DatasetConfig::default();
+    let config = DatasetConfig {
+        bodies: value_t!(matches, "bodies", usize).unwrap_or(default.bodies),
+        papers: value_t!(matches, "papers", usize).unwrap_or(default.papers),
+        meetings: value_t!(matches, "meetings", usize).unwrap_or(default.meetings),
+        persons: value_t!(matches, "persons", usize).unwrap_or(default.persons),
+        files: value_t!(matches, "files", usize).unwrap_or(default.files),
+        page_size: value_t!(matches, "page_size", usize).unwrap_or(default.page_size),
+        ..default
+    };
+    let mutations = Mutations {
+        added: value_t!(matches, "added", usize).unwrap_or(1),
+        modified: value_t!(matches, "modified", usize).unwrap_or(1),
+        deleted: value_t!(matches, "deleted", usize).unwrap_or(0),
+    };
+
+    let address = matches.value_of("listen").unwrap_or("127.0.0.1:8080");
+    let dataset = Arc::new(RwLock::new(SyntheticDataset::new(config)));
+    let _listening = match serve(address, dataset.clone()) {
+        Ok(ok) => ok,
+        Err(err) => {
+            println!("✗ Failed to listen on {}: {}", address, err);
+            return;
+        }
+    };
+
+    println!("Serving the entrypoint {}", oparl_cache::Server::get_entrypoint(&*dataset.read().unwrap()));
+
+    // Every line on stdin triggers a mutation, so that incremental updates can be tested
+    let mut line = String::new();
+    while stdin().read_line(&mut line).map(|x| x > 0).unwrap_or(false) {
+        dataset.write().unwrap().mutate(&mutations);
+        println!("Mutated the papers: {:?}", mutations);
+        line.clear();
+    }
+
+    // Dropping the listening server waits for its threads, so this keeps serving after stdin was closed
+}
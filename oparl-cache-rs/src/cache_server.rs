@@ -0,0 +1,145 @@
+use std::error::Error;
+use std::io::Read;
+use std::sync::Arc;
+
+use hyper::header::ContentType;
+use hyper::method::Method;
+use hyper::server::{Server as HttpServer, Listening, Request, Response};
+use hyper::status::StatusCode;
+use json::{self, JsonValue};
+use reqwest::{IntoUrl, Url};
+
+use atom::{FeedIndex, FeedSelection, DEFAULT_FEED_LIMIT};
+use graphql::{execute, render_schema};
+use http::{request_url, respond, not_found};
+use ical::calendar;
+use refs::ReferenceIndex;
+
+/// Requests with larger bodies are rejected
+const MAX_BODY_SIZE: u64 = 1024 * 1024;
+
+/// Reads the query and the variables of a GraphQL request. Both POST with a json body and GET
+/// with the `query` and `variables` parameters are accepted
+fn read_graphql_request(request: &mut Request) -> Result<(String, JsonValue), Box<Error>> {
+    let url = request_url(request).ok_or("Invalid request url")?;
+
+    let payload = if request.method == Method::Post {
+        let mut body = String::new();
+        request.take(MAX_BODY_SIZE).read_to_string(&mut body)?;
+        json::parse(&body)?
+    } else {
+        let mut payload = JsonValue::new_object();
+        for (key, value) in url.query_pairs() {
+            payload[key.as_ref()] = match key.as_ref() {
+                "variables" => json::parse(&value)?,
+                _ => value.as_ref().into(),
+            };
+        }
+        payload
+    };
+
+    let query = payload["query"].as_str().ok_or("The request contains no query")?.to_string();
+    Ok((query, payload["variables"].clone()))
+}
+
+/// Renders the calendar of the body or organization given in the `id` parameter
+fn calendar_response<S: ReferenceIndex>(storage: &S, url: &Url) -> Result<String, Box<Error>> {
+    let id = url.query_pairs()
+        .find(|&(ref key, _)| key == "id")
+        .map(|(_, value)| value.into_owned())
+        .ok_or("The id parameter is missing")?;
+    calendar(storage, &storage.get(&id.into_url()?)?)
+}
+
+/// Reads the feed selected with the `body`, `organization` or `keyword` parameter and the
+/// optional `limit`
+fn read_feed_request(url: &Url) -> Result<(FeedSelection, usize), Box<Error>> {
+    let mut selection = None;
+    let mut limit = DEFAULT_FEED_LIMIT;
+    for (key, value) in url.query_pairs() {
+        match key.as_ref() {
+            "body" => selection = Some(FeedSelection::Body(value.into_owned())),
+            "organization" => selection = Some(FeedSelection::Organization(value.into_owned())),
+            "keyword" => selection = Some(FeedSelection::Keyword(value.into_owned())),
+            "limit" => {
+                limit = value.parse()
+                    .map_err(|_| format!("The limit {} is not a non-negative integer", value))?
+            }
+            _ => {}
+        }
+    }
+
+    let selection = selection.ok_or("One of the parameters body, organization or keyword is required")?;
+    Ok((selection, limit))
+}
+
+/// Serves the cached objects on `address` in background threads:
+///
+///  - `/graphql` executes GraphQL queries, see the `graphql` module
+///  - `/graphql/schema` returns the GraphQL schema
+///  - `/calendar?id=<id>` returns the iCalendar feed of a body or an organization
+///  - `/feed?body=<id>`, `/feed?organization=<id>` and `/feed?keyword=<keyword>` return the
+///    Atom feeds of the new and changed papers of the servers with the given entrypoints
+pub fn serve_cache<S>(address: &str, storage: Arc<S>, entrypoints: Vec<Url>) -> Result<Listening, Box<Error>>
+    where S: ReferenceIndex + Send + Sync + 'static
+{
+    let feeds = FeedIndex::new(entrypoints);
+    let handler = move |mut request: Request, response: Response| {
+        let url = match request_url(&request) {
+            Some(url) => url,
+            None => return not_found(response),
+        };
+        match url.path() {
+            "/graphql" => {
+                match read_graphql_request(&mut request) {
+                    Ok((query, variables)) => {
+                        let body = execute(&*storage, &query, &variables);
+                        respond(response, StatusCode::Ok, ContentType::json(), body.dump().as_bytes());
+                    }
+                    Err(err) => {
+                        let body = object!{ "errors" => array![object!{ "message" => err.to_string() }] };
+                        respond(response, StatusCode::BadRequest, ContentType::json(), body.dump().as_bytes());
+                    }
+                }
+            }
+            "/graphql/schema" => {
+                let body = render_schema(storage.get_schema());
+                respond(response, StatusCode::Ok, ContentType::plaintext(), body.as_bytes());
+            }
+            "/calendar" => {
+                match calendar_response(&*storage, &url) {
+                    Ok(body) => {
+                        let content_type = ContentType("text/calendar; charset=utf-8".parse().unwrap());
+                        respond(response, StatusCode::Ok, content_type, body.as_bytes());
+                    }
+                    Err(err) => {
+                        let body = format!("{}\n", err);
+                        respond(response, StatusCode::NotFound, ContentType::plaintext(), body.as_bytes());
+                    }
+                }
+            }
+            "/feed" => {
+                let (selection, limit) = match read_feed_request(&url) {
+                    Ok(request) => request,
+                    Err(err) => {
+                        let body = format!("{}\n", err);
+                        return respond(response, StatusCode::BadRequest, ContentType::plaintext(), body.as_bytes());
+                    }
+                };
+                match feeds.feed(&*storage, &selection, limit) {
+                    Ok(body) => {
+                        let content_type = ContentType("application/atom+xml; charset=utf-8".parse().unwrap());
+                        respond(response, StatusCode::Ok, content_type, body.as_bytes());
+                    }
+                    Err(err) => {
+                        let body = format!("{}\n", err);
+                        respond(response, StatusCode::NotFound, ContentType::plaintext(), body.as_bytes());
+                    }
+                }
+            }
+            _ => not_found(response),
+        }
+    };
+
+    Ok(HttpServer::http(address)?.handle(handler)?)
+}
@@ -1,7 +1,8 @@
 use std::collections::VecDeque;
 use std::error::Error;
+use std::sync::MutexGuard;
 use std::sync::mpsc::{Sender, channel};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::io::stdout;
 
 use reqwest::Url;
@@ -12,8 +13,14 @@ use chrono::Local;
 
 use server::Server;
 use storage::Storage;
+use refs::{ReferenceIndex, collect_references};
 use file_storage::{CacheStatus, UrlWithTimestamp};
 use external_list::ExternalList;
+use time_window::TimeWindow;
+use object_filter::ObjectFilter;
+use canonical::IdRewriter;
+use schema::{split_type_url, version_from_url, is_external_list};
+use sync_report::{ListReport, MeasuringServer, ChangedObject};
 
 /// The type of the messages send from the worker to main thread
 #[derive(Debug)]
@@ -26,57 +33,174 @@ pub enum Message {
 
 type ListSender = Sender<Message>;
 
+/// The progress of a sync run as reported to `Cacher::report_progress`
+#[derive(Debug)]
+pub enum Progress<'r> {
+    /// A worker started loading the external list with this url
+    ListStarted(&'r Url),
+    /// A worker finished loading an external list, successfully or not
+    ListFinished(&'r ListReport),
+}
+
 /// A Storage able to consume all data from a server
 ///
 /// TODO: Refactor out helper functions
-pub trait Cacher: Storage + Sync {
+pub trait Cacher: ReferenceIndex + Sync {
     /// Consumes all data from a server
     fn cache<T: Server>(&self, server: T) -> Result<(), Box<Error>>;
 
+    /// Downloads only the objects in `window` and merges them into an existing cache
+    fn backfill<T: Server>(&self, server: T, window: &TimeWindow) -> Result<(), Box<Error>>;
+
+    /// Returns the rules deciding which external lists are followed
+    fn get_object_filter(&self) -> &ObjectFilter;
+
+    /// Returns the rules for rewriting ids and references to their canonical form
+    fn get_id_rewriter(&self) -> &IdRewriter;
+
+    /// Returns the maximal number of lists loaded at the same time or `None` for no limit
+    fn get_max_workers(&self) -> Option<usize>;
+
+    /// Called with the progress of the sync runs. Lists finish in the worker threads, so this
+    /// must be cheap and thread safe
+    fn report_progress(&self, _progress: Progress) {}
+
+    /// Locks the object `id` while it's compared with its cached version and written, so that
+    /// two workers writing the same object can't leave the reference index inconsistent
+    fn lock_object(&self, _id: &Url) -> Option<MutexGuard<()>> {
+        None
+    }
+
     /// Parses the data of a single attribute of an object recursively and replaces embedded objects
-    /// by the id. The embedded objects are them parsed by themselves
+    /// by the id. The embedded objects are them parsed by themselves, using the schema of
+    /// `version` if given. Returns the embedded objects that are new or changed
     fn parse_entry(&self,
                    key: &str,
                    entry: &mut JsonValue,
                    entry_def: &JsonValue,
-                   add_list: ListSender) -> Result<(), Box<Error>> {
+                   version: Option<&str>,
+                   add_list: ListSender) -> Result<Vec<ChangedObject>, Box<Error>> {
+        let mut changed = vec![];
         if entry_def["type"] == "array" {
             for mut i in entry.members_mut() {
                 let key = key.to_string() + "[" + &i.to_string() + "]";
-                self.parse_entry(key.as_str(), &mut i, &entry_def["items"], add_list.clone())?;
+                changed.extend(self.parse_entry(key.as_str(), &mut i, &entry_def["items"], version, add_list.clone())?);
             }
         } else if entry_def["type"] == "object" {
             if entry["type"] == "Feature" {
-                return Ok(()); // GeoJSON is treated is a single value
+                return Ok(changed); // GeoJSON is treated is a single value
             }
             // Extract the embedded object leaving its id
-            self.parse_object(entry, add_list)?;
+            changed = self.parse_object_as(entry, version, add_list)?;
             *entry = JsonValue::String(entry["id"].to_string());
         } else if entry_def["references"] == "externalList" {
+            *entry = self.get_id_rewriter().rewrite(&entry.to_string()).into();
             add_list.send(Message::List(entry.to_string().into_url()?)).unwrap();
+        } else if entry_def["references"].is_string() {
+            // A reference to another object
+            if let Some(reference) = entry.as_str().map(|x| self.get_id_rewriter().rewrite(x)) {
+                *entry = reference.into();
+            }
         }
 
-        Ok(())
+        Ok(changed)
+    }
+
+    /// Returns the OParl version of a System object if there's a schema for it
+    fn check_oparl_version(&self, system: &JsonValue) -> Result<String, Box<Error>> {
+        let version = match system["oparlVersion"].as_str() {
+            Some(oparl_version) => {
+                version_from_url(oparl_version).ok_or("Invalid oparlVersion in the System object")?
+            }
+            None => split_type_url(&system["type"].to_string())?.0,
+        };
+
+        if !self.get_schema().has_key(&version) {
+            return Err(From::from(format!("Unsupported OParl version: {}", version)));
+        }
+
+        Ok(version)
     }
 
     /// Determines the corresponding schema of an object, lets all it's attributes be parsed
     /// recursively and then writes the object to the cache
-    fn parse_object(&self, target: &mut JsonValue, add_list: ListSender) -> Result<(), Box<Error>> {
-        let let_binding = target["type"].to_string();
-        let oparl_type = let_binding.split("/").last().ok_or("Invalid type url")?;
-        let spec_for_object = &self.get_schema()[oparl_type]["properties"];
+    ///
+    /// Returns the object and its embedded objects if they are new or differ from their cached
+    /// version
+    fn parse_object(&self, target: &mut JsonValue, add_list: ListSender) -> Result<Vec<ChangedObject>, Box<Error>> {
+        self.parse_object_as(target, None, add_list)
+    }
 
+    /// Like `parse_object`, but uses the schema of `version`, the OParl version announced by the
+    /// server, instead of the version in the type url of the object
+    fn parse_object_as(&self,
+                       target: &mut JsonValue,
+                       version: Option<&str>,
+                       add_list: ListSender)
+                       -> Result<Vec<ChangedObject>, Box<Error>> {
+        let server_version = version;
+        let (type_version, oparl_type) = split_type_url(&target["type"].to_string())?;
+        let version = server_version.map_or(type_version, String::from);
+        if !self.get_schema().has_key(&version) {
+            return Err(From::from(format!("Unsupported OParl version: {}", version)));
+        }
+        let spec_for_object = &self.get_schema()[&version][&oparl_type]["properties"];
+
+        if let Some(id) = target["id"].as_str().map(|x| self.get_id_rewriter().rewrite(x)) {
+            target["id"] = id.into();
+        }
+
+        // The body the external lists of this object belong to. The `body` of the System object
+        // is the list of all bodies, which doesn't belong to any of them
+        let body = if oparl_type == "Body" {
+            target["id"].as_str().map(String::from)
+        } else if is_external_list(&spec_for_object["body"]) {
+            None
+        } else {
+            target["body"].as_str().map(|x| self.get_id_rewriter().rewrite(x))
+        };
+
+        let mut changed = vec![];
         for (key, mut value) in target.entries_mut() {
-            // Check if the key is defined in the specification
-            if spec_for_object.has_key(key) {
-                self.parse_entry(key, &mut value, &spec_for_object[key], add_list.clone())?;
+            if is_external_list(&spec_for_object[key]) {
+                *value = self.get_id_rewriter().rewrite(&value.to_string()).into();
+                if self.get_object_filter().allows_list(key, body.as_ref().map(String::as_str)) {
+                    add_list.send(Message::List(value.to_string().into_url()?)).unwrap();
+                } else {
+                    println!("List excluded by the filter: {}", value);
+                }
+            } else if spec_for_object.has_key(key) {
+                // The key is defined in the specification
+                changed.extend(self.parse_entry(key,
+                                                &mut value,
+                                                &spec_for_object[key],
+                                                server_version,
+                                                add_list.clone())?);
             }
         }
 
         let id = target["id"].as_str().ok_or("The id has to be a String")?.into_url()?;
+
+        // Compare with the cached version so that references that were removed leave the index
+        let _guard = self.lock_object(&id);
+        let old = self.get(&id).ok();
+        let references = collect_references(target, spec_for_object);
+        let old_references = match old {
+            Some(ref old) => collect_references(old, spec_for_object),
+            None => vec![],
+        };
+        self.update_references(&id, &oparl_type, &old_references, &references)?;
+
+        if old.as_ref() != Some(&*target) {
+            changed.push(ChangedObject {
+                id: id.to_string(),
+                oparl_type: oparl_type.clone(),
+            });
+        }
+
         self.write_to_cache(&id, &target)?;
 
-        Ok(())
+        Ok(changed)
     }
 
     /// Downloads a whole external list and saves the results to the cache
@@ -88,41 +212,59 @@ pub trait Cacher: Storage + Sync {
                                       server: &T,
                                       add_list: ListSender)
                                       -> Result<(Url, Option<String>), Box<Error>> {
+        self.parse_external_list_window(url,
+                                        last_sync,
+                                        &TimeWindow::default(),
+                                        None,
+                                        server,
+                                        add_list,
+                                        &mut ListReport::default())
+    }
+
+    /// Like `parse_external_list`, but only downloads the objects in `window`
+    ///
+    /// With a non-empty window the objects are merged into the existing list cache and
+    /// `last_sync` is returned unchanged, as the list was only partially refreshed. The objects
+    /// are parsed with the schema of `version`, the OParl version of the server, if given
+    ///
+    /// The counters and timings of the download are recorded in `report`, also if it fails
+    fn parse_external_list_window<T: Server>(&self,
+                                             url: Url,
+                                             last_sync: Option<String>,
+                                             window: &TimeWindow,
+                                             version: Option<&str>,
+                                             server: &T,
+                                             add_list: ListSender,
+                                             report: &mut ListReport)
+                                             -> Result<(Url, Option<String>), Box<Error>> {
         // Take the time before the downloading as the data can change while obtaining pages
         let this_sync = Local::now().format("%Y-%m-%dT%H:%M:%S%Z").to_string();
 
         let url_without_filters = url.into_url()?;
         let mut url_with_filters: Url = url_without_filters.clone();
 
-        if let Some(ref last_sync_time) = last_sync {
+        if !window.is_empty() {
+            window.apply_to(&mut url_with_filters);
+        } else if let Some(ref last_sync_time) = last_sync {
             // Add the modified_since filter
             url_with_filters.query_pairs_mut()
                 .append_pair("modified_since", &last_sync_time)
                 .finish();
         }
 
-        let list = ExternalList::new(Url::parse(url_with_filters.as_str())?, server);
-
-        // A Vec is used instead of a Set as we want to preserve the ordering
-        let mut urls: Vec<String> = Vec::new();
-
-        for i in list {
-            let mut i: JsonValue = i?;
-            let result = self.parse_object(&mut i, add_list.clone());
-            if let Err(err) = result {
-                println!("Invalid object: {}", err);
-                i.write_pretty(&mut stdout(), 4).unwrap();
-                println!("Skipping the above object");
-                continue;
-            }
-            let value = i["id"].to_string();
-            if !urls.contains(&value) {
-                urls.push(value);
-            }
-        }
+        let started = Instant::now();
+        let measuring_server = MeasuringServer::new(server);
+        let result = self.parse_list_objects(url_with_filters.clone(),
+                                             version,
+                                             &measuring_server,
+                                             add_list,
+                                             report);
+        report.add_pages_of(measuring_server.into_report());
+        report.finish(started.elapsed());
+        let mut urls = result?;
 
         // Get the the lists cached in the last run
-        let mut urls_as_json = if last_sync.is_some() {
+        let mut urls_as_json = if last_sync.is_some() || !window.is_empty() {
             match self.get(&url_with_filters) {
                 Ok(ok) => ok,
                 Err(_) => {
@@ -150,17 +292,76 @@ pub trait Cacher: Storage + Sync {
 
         self.write_to_cache(&url_with_filters, &urls_new_json)?;
 
-        Ok((url_without_filters, Some(this_sync)))
+        if window.is_empty() {
+            Ok((url_without_filters, Some(this_sync)))
+        } else {
+            Ok((url_without_filters, last_sync))
+        }
+    }
+
+    /// Parses all objects of an external list and returns their ids in the order of the list
+    ///
+    /// Invalid objects are skipped and counted in `report`
+    fn parse_list_objects<T: Server>(&self,
+                                     url: Url,
+                                     version: Option<&str>,
+                                     server: &T,
+                                     add_list: ListSender,
+                                     report: &mut ListReport)
+                                     -> Result<Vec<String>, Box<Error>> {
+        // The canonical form of the list url, which the ids of the objects are compared with
+        let list_url = self.get_id_rewriter().rewrite(url.as_str()).into_url()?;
+        let list = ExternalList::new(url.clone(), server);
+
+        // A Vec is used instead of a Set as we want to preserve the ordering
+        let mut urls: Vec<String> = Vec::new();
+
+        for i in list {
+            let mut i: JsonValue = i?;
+            report.objects += 1;
+            let original_id = i["id"].to_string();
+            match self.parse_object_as(&mut i, version, add_list.clone()) {
+                Ok(changed) => report.changed.extend(changed),
+                Err(err) => {
+                    println!("Invalid object: {}", err);
+                    i.write_pretty(&mut stdout(), 4).unwrap();
+                    println!("Skipping the above object");
+                    report.invalid_objects += 1;
+                    report.add_error(format!("Invalid object {}: {}", i["id"], err));
+                    continue;
+                }
+            }
+            let value = i["id"].to_string();
+            if value != original_id {
+                println!("Rewrote the id {} to {}", original_id, value);
+                report.rewritten_ids += 1;
+            }
+            // Mismatches that no rewrite rule fixed would scatter the cache across host folders
+            if Url::parse(&value).ok().map(|x| x.origin()) != Some(list_url.origin()) {
+                println!("Warn: The id {} isn't on the host of the list {}", value, url);
+                report.id_mismatches += 1;
+                report.add_error(format!("The id {} isn't on the host of the list {}", value, url));
+            }
+            if !urls.contains(&value) {
+                urls.push(value);
+            }
+        }
+
+        Ok(urls)
     }
 
     /// Downloads and caches all external lists while adding those newly found in parallel. This
     /// function blocks until all threads have finished.
     /// The weird command order is due to the Mutex-locking which would otherwise dead-lock
     /// the child threads
+    ///
+    /// The lists are restricted to `window`, which is empty for regular syncs. Returns the new
+    /// cache status and a report for every list
     fn load_all_external_lists<T: Server>(&self,
                                           server: &T,
-                                          known: &CacheStatus)
-                                          -> CacheStatus {
+                                          known: &CacheStatus,
+                                          window: &TimeWindow)
+                                          -> (CacheStatus, Vec<ListReport>) {
         let mut thread_handles = vec![];
 
         // Avoid doing same list more than once
@@ -179,12 +380,37 @@ pub trait Cacher: Storage + Sync {
         // Download the entrypoint which is the System object
         // This will set the first external list, which is the body list
         let system_object = server.get_json(server.get_entrypoint().clone());
-        let result = system_object.and_then(|mut x| self.parse_object(&mut x, add_list.clone()));
+        let mut reports = vec![];
+        let result = system_object.and_then(|mut x| {
+            let version = self.check_oparl_version(&x)?;
+            println!("OParl version: {}", version);
+            self.parse_object_as(&mut x, Some(version.as_str()), add_list.clone())?;
+            let entrypoint = self.get_id_rewriter().rewrite(server.get_entrypoint().as_str());
+            if x["id"] != entrypoint.as_str() {
+                let message = format!("The id of the System object is {}, but the entrypoint is {}",
+                                      x["id"],
+                                      server.get_entrypoint());
+                println!("Warn: {}", message);
+                let mut report = ListReport::new(&server.get_entrypoint());
+                report.id_mismatches += 1;
+                report.add_error(message);
+                reports.push(report);
+            }
+            Ok(version)
+        });
 
-        if let Err(err) = result {
-            println!("Failed to parse the System object: {}", err);
-            println!("Aborting");
-            return vec![];
+        // The schema for all objects of the server is chosen by the version of the System object
+        let version = match result {
+            Ok(version) => version,
+            Err(err) => {
+                println!("Failed to parse the System object: {}", err);
+                println!("Aborting");
+                let mut report = ListReport::new(&server.get_entrypoint());
+                report.failed = true;
+                report.add_error(format!("Failed to parse the System object: {}", err));
+                reports.push(report);
+                return (vec![], reports);
+            }
         };
 
         for i in receive_list.try_iter() {
@@ -195,21 +421,33 @@ pub trait Cacher: Storage + Sync {
 
         if queue.is_empty() {
             println!("Warn: No external lists found");
-            return vec![];
+            return (vec![], reports);
         }
 
+        let max_workers = self.get_max_workers();
+
         crossbeam::scope(|scope| {
             loop {
-                // Searches for new lists or exits when all workers finshed
+                // Searches for new lists or exits when all workers finshed. With all workers busy,
+                // new lists are queued until one is done
+                let at_limit = max_workers.map_or(false, |x| x > 0 && threadcounter >= x);
                 let UrlWithTimestamp {url, last_sync: last_update} = {
-                    if let Some(queued) = queue.pop_front() {
+                    let queued = if at_limit { None } else { queue.pop_front() };
+                    if let Some(queued) = queued {
                         queued
                     } else {
                         match receive_list.recv_timeout(Duration::from_secs(10)) {
-                            Ok(Message::List(url)) => UrlWithTimestamp {url: url, last_sync: None},
+                            Ok(Message::List(url)) => {
+                                let list = UrlWithTimestamp {url: url, last_sync: None};
+                                if at_limit {
+                                    queue.push_back(list);
+                                    continue;
+                                }
+                                list
+                            }
                             Ok(Message::Done) => {
                                 threadcounter -= 1;
-                                if threadcounter == 0 { break } else { continue }
+                                if threadcounter == 0 && queue.is_empty() { break } else { continue }
                             }
                             Err(_) => {
                                 // Granted, this is no the optimal solution. But it's better than
@@ -229,19 +467,32 @@ pub trait Cacher: Storage + Sync {
                 }
 
                 done.push(url.clone());
+                self.report_progress(Progress::ListStarted(&url));
 
                 let add_list = add_list.clone();
                 let url = url.clone();
                 let last_update = last_update.clone();
+                let version = version.clone();
 
                 let closure = move || {
-                    let list_result =
-                        self.parse_external_list(url, last_update, server, add_list.clone());
+                    let mut report = ListReport::new(&url);
+                    let list_result = self.parse_external_list_window(url,
+                                                                      last_update,
+                                                                      window,
+                                                                      Some(version.as_str()),
+                                                                      server,
+                                                                      add_list.clone(),
+                                                                      &mut report);
                     add_list.send(Message::Done).unwrap();
 
                     let sendable_and_typed: Result<_, Box<Error + Send + Sync>>;
                     sendable_and_typed = list_result.map_err(|err| From::from(err.description()));
-                    sendable_and_typed
+                    if let Err(ref err) = sendable_and_typed {
+                        report.failed = true;
+                        report.add_error(err.to_string());
+                    }
+                    self.report_progress(Progress::ListFinished(&report));
+                    (sendable_and_typed, report)
                 };
                 thread_handles.push(scope.spawn(closure));
                 threadcounter += 1;
@@ -251,15 +502,17 @@ pub trait Cacher: Storage + Sync {
         let mut new_cache_status = vec![];
 
         for thread in thread_handles {
-            match thread.join() {
+            let (result, report) = thread.join();
+            match result {
                 Ok(list) => {
                     println!("Success: {}", &list.0);
                     new_cache_status.push(UrlWithTimestamp {url: list.0, last_sync: list.1});
                 }
                 Err(err) => println!("Failed: {}", err),
             }
+            reports.push(report);
         }
 
-        new_cache_status
+        (new_cache_status, reports)
     }
 }
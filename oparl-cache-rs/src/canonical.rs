@@ -0,0 +1,137 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+
+use reqwest::Url;
+
+/// A known way in which server implementations mangle their ids
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Quirk {
+    /// Replaces repeated slashes in the path with a single one, e.g. `/oparl//paper/1`
+    CollapseSlashes,
+    /// Removes java session ids from the path, e.g. `/paper/1;jsessionid=0A1B2C`
+    StripSessionId,
+    /// Removes a query parameter, e.g. `PHPSESSID`
+    RemoveQueryParameter(String),
+}
+
+impl Quirk {
+    /// Parses `collapse-slashes`, `strip-session-id` and `remove-query-parameter=<name>`
+    pub fn parse(quirk: &str) -> Result<Quirk, Box<Error>> {
+        let mut parts = quirk.splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some("collapse-slashes"), None) => Ok(Quirk::CollapseSlashes),
+            (Some("strip-session-id"), None) => Ok(Quirk::StripSessionId),
+            (Some("remove-query-parameter"), Some(name)) if !name.is_empty() => {
+                Ok(Quirk::RemoveQueryParameter(name.to_string()))
+            }
+            _ => Err(From::from(format!("Unknown quirk: {}", quirk))),
+        }
+    }
+
+    fn apply(&self, url: &mut Url) {
+        match *self {
+            Quirk::CollapseSlashes => {
+                let mut path = url.path().to_string();
+                while path.contains("//") {
+                    path = path.replace("//", "/");
+                }
+                url.set_path(&path);
+            }
+            Quirk::StripSessionId => {
+                let path = url.path()
+                    .split('/')
+                    .map(|segment| {
+                        match segment.find(";jsessionid=").or_else(|| segment.find(";JSESSIONID=")) {
+                            Some(position) => &segment[..position],
+                            None => segment,
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join("/");
+                url.set_path(&path);
+            }
+            Quirk::RemoveQueryParameter(ref name) => {
+                let pairs = url.query_pairs()
+                    .filter(|&(ref key, _)| key != name)
+                    .map(|(key, value)| (key.into_owned(), value.into_owned()))
+                    .collect::<Vec<_>>();
+                if pairs.is_empty() {
+                    url.set_query(None);
+                } else {
+                    url.query_pairs_mut().clear().extend_pairs(pairs);
+                }
+            }
+        }
+    }
+}
+
+/// Rules that rewrite the ids and references of the objects before they are cached, so that a
+/// server that mixes hosts or schemes still ends up in one consistent tree
+///
+/// The rules are applied in the order of the fields
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct IdRewriter {
+    /// Maps hosts, optionally with a port, to the host that should be used instead, e.g.
+    /// `www.example.com` => `example.com`
+    pub host_aliases: BTreeMap<String, String>,
+    /// The ids on these hosts are rewritten to https
+    pub https_hosts: Vec<String>,
+    /// Removes trailing slashes from the path
+    pub strip_trailing_slash: bool,
+    /// Vendor specific fixes
+    pub quirks: Vec<Quirk>,
+}
+
+impl IdRewriter {
+    /// Whether there are no rules
+    pub fn is_empty(&self) -> bool {
+        *self == IdRewriter::default()
+    }
+
+    /// Returns the canonical form of `id`. Strings that aren't urls are returned unchanged
+    pub fn rewrite(&self, id: &str) -> String {
+        if self.is_empty() {
+            return id.to_string();
+        }
+
+        let mut url = match Url::parse(id) {
+            Ok(url) => url,
+            Err(_) => return id.to_string(),
+        };
+
+        if let Some(alias) = self.host_aliases.get(&host_with_port(&url)) {
+            let mut parts = alias.rsplitn(2, ':');
+            let (host, port) = match (parts.next(), parts.next()) {
+                (Some(port), Some(host)) if port.parse::<u16>().is_ok() => (host, port.parse().ok()),
+                _ => (alias.as_str(), None),
+            };
+            let _ = url.set_host(Some(host));
+            let _ = url.set_port(port);
+        }
+
+        if url.scheme() == "http" && self.https_hosts.contains(&host_with_port(&url)) {
+            let _ = url.set_scheme("https");
+        }
+
+        if self.strip_trailing_slash && url.path().len() > 1 && url.path().ends_with('/') {
+            let path = url.path().trim_right_matches('/').to_string();
+            url.set_path(&path);
+        }
+
+        for quirk in &self.quirks {
+            quirk.apply(&mut url);
+        }
+
+        url.to_string()
+    }
+}
+
+/// Returns the host of `url` with the port if it's not the default one
+fn host_with_port(url: &Url) -> String {
+    match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or(""), port),
+        None => url.host_str().unwrap_or("").to_string(),
+    }
+}
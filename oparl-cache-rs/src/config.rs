@@ -0,0 +1,434 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use reqwest::{IntoUrl, Url};
+use toml;
+
+use canonical::{IdRewriter, Quirk};
+use daemon::{Job, Schedule};
+use object_filter::ObjectFilter;
+use server::{Auth, CommonServer};
+
+/// The file name of the config inside the config directories
+pub const CONFIG_FILE: &'static str = "oparl-cache/config.toml";
+/// The entrypoint used when none is configured
+pub const DEFAULT_ENTRYPOINT: &'static str = "http://localhost:8080/oparl/v1.0/";
+/// The schema folder used when none is configured
+pub const DEFAULT_SCHEMA_DIR: &'static str = "oparl/schema";
+
+/// The settings of a single server, which override the global ones
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ServerConfig {
+    /// The url of the entrypoint
+    pub entrypoint: Url,
+    /// When the daemon should sync the server, e.g. `30m` or `daily@03:15`
+    #[serde(default)]
+    pub schedule: Option<String>,
+    /// The maximal number of requests per second
+    #[serde(default)]
+    pub rate_limit: Option<f64>,
+    /// The credentials for the server
+    #[serde(default)]
+    pub auth: Option<Auth>,
+}
+
+/// The rules for rewriting ids, written like the command line flags
+///
+/// Tables have to come after the plain values, otherwise the config can't be written as toml
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct IdRewriteConfig {
+    /// The ids on these hosts are rewritten to https
+    pub https_hosts: Vec<String>,
+    /// Whether trailing slashes are removed from the ids
+    pub strip_trailing_slash: Option<bool>,
+    /// Vendor quirks such as `collapse-slashes` or `remove-query-parameter=PHPSESSID`
+    pub quirks: Vec<String>,
+    /// Maps hosts to the host that should be used instead, e.g. `"www.example.com" = "example.com"`
+    pub host_aliases: BTreeMap<String, String>,
+}
+
+impl IdRewriteConfig {
+    /// Whether there are no rules
+    pub fn is_empty(&self) -> bool {
+        *self == IdRewriteConfig::default()
+    }
+
+    /// Adds a host alias given as `<alias>=<host>`
+    pub fn add_host_alias(&mut self, alias: &str) -> Result<(), Box<Error>> {
+        let mut parts = alias.splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some(from), Some(to)) if !from.is_empty() && !to.is_empty() => {
+                self.host_aliases.insert(from.to_string(), to.to_string());
+                Ok(())
+            }
+            _ => Err(From::from(format!("Invalid host alias, expected <alias>=<host>: {}", alias))),
+        }
+    }
+
+    /// Overrides the rules set in `other`
+    pub fn merge(&mut self, other: IdRewriteConfig) {
+        replace_non_empty(&mut self.https_hosts, other.https_hosts);
+        if other.strip_trailing_slash.is_some() {
+            self.strip_trailing_slash = other.strip_trailing_slash;
+        }
+        replace_non_empty(&mut self.quirks, other.quirks);
+        if !other.host_aliases.is_empty() {
+            self.host_aliases = other.host_aliases;
+        }
+    }
+
+    /// Returns the rewriter with the parsed quirks
+    pub fn id_rewriter(&self) -> Result<IdRewriter, Box<Error>> {
+        let mut quirks = vec![];
+        for quirk in &self.quirks {
+            quirks.push(Quirk::parse(quirk)?);
+        }
+        Ok(IdRewriter {
+            host_aliases: self.host_aliases.clone(),
+            https_hosts: self.https_hosts.clone(),
+            strip_trailing_slash: self.strip_trailing_slash.unwrap_or(false),
+            quirks: quirks,
+        })
+    }
+}
+
+/// The settings of the CLI, which can be combined from config files, environment variables and
+/// command line flags
+///
+/// Unset values fall back to the defaults. Tables have to come after the plain values, otherwise
+/// the config can't be written as toml
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct Config {
+    /// The directory where the API responses are saved
+    pub cache_dir: Option<PathBuf>,
+    /// The folder with the OParl schema
+    pub schema_dir: Option<PathBuf>,
+    /// The entrypoint used when a command gets none
+    pub entrypoint: Option<Url>,
+    /// The maximal number of lists loaded at the same time
+    pub concurrency: Option<usize>,
+    /// The maximal number of requests per second for every server
+    pub rate_limit: Option<f64>,
+    /// A json file with the webhooks and commands to run after syncing a server
+    pub hooks: Option<PathBuf>,
+    /// Whether a snapshot of the objects is written after every sync run
+    pub keep_snapshots: Option<bool>,
+    /// The number of snapshots kept per server, `0` keeps all
+    pub max_snapshots: Option<usize>,
+    /// The credentials for every server without its own
+    pub auth: Option<Auth>,
+    /// The lists that are cached
+    #[serde(skip_serializing_if = "ObjectFilter::is_empty")]
+    pub object_filter: ObjectFilter,
+    /// The rules for rewriting the ids of the objects
+    #[serde(skip_serializing_if = "IdRewriteConfig::is_empty")]
+    pub id_rewrite: IdRewriteConfig,
+    /// The servers synced by the daemon
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub servers: Vec<ServerConfig>,
+}
+
+/// Returns the path in an environment variable, treating an empty one as unset like the XDG
+/// base directory specification demands
+fn non_empty_var(name: &str) -> Option<PathBuf> {
+    match env::var_os(name) {
+        Some(ref value) if !value.is_empty() => Some(PathBuf::from(value)),
+        _ => None,
+    }
+}
+
+/// Replaces `target` with `other` unless `other` is empty
+fn replace_non_empty<T>(target: &mut Vec<T>, other: Vec<T>) {
+    if !other.is_empty() {
+        *target = other;
+    }
+}
+
+/// Splits a list in an environment variable at whitespace
+fn split_var(value: &str) -> Vec<String> {
+    value.split_whitespace().map(String::from).collect()
+}
+
+/// Parses a boolean environment variable
+fn bool_var(name: &str, value: &str) -> Result<bool, Box<Error>> {
+    match value {
+        "true" | "1" => Ok(true),
+        "false" | "0" => Ok(false),
+        _ => Err(From::from(format!("{} must be true or false, not {}", name, value))),
+    }
+}
+
+/// Replaces a secret with a placeholder
+fn redact(secret: &str) -> String {
+    if secret.is_empty() { String::new() } else { "********".to_string() }
+}
+
+fn redact_auth(auth: &Auth) -> Auth {
+    match *auth {
+        Auth::Basic { ref username, ref password } => {
+            Auth::Basic {
+                username: username.clone(),
+                password: password.as_ref().map(|x| redact(x)),
+            }
+        }
+        Auth::Bearer { ref token } => Auth::Bearer { token: redact(token) },
+    }
+}
+
+impl Config {
+    /// Reads a toml config file
+    pub fn load(path: &Path) -> Result<Config, Box<Error>> {
+        let mut s = String::new();
+        File::open(path)?.read_to_string(&mut s)?;
+        match toml::from_str(&s) {
+            Ok(config) => Ok(config),
+            Err(err) => Err(From::from(format!("Invalid config file {}: {}", path.display(), err))),
+        }
+    }
+
+    /// Returns the locations of the config files, ordered from the lowest to the highest
+    /// priority: The system wide ones from `XDG_CONFIG_DIRS` and then the one of the user in
+    /// `XDG_CONFIG_HOME`
+    pub fn default_paths() -> Vec<PathBuf> {
+        let mut paths = vec![];
+
+        let dirs = env::var("XDG_CONFIG_DIRS").ok().unwrap_or_default();
+        let dirs = if dirs.is_empty() { "/etc/xdg".to_string() } else { dirs };
+        // The first entry of XDG_CONFIG_DIRS is the most important one
+        for dir in dirs.split(':').filter(|x| !x.is_empty()).collect::<Vec<_>>().into_iter().rev() {
+            paths.push(Path::new(dir).join(CONFIG_FILE));
+        }
+
+        match non_empty_var("XDG_CONFIG_HOME") {
+            Some(home) => paths.push(home.join(CONFIG_FILE)),
+            None => {
+                if let Some(home) = env::home_dir() {
+                    paths.push(home.join(".config").join(CONFIG_FILE));
+                }
+            }
+        }
+
+        paths
+    }
+
+    /// Reads the settings from `OPARL_CACHE_*` environment variables. `vars` are pairs of name
+    /// and value as returned by `std::env::vars()`. Lists such as `OPARL_CACHE_INCLUDE_BODY` are
+    /// separated by whitespace
+    pub fn from_env<I: IntoIterator<Item = (String, String)>>(vars: I) -> Result<Config, Box<Error>> {
+        let mut config = Config::default();
+        let mut username = None;
+        let mut password = None;
+        let mut token = None;
+
+        for (name, value) in vars {
+            match name.as_str() {
+                "OPARL_CACHE_DIR" => config.cache_dir = Some(PathBuf::from(value)),
+                "OPARL_CACHE_SCHEMA_DIR" => config.schema_dir = Some(PathBuf::from(value)),
+                "OPARL_CACHE_ENTRYPOINT" => config.entrypoint = Some(value.as_str().into_url()?),
+                "OPARL_CACHE_CONCURRENCY" => {
+                    config.concurrency = Some(value.parse().map_err(|_| {
+                        format!("OPARL_CACHE_CONCURRENCY must be a number, not {}", value)
+                    })?)
+                }
+                "OPARL_CACHE_RATE_LIMIT" => {
+                    config.rate_limit = Some(value.parse().map_err(|_| {
+                        format!("OPARL_CACHE_RATE_LIMIT must be a number, not {}", value)
+                    })?)
+                }
+                "OPARL_CACHE_HOOKS" => config.hooks = Some(PathBuf::from(value)),
+                "OPARL_CACHE_KEEP_SNAPSHOTS" => {
+                    config.keep_snapshots = Some(bool_var("OPARL_CACHE_KEEP_SNAPSHOTS", &value)?)
+                }
+                "OPARL_CACHE_MAX_SNAPSHOTS" => {
+                    config.max_snapshots = Some(value.parse().map_err(|_| {
+                        format!("OPARL_CACHE_MAX_SNAPSHOTS must be a number, not {}", value)
+                    })?)
+                }
+                "OPARL_CACHE_INCLUDE_BODY" => config.object_filter.include_bodies = split_var(&value),
+                "OPARL_CACHE_EXCLUDE_BODY" => config.object_filter.exclude_bodies = split_var(&value),
+                "OPARL_CACHE_INCLUDE_TYPE" => config.object_filter.include_types = split_var(&value),
+                "OPARL_CACHE_EXCLUDE_TYPE" => config.object_filter.exclude_types = split_var(&value),
+                "OPARL_CACHE_INCLUDE_LIST" => config.object_filter.include_lists = split_var(&value),
+                "OPARL_CACHE_EXCLUDE_LIST" => config.object_filter.exclude_lists = split_var(&value),
+                "OPARL_CACHE_HOST_ALIAS" => {
+                    for alias in split_var(&value) {
+                        config.id_rewrite.add_host_alias(&alias)?;
+                    }
+                }
+                "OPARL_CACHE_HTTPS_HOST" => config.id_rewrite.https_hosts = split_var(&value),
+                "OPARL_CACHE_STRIP_TRAILING_SLASH" => {
+                    let strip = bool_var("OPARL_CACHE_STRIP_TRAILING_SLASH", &value)?;
+                    config.id_rewrite.strip_trailing_slash = Some(strip);
+                }
+                "OPARL_CACHE_QUIRK" => {
+                    for quirk in split_var(&value) {
+                        Quirk::parse(&quirk)?;
+                        config.id_rewrite.quirks.push(quirk);
+                    }
+                }
+                "OPARL_CACHE_TOKEN" => token = Some(value),
+                "OPARL_CACHE_USERNAME" => username = Some(value),
+                "OPARL_CACHE_PASSWORD" => password = Some(value),
+                _ => {}
+            }
+        }
+
+        if password.is_some() && username.is_none() {
+            return Err(From::from("OPARL_CACHE_PASSWORD is set without OPARL_CACHE_USERNAME"));
+        }
+
+        config.auth = match (token, username) {
+            (Some(token), _) => Some(Auth::Bearer { token: token }),
+            (None, Some(username)) => {
+                Some(Auth::Basic {
+                    username: username,
+                    password: password,
+                })
+            }
+            (None, None) => None,
+        };
+
+        Ok(config)
+    }
+
+    /// Overrides the settings with those set in `other`. The servers are replaced as a whole, the
+    /// lists of the object filter and the id rewrite rules one by one
+    pub fn merge(&mut self, other: Config) {
+        if other.cache_dir.is_some() {
+            self.cache_dir = other.cache_dir;
+        }
+        if other.schema_dir.is_some() {
+            self.schema_dir = other.schema_dir;
+        }
+        if other.entrypoint.is_some() {
+            self.entrypoint = other.entrypoint;
+        }
+        if other.concurrency.is_some() {
+            self.concurrency = other.concurrency;
+        }
+        if other.rate_limit.is_some() {
+            self.rate_limit = other.rate_limit;
+        }
+        if other.hooks.is_some() {
+            self.hooks = other.hooks;
+        }
+        if other.keep_snapshots.is_some() {
+            self.keep_snapshots = other.keep_snapshots;
+        }
+        if other.max_snapshots.is_some() {
+            self.max_snapshots = other.max_snapshots;
+        }
+        if other.auth.is_some() {
+            self.auth = other.auth;
+        }
+        replace_non_empty(&mut self.object_filter.include_bodies, other.object_filter.include_bodies);
+        replace_non_empty(&mut self.object_filter.exclude_bodies, other.object_filter.exclude_bodies);
+        replace_non_empty(&mut self.object_filter.include_types, other.object_filter.include_types);
+        replace_non_empty(&mut self.object_filter.exclude_types, other.object_filter.exclude_types);
+        replace_non_empty(&mut self.object_filter.include_lists, other.object_filter.include_lists);
+        replace_non_empty(&mut self.object_filter.exclude_lists, other.object_filter.exclude_lists);
+        self.id_rewrite.merge(other.id_rewrite);
+        if !other.servers.is_empty() {
+            self.servers = other.servers;
+        }
+    }
+
+    /// Returns the configured cache directory or `$XDG_CACHE_HOME/oparl-cache`
+    pub fn cache_dir(&self) -> PathBuf {
+        if let Some(ref cache_dir) = self.cache_dir {
+            return cache_dir.clone();
+        }
+        match non_empty_var("XDG_CACHE_HOME") {
+            Some(cache_home) => cache_home.join("oparl-cache"),
+            None => {
+                env::home_dir()
+                    .map(|x| x.join(".cache"))
+                    .unwrap_or_else(|| PathBuf::from("."))
+                    .join("oparl-cache")
+            }
+        }
+    }
+
+    /// Returns the configured schema folder or `oparl/schema`
+    pub fn schema_dir(&self) -> PathBuf {
+        self.schema_dir.clone().unwrap_or_else(|| PathBuf::from(DEFAULT_SCHEMA_DIR))
+    }
+
+    /// Returns the configured entrypoint or `DEFAULT_ENTRYPOINT`
+    pub fn entrypoint(&self) -> Url {
+        self.entrypoint.clone().unwrap_or_else(|| DEFAULT_ENTRYPOINT.into_url().unwrap())
+    }
+
+    fn server_config(&self, entrypoint: &Url) -> Option<&ServerConfig> {
+        self.servers.iter().find(|x| &x.entrypoint == entrypoint)
+    }
+
+    /// Returns the credentials of the server or the global ones
+    pub fn auth(&self, entrypoint: &Url) -> Option<Auth> {
+        self.server_config(entrypoint)
+            .and_then(|x| x.auth.clone())
+            .or_else(|| self.auth.clone())
+    }
+
+    /// Returns the rate limit of the server or the global one
+    pub fn rate_limit(&self, entrypoint: &Url) -> Option<f64> {
+        self.server_config(entrypoint)
+            .and_then(|x| x.rate_limit)
+            .or(self.rate_limit)
+    }
+
+    /// Returns the server with its credentials and rate limit
+    pub fn common_server(&self, entrypoint: &Url) -> CommonServer {
+        let mut server = CommonServer::new(entrypoint.clone());
+        if let Some(auth) = self.auth(entrypoint) {
+            server = server.with_auth(auth);
+        }
+        if let Some(rate_limit) = self.rate_limit(entrypoint) {
+            server = server.with_rate_limit(rate_limit);
+        }
+        server
+    }
+
+    /// Returns the daemon job for the server with its credentials and rate limit
+    pub fn job(&self, entrypoint: Url, schedule: Schedule) -> Job {
+        let mut job = Job::new(entrypoint, schedule);
+        job.auth = self.auth(&job.entrypoint);
+        job.rate_limit = self.rate_limit(&job.entrypoint);
+        job
+    }
+
+    /// Returns the daemon jobs for the configured servers. Servers without a schedule get
+    /// `default_schedule`
+    pub fn jobs(&self, default_schedule: &Schedule) -> Result<Vec<Job>, Box<Error>> {
+        let mut jobs = vec![];
+        for server in &self.servers {
+            let schedule = match server.schedule {
+                Some(ref schedule) => Schedule::parse(schedule)?,
+                None => default_schedule.clone(),
+            };
+            jobs.push(self.job(server.entrypoint.clone(), schedule));
+        }
+        Ok(jobs)
+    }
+
+    /// Returns a copy with the passwords and tokens replaced, so it can be printed
+    pub fn redacted(&self) -> Config {
+        let mut config = self.clone();
+        config.auth = config.auth.as_ref().map(redact_auth);
+        for server in &mut config.servers {
+            server.auth = server.auth.as_ref().map(redact_auth);
+        }
+        config
+    }
+
+    /// Serializes the config as toml
+    pub fn to_toml(&self) -> Result<String, Box<Error>> {
+        Ok(toml::to_string(self)?)
+    }
+}
@@ -0,0 +1,430 @@
+use std::collections::{HashSet, VecDeque};
+
+use chrono::{DateTime, FixedOffset};
+use json::JsonValue;
+use reqwest::{IntoUrl, Url};
+
+use schema::{DEFAULT_VERSION, split_type_url, version_from_url, is_external_list};
+use server::Server;
+use sync_report::MAX_ERROR_SAMPLES;
+
+/// The prefix of all valid type urls
+const TYPE_URL_PREFIX: &'static str = "https://schema.oparl.org/";
+
+/// Limits how much of a server is crawled by a check
+#[derive(Clone, Debug)]
+pub struct CheckLimits {
+    /// The number of external lists that are crawled
+    pub max_lists: usize,
+    /// The number of pages that are crawled per list
+    pub max_pages: usize,
+    /// The number of objects per list that are also fetched by their id
+    pub max_objects: usize,
+}
+
+impl Default for CheckLimits {
+    fn default() -> CheckLimits {
+        CheckLimits {
+            max_lists: 20,
+            max_pages: 10,
+            max_objects: 5,
+        }
+    }
+}
+
+/// The outcome of a single aspect of the protocol
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct CheckResult {
+    /// What was checked
+    pub description: String,
+    /// The number of passed assertions
+    pub passed: usize,
+    /// The number of failed assertions
+    pub failed: usize,
+    /// The first `MAX_ERROR_SAMPLES` failures
+    pub failures: Vec<String>,
+}
+
+impl CheckResult {
+    fn new(description: &str) -> CheckResult {
+        CheckResult { description: description.to_string(), ..CheckResult::default() }
+    }
+
+    fn assert(&mut self, condition: bool, failure: String) {
+        if condition {
+            self.passed += 1;
+        } else {
+            self.failed += 1;
+            if self.failures.len() < MAX_ERROR_SAMPLES {
+                self.failures.push(failure);
+            }
+        }
+    }
+
+    /// The percentage of passed assertions, or `None` if nothing could be checked
+    pub fn score(&self) -> Option<f64> {
+        if self.passed + self.failed == 0 {
+            None
+        } else {
+            Some(100.0 * self.passed as f64 / (self.passed + self.failed) as f64)
+        }
+    }
+}
+
+/// The result of checking a server for conformance with the OParl specification
+#[derive(Serialize, Clone, Debug)]
+pub struct ConformanceReport {
+    /// The entrypoint of the server
+    pub entrypoint: Url,
+    /// Objects fetched by their url have that url as id
+    pub id_matches_url: CheckResult,
+    /// The type urls are valid and known to the schema
+    pub type_urls: CheckResult,
+    /// All pages linked by `links.next` can be fetched and contain data
+    pub pagination: CheckResult,
+    /// Lists return the same objects in the same order when fetched twice, without duplicates
+    pub stable_ordering: CheckResult,
+    /// `modified_since` returns exactly the objects modified at or after the given time
+    pub modified_since: CheckResult,
+    /// Embedded objects are equal to the objects fetched by their id
+    pub embedded_objects: CheckResult,
+}
+
+impl ConformanceReport {
+    fn new(entrypoint: Url) -> ConformanceReport {
+        ConformanceReport {
+            entrypoint: entrypoint,
+            id_matches_url: CheckResult::new("Objects fetched by their url have that url as id"),
+            type_urls: CheckResult::new("Type urls are valid and known to the schema"),
+            pagination: CheckResult::new("All pages linked by links.next can be fetched"),
+            stable_ordering: CheckResult::new("Lists are stable across requests and without duplicates"),
+            modified_since: CheckResult::new("modified_since returns exactly the modified objects"),
+            embedded_objects: CheckResult::new("Embedded objects equal the objects fetched by their id"),
+        }
+    }
+
+    /// Returns all checks with their names
+    pub fn checks(&self) -> Vec<(&'static str, &CheckResult)> {
+        vec![("id_matches_url", &self.id_matches_url),
+             ("type_urls", &self.type_urls),
+             ("pagination", &self.pagination),
+             ("stable_ordering", &self.stable_ordering),
+             ("modified_since", &self.modified_since),
+             ("embedded_objects", &self.embedded_objects)]
+    }
+
+    /// The average score of all checks that could be performed, from 0 to 100
+    pub fn score(&self) -> f64 {
+        let scores = self.checks().iter().filter_map(|&(_, x)| x.score()).collect::<Vec<_>>();
+        if scores.is_empty() {
+            0.0
+        } else {
+            scores.iter().sum::<f64>() / scores.len() as f64
+        }
+    }
+}
+
+/// Crawls a server within `limits` and checks it for conformance with the OParl specification
+///
+/// `schema` is the schema as loaded by the storage, i.e. `{<version>: {<type>: <schema>}}`
+pub fn check_conformance<T: Server>(server: &T,
+                                    schema: &JsonValue,
+                                    limits: &CheckLimits)
+                                    -> ConformanceReport {
+    let mut checker = Checker {
+        server: server,
+        schema: schema,
+        limits: limits,
+        report: ConformanceReport::new(server.get_entrypoint()),
+    };
+    checker.run();
+    checker.report
+}
+
+struct Checker<'a, T: 'a + Server> {
+    server: &'a T,
+    schema: &'a JsonValue,
+    limits: &'a CheckLimits,
+    report: ConformanceReport,
+}
+
+impl<'a, T: 'a + Server> Checker<'a, T> {
+    fn run(&mut self) {
+        let entrypoint = self.server.get_entrypoint();
+        let system = match self.server.get_json(entrypoint.clone()) {
+            Ok(system) => system,
+            Err(err) => {
+                let failure = format!("Failed to fetch the System object: {}", err);
+                self.report.id_matches_url.assert(false, failure);
+                return;
+            }
+        };
+
+        let version = system["oparlVersion"]
+            .as_str()
+            .and_then(version_from_url)
+            .unwrap_or(DEFAULT_VERSION.to_string());
+        let has_version = self.schema.has_key(&version);
+        self.report.type_urls.assert(has_version, format!("Unsupported OParl version: {}", version));
+        if !has_version {
+            return;
+        }
+
+        self.check_id(&entrypoint, &system);
+        let mut budget = self.limits.max_objects;
+        self.check_object(&system, &mut budget);
+
+        let mut seen = HashSet::new();
+        let mut lists = VecDeque::new();
+        self.add_lists(&system, &mut seen, &mut lists);
+
+        let mut checked = 0;
+        while let Some(list) = lists.pop_front() {
+            if checked >= self.limits.max_lists {
+                break;
+            }
+            checked += 1;
+
+            for object in self.check_list(&list) {
+                self.add_lists(&object, &mut seen, &mut lists);
+            }
+        }
+    }
+
+    /// Queues the external lists referenced by `object`
+    fn add_lists(&self, object: &JsonValue, seen: &mut HashSet<String>, lists: &mut VecDeque<Url>) {
+        let (version, oparl_type) = match split_type_url(&object["type"].to_string()) {
+            Ok(ok) => ok,
+            Err(_) => return,
+        };
+        let properties = &self.schema[&version][&oparl_type]["properties"];
+
+        for (key, value) in object.entries() {
+            if !is_external_list(&properties[key]) {
+                continue;
+            }
+            if let Some(url) = value.as_str().and_then(|x| x.into_url().ok()) {
+                if seen.insert(url.to_string()) {
+                    lists.push_back(url);
+                }
+            }
+        }
+    }
+
+    fn check_id(&mut self, url: &Url, object: &JsonValue) {
+        self.report.id_matches_url.assert(object["id"].as_str() == Some(url.as_str()),
+                                          format!("{} returned the id {}", url, object["id"]));
+    }
+
+    /// Checks the type url and the embedded objects of an object. `budget` is the number of
+    /// embedded objects that may still be fetched by their id
+    fn check_object(&mut self, object: &JsonValue, budget: &mut usize) {
+        let schema = self.schema;
+        let type_url = object["type"].to_string();
+        let (version, oparl_type) = match split_type_url(&type_url) {
+            Ok(ok) => ok,
+            Err(err) => {
+                self.report.type_urls.assert(false, format!("{} has an invalid type: {}", object["id"], err));
+                return;
+            }
+        };
+
+        let known = type_url.starts_with(TYPE_URL_PREFIX) && schema[&version].has_key(&oparl_type);
+        self.report.type_urls.assert(known, format!("{} has the unknown type {}", object["id"], type_url));
+        if !known {
+            return;
+        }
+
+        let properties = &schema[&version][&oparl_type]["properties"];
+        for (key, value) in object.entries() {
+            let embedded = match (properties[key]["type"].as_str(), value.is_array()) {
+                (Some("object"), false) => vec![value],
+                (Some("array"), true) if properties[key]["items"]["type"] == "object" => {
+                    value.members().collect()
+                }
+                _ => continue,
+            };
+
+            for embedded_object in embedded {
+                // GeoJSON is a single value and not an OParl object
+                if embedded_object["type"] == "Feature" || !embedded_object.has_key("id") {
+                    continue;
+                }
+                self.check_object(embedded_object, budget);
+                if *budget > 0 {
+                    *budget -= 1;
+                    self.check_embedded(embedded_object);
+                }
+            }
+        }
+    }
+
+    /// Compares an embedded object with the object fetched by its id. Embedded objects may omit
+    /// the reference to their parent, so only the properties of the embedded object are compared
+    fn check_embedded(&mut self, embedded: &JsonValue) {
+        let url = match embedded["id"].as_str().and_then(|x| x.into_url().ok()) {
+            Some(url) => url,
+            None => {
+                let failure = format!("Invalid id of an embedded object: {}", embedded["id"]);
+                self.report.embedded_objects.assert(false, failure);
+                return;
+            }
+        };
+
+        let standalone = match self.server.get_json(url.clone()) {
+            Ok(standalone) => standalone,
+            Err(err) => {
+                self.report.embedded_objects.assert(false, format!("Failed to fetch {}: {}", url, err));
+                return;
+            }
+        };
+
+        self.check_id(&url, &standalone);
+        let differing = embedded.entries()
+            .filter(|&(key, value)| standalone[key] != *value)
+            .map(|(key, _)| key.to_string())
+            .collect::<Vec<_>>();
+        self.report.embedded_objects.assert(differing.is_empty(),
+                                            format!("The embedded {} differs in {:?}", url, differing));
+    }
+
+    /// Crawls a list, checks its pagination, ordering and filtering and returns the objects
+    fn check_list(&mut self, url: &Url) -> Vec<JsonValue> {
+        let (objects, complete) = self.crawl(url, true);
+
+        let mut ids = HashSet::new();
+        for object in &objects {
+            let id = object["id"].to_string();
+            self.report.stable_ordering.assert(ids.insert(id.clone()),
+                                               format!("{} is contained twice in {}", id, url));
+        }
+
+        let mut budget = self.limits.max_objects;
+        for object in &objects {
+            self.check_object(object, &mut budget);
+        }
+
+        // Fetch some objects by their id
+        for object in objects.iter().take(self.limits.max_objects) {
+            let id = match object["id"].as_str().and_then(|x| x.into_url().ok()) {
+                Some(id) => id,
+                None => {
+                    let failure = format!("Invalid id {} in {}", object["id"], url);
+                    self.report.id_matches_url.assert(false, failure);
+                    continue;
+                }
+            };
+
+            match self.server.get_json(id.clone()) {
+                Ok(standalone) => self.check_id(&id, &standalone),
+                Err(err) => {
+                    let failure = format!("Failed to fetch {}: {}", id, err);
+                    self.report.id_matches_url.assert(false, failure);
+                }
+            }
+        }
+
+        let (again, _) = self.crawl(url, false);
+        let first_ids = objects.iter().map(|x| x["id"].to_string()).collect::<Vec<_>>();
+        let again_ids = again.iter().map(|x| x["id"].to_string()).collect::<Vec<_>>();
+        self.report.stable_ordering.assert(first_ids == again_ids,
+                                           format!("{} returned a different order when fetched again", url));
+
+        self.check_modified_since(url, &objects, complete);
+
+        objects
+    }
+
+    /// Checks `modified_since` with the median modification time of the list
+    fn check_modified_since(&mut self, url: &Url, objects: &[JsonValue], complete: bool) {
+        let mut modified = objects.iter()
+            .filter_map(|x| {
+                x["modified"].as_str().and_then(parse_time).map(|time| (x["id"].to_string(), time))
+            })
+            .collect::<Vec<_>>();
+        if modified.is_empty() {
+            return;
+        }
+        modified.sort_by_key(|&(_, time)| time);
+        let threshold = modified[modified.len() / 2].1;
+
+        let mut filtered_url = url.clone();
+        filtered_url.query_pairs_mut().append_pair("modified_since", &threshold.to_rfc3339());
+        let (filtered, filtered_complete) = self.crawl(&filtered_url, false);
+
+        let mut filtered_ids = HashSet::new();
+        for object in &filtered {
+            let time = object["modified"].as_str().and_then(parse_time);
+            self.report.modified_since.assert(time.map_or(false, |x| x >= threshold),
+                                              format!("{} was returned by {} but modified at {}",
+                                                      object["id"],
+                                                      filtered_url,
+                                                      object["modified"]));
+            filtered_ids.insert(object["id"].to_string());
+        }
+
+        // Missing objects can only be detected if both lists were crawled completely
+        if complete && filtered_complete {
+            for &(ref id, time) in &modified {
+                if time >= threshold {
+                    self.report.modified_since.assert(filtered_ids.contains(id),
+                                                      format!("{} is missing in {}", id, filtered_url));
+                }
+            }
+        }
+    }
+
+    /// Fetches up to `max_pages` pages of a list. Returns the objects and whether the last page
+    /// was reached. The pagination is only checked if `check` is set
+    fn crawl(&mut self, url: &Url, check: bool) -> (Vec<JsonValue>, bool) {
+        let mut objects = vec![];
+        let mut seen_pages = HashSet::new();
+        let mut page_url = Some(url.clone());
+        let mut pages = 0;
+
+        while let Some(current) = page_url.take() {
+            if pages >= self.limits.max_pages {
+                return (objects, false);
+            }
+            pages += 1;
+
+            if !seen_pages.insert(current.to_string()) {
+                if check {
+                    let failure = format!("links.next of {} points to a previous page", url);
+                    self.report.pagination.assert(false, failure);
+                }
+                return (objects, false);
+            }
+
+            let mut page = match self.server.get_json(current.clone()) {
+                Ok(page) => page,
+                Err(err) => {
+                    if check {
+                        self.report.pagination.assert(false, format!("Failed to fetch {}: {}", current, err));
+                    }
+                    return (objects, false);
+                }
+            };
+
+            let next = &page["links"]["next"];
+            let valid = page["data"].is_array() &&
+                        (next.is_null() || next.as_str().map_or(false, |x| x.into_url().is_ok()));
+            if check {
+                let failure = format!("{} has no data array or an invalid links.next", current);
+                self.report.pagination.assert(valid, failure);
+            }
+            if !valid {
+                return (objects, false);
+            }
+
+            page_url = page["links"]["next"].as_str().and_then(|x| x.into_url().ok());
+            objects.extend(page["data"].members_mut().map(|x| x.take()));
+        }
+
+        (objects, true)
+    }
+}
+
+fn parse_time(time: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(time).ok()
+}
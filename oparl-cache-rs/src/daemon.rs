@@ -0,0 +1,246 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local, TimeZone, Duration as ChronoDuration};
+use reqwest::Url;
+
+use cacher::Cacher;
+use file_storage::FileStorage;
+use metrics::Metrics;
+use server::{Auth, CommonServer};
+use storage::Storage;
+
+/// When a server should be synced
+#[derive(Clone, Debug, PartialEq)]
+pub enum Schedule {
+    /// A fixed interval between the starts of two runs
+    Every(Duration),
+    /// Once a day at the given local time (hour, minute)
+    Daily(u32, u32),
+}
+
+impl Schedule {
+    /// Parses an interval such as `90s`, `30m`, `2h` and `1d` or a daily time as `daily@03:15`
+    pub fn parse(schedule: &str) -> Result<Schedule, Box<Error>> {
+        if schedule.starts_with("daily@") {
+            let time = &schedule["daily@".len()..];
+            let mut parts = time.splitn(2, ':');
+            let hour: u32 = parts.next().ok_or("Missing hour")?.parse()?;
+            let minute: u32 = parts.next().ok_or("Missing minute, expected HH:MM")?.parse()?;
+            if hour > 23 || minute > 59 {
+                return Err(From::from(format!("Invalid time of day: {}", time)));
+            }
+            return Ok(Schedule::Daily(hour, minute));
+        }
+
+        if schedule.len() < 2 {
+            return Err(From::from(format!("Invalid schedule: {}", schedule)));
+        }
+        let (number, unit) = schedule.split_at(schedule.len() - 1);
+        let number: u64 = number.parse()?;
+        let seconds = match unit {
+            "s" => number,
+            "m" => number * 60,
+            "h" => number * 60 * 60,
+            "d" => number * 60 * 60 * 24,
+            _ => return Err(From::from(format!("Unknown unit in schedule: {}", schedule))),
+        };
+
+        if seconds == 0 {
+            return Err(From::from("The interval must not be zero"));
+        }
+
+        Ok(Schedule::Every(Duration::from_secs(seconds)))
+    }
+
+    /// Returns the time of the next run after a run was started at `last_start`
+    ///
+    /// A daily time that doesn't exist on a day because of the switch to daylight saving time is
+    /// skipped on that day, a time that exists twice is taken the first time
+    pub fn next_run<Tz: TimeZone>(&self, last_start: DateTime<Tz>) -> DateTime<Tz> {
+        match *self {
+            Schedule::Every(interval) => {
+                last_start + ChronoDuration::from_std(interval).unwrap_or(ChronoDuration::days(1))
+            }
+            Schedule::Daily(hour, minute) => {
+                let timezone = last_start.timezone();
+                let mut date = last_start.naive_local().date();
+                // A few days suffice for any daylight saving time rules
+                for _ in 0..4 {
+                    let run = date.and_hms_opt(hour, minute, 0)
+                        .and_then(|x| timezone.from_local_datetime(&x).earliest());
+                    if let Some(run) = run {
+                        if run > last_start {
+                            return run;
+                        }
+                    }
+                    date = date.succ();
+                }
+                last_start + ChronoDuration::days(1)
+            }
+        }
+    }
+
+    /// Returns the time of the first run when the daemon starts at `now`
+    pub fn first_run<Tz: TimeZone>(&self, now: DateTime<Tz>) -> DateTime<Tz> {
+        match *self {
+            // Sync right away so that a restart doesn't delay the data by a whole interval
+            Schedule::Every(_) => now,
+            Schedule::Daily(..) => self.next_run(now),
+        }
+    }
+}
+
+/// A server with its schedule
+#[derive(Clone, Debug)]
+pub struct Job {
+    /// The entrypoint of the server
+    pub entrypoint: Url,
+    /// When the server should be synced
+    pub schedule: Schedule,
+    /// The credentials for the server
+    pub auth: Option<Auth>,
+    /// The maximal number of requests per second
+    pub rate_limit: Option<f64>,
+}
+
+impl Job {
+    /// Creates a job for a server without credentials and rate limit
+    pub fn new(entrypoint: Url, schedule: Schedule) -> Job {
+        Job {
+            entrypoint: entrypoint,
+            schedule: schedule,
+            auth: None,
+            rate_limit: None,
+        }
+    }
+
+    /// Returns the server with the credentials and the rate limit of the job
+    pub fn server(&self) -> CommonServer {
+        let mut server = CommonServer::new(self.entrypoint.clone());
+        if let Some(ref auth) = self.auth {
+            server = server.with_auth(auth.clone());
+        }
+        if let Some(rate_limit) = self.rate_limit {
+            server = server.with_rate_limit(rate_limit);
+        }
+        server
+    }
+}
+
+/// Keeps a set of servers up to date by syncing them according to their schedule
+///
+/// The runs are performed one after another, so a slow server delays the others
+pub struct Daemon<'a> {
+    storage: FileStorage<'a>,
+    jobs: Vec<(Job, DateTime<Local>)>,
+    metrics: Arc<Mutex<Metrics>>,
+}
+
+impl<'a> Daemon<'a> {
+    /// Creates a new `Daemon`
+    pub fn new(storage: FileStorage<'a>, jobs: Vec<Job>) -> Daemon<'a> {
+        let entrypoints = jobs.iter().map(|x| x.entrypoint.clone()).collect::<Vec<_>>();
+        let now = Local::now();
+        Daemon {
+            storage: storage,
+            jobs: jobs.into_iter()
+                .map(|x| {
+                    let first_run = x.schedule.first_run(now);
+                    (x, first_run)
+                })
+                .collect(),
+            metrics: Arc::new(Mutex::new(Metrics::new(&entrypoints))),
+        }
+    }
+
+    /// Returns the metrics which are updated after every run
+    pub fn get_metrics(&self) -> Arc<Mutex<Metrics>> {
+        self.metrics.clone()
+    }
+
+    /// Runs the jobs forever
+    pub fn run(&mut self) {
+        if self.jobs.is_empty() {
+            println!("Warn: No servers to sync");
+            return;
+        }
+
+        loop {
+            self.run_next();
+        }
+    }
+
+    /// Waits for the job that is due next and runs it
+    pub fn run_next(&mut self) {
+        let index = {
+            let mut index = 0;
+            for (i, &(_, next_run)) in self.jobs.iter().enumerate() {
+                if next_run < self.jobs[index].1 {
+                    index = i;
+                }
+            }
+            index
+        };
+
+        let next_run = self.jobs[index].1;
+        if let Ok(delay) = next_run.signed_duration_since(Local::now()).to_std() {
+            println!("Next sync of {} at {}", self.jobs[index].0.entrypoint, next_run);
+            sleep(delay);
+        }
+
+        let started = Local::now();
+        let job = self.jobs[index].0.clone();
+        self.sync(&job);
+        self.jobs[index].1 = self.jobs[index].0.schedule.next_run(started);
+    }
+
+    /// Syncs a server and records the metrics of the run
+    ///
+    /// A run only counts as successful if its report has no failed lists, as a sync that e.g.
+    /// couldn't fetch the System object still finishes without an error. The daemon holds a lock
+    /// during the run, so the garbage collection can run between the syncs but not during them
+    fn sync(&self, job: &Job) {
+        let entrypoint = &job.entrypoint;
+        println!("Syncing {}", entrypoint);
+        let started = Instant::now();
+        let result = self.storage.create_lock("daemon").and_then(|_lock| self.storage.cache(job.server()));
+        let duration = started.elapsed();
+
+        // After an error, the latest report is the one of an earlier run
+        let report = match result {
+            Ok(()) => self.storage.get_sync_reports(entrypoint).ok().and_then(|mut x| x.pop()),
+            Err(ref err) => {
+                println!("✗ Syncing {} failed: {}", entrypoint, err);
+                None
+            }
+        };
+        let success = report.as_ref().map_or(false, |x| x.failed_lists() == 0);
+        if result.is_ok() && !success {
+            println!("✗ Syncing {} failed, see the sync report", entrypoint);
+        }
+
+        let cached = match count_cached_objects(&self.storage, entrypoint) {
+            Ok(cached) => Some(cached),
+            Err(err) => {
+                println!("✗ Counting the objects of {} failed: {}", entrypoint, err);
+                None
+            }
+        };
+        self.metrics.lock().unwrap().record(entrypoint, success, report.as_ref(), cached, duration);
+    }
+}
+
+/// Counts the distinct objects in the cached external lists of a server. Only the list files
+/// are read, so this is cheap compared to walking all objects
+fn count_cached_objects<S: Storage>(storage: &S, entrypoint: &Url) -> Result<usize, Box<Error>> {
+    let mut ids = HashSet::new();
+    for list in storage.iter_lists(entrypoint) {
+        let (_, members) = list?;
+        ids.extend(members.members().filter_map(|x| x.as_str()).map(String::from));
+    }
+    Ok(ids.len())
+}
@@ -0,0 +1,321 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+
+use json::JsonValue;
+use reqwest::Url;
+
+use file_storage::FileStorage;
+use refs::iter_reachable;
+use schema::split_type_url;
+use snapshot::read_snapshot;
+use storage::Storage;
+
+/// References are replaced by the key of the referenced object, which may again contain
+/// references. Deeper chains and cycles fall back to the id
+const MAX_KEY_DEPTH: usize = 4;
+
+/// Decides which objects of two servers are the same and which properties are compared
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiffOptions {
+    /// The properties identifying an object per type, e.g. `reference` for `Paper`. Objects of
+    /// other types or with missing properties are matched by their id relative to the entrypoint
+    pub keys: BTreeMap<String, Vec<String>>,
+    /// The properties that aren't compared
+    pub ignore: Vec<String>,
+}
+
+impl Default for DiffOptions {
+    fn default() -> DiffOptions {
+        let keys = [("Body", "name"),
+                    ("LegislativeTerm", "name+startDate"),
+                    ("Organization", "name"),
+                    ("Person", "name"),
+                    ("Membership", "person+organization+startDate"),
+                    ("Meeting", "name+start"),
+                    ("AgendaItem", "meeting+number"),
+                    ("Paper", "reference"),
+                    ("Consultation", "paper+meeting"),
+                    ("File", "fileName")];
+        DiffOptions {
+            keys: keys.iter()
+                .map(|&(oparl_type, properties)| {
+                    (oparl_type.to_string(), properties.split('+').map(String::from).collect())
+                })
+                .collect(),
+            ignore: vec!["id".to_string(), "created".to_string(), "modified".to_string(), "web".to_string()],
+        }
+    }
+}
+
+impl DiffOptions {
+    /// Sets the key of a type given as `<type>=<property>[+<property>...]`, e.g. `Meeting=name+start`
+    pub fn set_key(&mut self, key: &str) -> Result<(), Box<Error>> {
+        let mut parts = key.splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some(oparl_type), Some(properties)) if !oparl_type.is_empty() && !properties.is_empty() => {
+                let properties = properties.split('+').map(String::from).collect();
+                self.keys.insert(oparl_type.to_string(), properties);
+                Ok(())
+            }
+            _ => Err(From::from(format!("Invalid key, expected <type>=<property>[+<property>]: {}", key))),
+        }
+    }
+}
+
+/// The objects of one side of a diff, either the cached ones or those of a snapshot
+#[derive(Clone, Debug)]
+pub struct DiffSide {
+    /// The entrypoint of the server
+    pub entrypoint: Url,
+    /// The objects with references instead of embedded objects
+    pub objects: Vec<JsonValue>,
+}
+
+impl DiffSide {
+    /// Reads the cached objects that can be reached from the server, so that other servers on the
+    /// same host aren't included
+    pub fn cached<S: Storage>(storage: &S, entrypoint: &Url) -> Result<DiffSide, Box<Error>> {
+        let mut objects = vec![];
+        for object in iter_reachable(storage, entrypoint) {
+            objects.push(object?);
+        }
+        Ok(DiffSide {
+            entrypoint: entrypoint.clone(),
+            objects: objects,
+        })
+    }
+
+    /// Reads the objects of a snapshot of the server
+    pub fn snapshot(storage: &FileStorage, entrypoint: &Url, name: &str) -> Result<DiffSide, Box<Error>> {
+        Ok(DiffSide {
+            entrypoint: entrypoint.clone(),
+            objects: read_snapshot(storage, entrypoint, name)?,
+        })
+    }
+}
+
+/// An object that only exists on one side
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Unmatched {
+    /// The key the object would have been matched by
+    pub key: String,
+    /// The id of the object
+    pub id: String,
+}
+
+/// A property that differs between two matched objects
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct FieldDiff {
+    /// The name of the property
+    pub property: String,
+    /// The value on the left side as json, `None` if the property is missing
+    pub left: Option<String>,
+    /// The value on the right side as json, `None` if the property is missing
+    pub right: Option<String>,
+}
+
+/// A pair of matched objects with different properties
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ObjectDiff {
+    /// The key the objects were matched by
+    pub key: String,
+    /// The id on the left side
+    pub left: String,
+    /// The id on the right side
+    pub right: String,
+    /// The differing properties. References are compared by the key of the referenced object
+    pub properties: Vec<FieldDiff>,
+}
+
+/// The differences between two servers or two snapshots
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct DiffReport {
+    /// The number of objects found on both sides
+    pub matched: usize,
+    /// The objects only on the left side
+    pub missing: Vec<Unmatched>,
+    /// The objects only on the right side
+    pub extra: Vec<Unmatched>,
+    /// The matched objects with different properties
+    pub changed: Vec<ObjectDiff>,
+    /// The keys shared by several objects on one side. Only the first of those is compared
+    pub ambiguous: Vec<String>,
+}
+
+impl DiffReport {
+    /// Returns true if both sides have the same objects with the same properties
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.extra.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// The objects of one side indexed by id with the keys computed so far
+struct KeyedSide<'d> {
+    /// The entrypoint without a trailing slash, which is stripped from ids
+    base: String,
+    objects: HashMap<String, &'d JsonValue>,
+    keys: HashMap<String, String>,
+}
+
+impl<'d> KeyedSide<'d> {
+    fn new(side: &'d DiffSide) -> KeyedSide<'d> {
+        let objects = side.objects
+            .iter()
+            .filter_map(|x| x["id"].as_str().map(|id| (id.to_string(), x)))
+            .collect();
+        KeyedSide {
+            base: side.entrypoint.as_str().trim_right_matches('/').to_string(),
+            objects: objects,
+            keys: HashMap::new(),
+        }
+    }
+
+    /// Strips the entrypoint from urls of the server, so both sides have the same paths
+    fn relative(&self, value: &str) -> String {
+        if value.starts_with(&self.base) {
+            value[self.base.len()..].to_string()
+        } else {
+            value.to_string()
+        }
+    }
+
+    fn key(&mut self, id: &str, options: &DiffOptions, depth: usize) -> String {
+        if let Some(key) = self.keys.get(id) {
+            return key.clone();
+        }
+        let object = match self.objects.get(id) {
+            Some(object) => *object,
+            None => return self.relative(id),
+        };
+        let oparl_type = split_type_url(&object["type"].to_string())
+            .map(|x| x.1)
+            .unwrap_or_else(|_| object["type"].to_string());
+        let fallback = format!("{}<{}>", oparl_type, self.relative(id));
+        if depth >= MAX_KEY_DEPTH {
+            return fallback;
+        }
+
+        let mut key = fallback.clone();
+        if let Some(properties) = options.keys.get(&oparl_type) {
+            let mut values = vec![];
+            for property in properties {
+                if object[property.as_str()].is_null() {
+                    break;
+                }
+                let value = self.normalize(&object[property.as_str()], options, depth + 1);
+                values.push(format!("{}={}", property, value.dump()));
+            }
+            if values.len() == properties.len() {
+                key = format!("{}[{}]", oparl_type, values.join(", "));
+            }
+        }
+
+        self.keys.insert(id.to_string(), key.clone());
+        key
+    }
+
+    /// Replaces the references with the keys of the referenced objects and strips the
+    /// entrypoint from the other urls of the server
+    fn normalize(&mut self, value: &JsonValue, options: &DiffOptions, depth: usize) -> JsonValue {
+        if let Some(string) = value.as_str() {
+            if self.objects.contains_key(string) {
+                return self.key(string, options, depth).into();
+            }
+            return self.relative(string).into();
+        }
+
+        match *value {
+            JsonValue::Array(ref members) => {
+                JsonValue::Array(members.iter().map(|x| self.normalize(x, options, depth)).collect())
+            }
+            JsonValue::Object(_) => {
+                let mut object = JsonValue::new_object();
+                for (key, member) in value.entries() {
+                    object[key] = self.normalize(member, options, depth);
+                }
+                object
+            }
+            _ => value.clone(),
+        }
+    }
+
+    /// Returns the ids by key, sorted by id so the first of several objects with the same key is
+    /// deterministic
+    fn index(&mut self, options: &DiffOptions, ambiguous: &mut Vec<String>) -> BTreeMap<String, String> {
+        let mut ids = self.objects.keys().cloned().collect::<Vec<_>>();
+        ids.sort();
+
+        let mut index = BTreeMap::new();
+        for id in ids {
+            let key = self.key(&id, options, 0);
+            if index.contains_key(&key) {
+                ambiguous.push(key);
+            } else {
+                index.insert(key, id);
+            }
+        }
+        index
+    }
+}
+
+/// Compares the objects of two sides. Objects are matched by the keys in `options`, and the
+/// properties of matched objects are compared with the references replaced by the keys of the
+/// referenced objects, so servers with different ids can be compared
+pub fn diff(left: &DiffSide, right: &DiffSide, options: &DiffOptions) -> DiffReport {
+    let mut report = DiffReport::default();
+    let mut left = KeyedSide::new(left);
+    let mut right = KeyedSide::new(right);
+    let left_index = left.index(options, &mut report.ambiguous);
+    let right_index = right.index(options, &mut report.ambiguous);
+
+    for (key, id) in &left_index {
+        if !right_index.contains_key(key) {
+            report.missing.push(Unmatched { key: key.clone(), id: id.clone() });
+        }
+    }
+    for (key, id) in &right_index {
+        if !left_index.contains_key(key) {
+            report.extra.push(Unmatched { key: key.clone(), id: id.clone() });
+        }
+    }
+
+    for (key, left_id) in &left_index {
+        let right_id = match right_index.get(key) {
+            Some(right_id) => right_id,
+            None => continue,
+        };
+        report.matched += 1;
+
+        let left_object = left.objects[left_id];
+        let right_object = right.objects[right_id];
+        let properties = left_object.entries()
+            .chain(right_object.entries())
+            .map(|(property, _)| property.to_string())
+            .filter(|x| !options.ignore.contains(x))
+            .collect::<BTreeSet<_>>();
+
+        let mut differences = vec![];
+        for property in properties {
+            let left_value = left.normalize(&left_object[property.as_str()], options, 1);
+            let right_value = right.normalize(&right_object[property.as_str()], options, 1);
+            if left_value != right_value {
+                differences.push(FieldDiff {
+                    property: property,
+                    left: if left_value.is_null() { None } else { Some(left_value.dump()) },
+                    right: if right_value.is_null() { None } else { Some(right_value.dump()) },
+                });
+            }
+        }
+
+        if !differences.is_empty() {
+            report.changed.push(ObjectDiff {
+                key: key.clone(),
+                left: left_id.clone(),
+                right: right_id.clone(),
+                properties: differences,
+            });
+        }
+    }
+
+    report
+}
@@ -1,17 +1,33 @@
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::io::Read;
 use std::path::{Path, PathBuf};
-use std::fs::{File, create_dir_all};
+use std::fs::{File, OpenOptions, create_dir_all, remove_file};
 use std::error::Error;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Instant, SystemTime};
+use std::vec::IntoIter;
 
+use chrono::Utc;
+use fs2::FileExt;
 use json;
 use json::JsonValue;
 use reqwest::Url;
 use serde_json;
 use serde_json::Error as SerdeError;
 
-use cacher::Cacher;
+use cacher::{Cacher, Progress};
 use server::Server;
-use storage::Storage;
+use storage::{CacheIter, Storage};
+use time_window::{TimeWindow, FILTER_PARAMETERS};
+use object_filter::ObjectFilter;
+use canonical::IdRewriter;
+use refs::{Reference, ReferenceIndex};
+use schema::{load_schema, split_type_url};
+use sync_report::{SyncReport, as_millis};
+use hooks::{Hook, run_hooks};
+use snapshot::{DEFAULT_MAX_SNAPSHOTS, SNAPSHOTS_DIR, prune_snapshots, write_snapshot};
 
 /// This file stores information about the cache status to allow incremental updates
 pub const CACHE_STATUS_FILE: &'static str = "cache_status.json";
@@ -19,9 +35,58 @@ pub const CACHE_STATUS_FILE: &'static str = "cache_status.json";
 /// This file lists all cached servers
 pub const CACHED_SERVERS_FILE: &'static str = "cached_servers.json";
 
+/// This folder next to the cache status file contains a report for every sync run
+pub const SYNC_REPORTS_DIR: &'static str = "sync_reports";
+
 /// File extension for the downloaded objects so that they can be distingishued from directories
 pub const FILE_EXTENSION: &'static str = ".json";
 
+/// Suffix of the files next to the objects that list the objects referencing them
+pub const REFERENCES_EXTENSION: &'static str = ".refs.json";
+
+/// Suffix of the mirrored documents next to the File objects
+pub const CONTENT_EXTENSION: &'static str = ".content";
+
+/// Suffix of the files next to the File objects that contain the text extracted from the document
+pub const TEXT_EXTENSION: &'static str = ".text.json";
+
+/// Suffix of the files next to the File objects that record from where and in which version the
+/// document was mirrored
+pub const MIRROR_EXTENSION: &'static str = ".mirror.json";
+
+/// This folder in the cache folder contains a lock file for every sync, daemon run and garbage
+/// collection, so that they don't get in each other's way across processes. The files are locked
+/// with advisory locks of the operating system, so a lock is released even if its process dies
+pub const LOCKS_DIR: &'static str = "locks";
+
+/// The name of the lock file of the garbage collection
+pub const GC_LOCK: &'static str = "gc";
+
+/// Suffix of the lock files
+const LOCK_EXTENSION: &'static str = ".lock";
+
+/// A locked file in `LOCKS_DIR`. The lock is released when this is dropped or the process exits.
+/// The file itself is kept, as deleting it would race with other processes opening it
+pub struct LockFile {
+    _file: File,
+}
+
+/// Returns the name of the lock file of a sync of the server with the entrypoint `entrypoint`
+fn sync_lock_name(entrypoint: &Url) -> String {
+    let slug = entrypoint.as_str().chars().map(|x| if x.is_alphanumeric() { x } else { '-' }).collect::<String>();
+    "sync-".to_string() + &slug
+}
+
+/// The number of locks the objects and the reference files are distributed over by their id
+const LOCK_STRIPES: usize = 64;
+
+/// Returns the lock of `locks` that is responsible for `url`
+fn stripe<'l>(locks: &'l [Mutex<()>], url: &Url) -> &'l Mutex<()> {
+    let mut hasher = DefaultHasher::new();
+    url.as_str().hash(&mut hasher);
+    &locks[hasher.finish() as usize % locks.len()]
+}
+
 /// Helper Struct for deserializing the cache Status files
 #[derive(Serialize, Deserialize, Clone)]
 pub struct UrlWithTimestamp {
@@ -30,6 +95,29 @@ pub struct UrlWithTimestamp {
 }
 pub type CacheStatus = Vec<UrlWithTimestamp>;
 
+/// The contents of the cache status file
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct CacheStatusFile {
+    /// The OParl version of the server as given by the System object
+    #[serde(default)]
+    pub oparl_version: Option<String>,
+    /// The filter the cache was created with
+    pub filter: ObjectFilter,
+    /// The rules the ids were rewritten with
+    #[serde(default)]
+    pub id_rewriter: IdRewriter,
+    /// The external lists with the time of their last successful update
+    pub lists: CacheStatus,
+}
+
+/// Older caches only stored the list of the external lists
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AnyCacheStatusFile {
+    Current(CacheStatusFile),
+    Legacy(CacheStatus),
+}
+
 
 /// A Storage where every object becomes a file under a specified folder
 ///
@@ -51,6 +139,18 @@ pub struct FileStorage<'a> {
     cache_dir: PathBuf,
     cache_status_file: &'a str,
     cached_servers_file: &'a str,
+    object_filter: ObjectFilter,
+    id_rewriter: IdRewriter,
+    hooks: HashMap<Url, Vec<Hook>>,
+    /// Serializes comparing an object with its cached version and writing it, by the id
+    object_locks: Arc<Vec<Mutex<()>>>,
+    /// Serializes the updates of a reference file, which are written from all workers, by the
+    /// id of the referenced object
+    references_locks: Arc<Vec<Mutex<()>>>,
+    progress_callback: Option<Arc<Fn(Progress) + Send + Sync>>,
+    max_workers: Option<usize>,
+    keep_snapshots: bool,
+    max_snapshots: usize,
 }
 
 impl<'a> Storage for FileStorage<'a> {
@@ -79,34 +179,224 @@ impl<'a> Storage for FileStorage<'a> {
         Ok(json)
     }
 
+    /// Walks the folder of the entrypoint of `server` in the order of the paths, followed by the
+    /// System object and the list elements stored outside of it. The folders of other servers
+    /// below the entrypoint, the sync reports and the reference index are skipped. Embedded
+    /// objects outside of the folder that aren't list elements aren't found
+    fn iter_objects<'s>(&'s self, server: &Url, type_filter: Option<&str>) -> CacheIter<'s, JsonValue> {
+        self.walk_objects(server, type_filter.map(|x| vec![x.to_string()]))
+    }
+
+    /// Walks the folder of the entrypoint of `server` once for all types
+    fn iter_objects_of<'s>(&'s self, server: &Url, types: &[&str]) -> CacheIter<'s, JsonValue> {
+        self.walk_objects(server, Some(types.iter().map(|x| x.to_string()).collect()))
+    }
+
+    /// Reads the lists recorded in the cache status file of `server`
+    fn iter_lists<'s>(&'s self, server: &Url) -> CacheIter<'s, (Url, JsonValue)> {
+        let lists = match self.get_cache_status(server) {
+            Ok(Some(status)) => status.lists,
+            Ok(None) => vec![],
+            Err(err) => return Box::new(Some(Err(err)).into_iter()),
+        };
+        Box::new(lists.into_iter().map(move |list| {
+            let ids = self.get(&list.url)?;
+            Ok((list.url, ids))
+        }))
+    }
+
+    /// Returns the modification time of the cache status file of `server`, which is rewritten
+    /// after every sync
+    fn last_updated(&self, server: &Url) -> Option<SystemTime> {
+        let path = self.url_to_path(server, "").join(self.cache_status_file);
+        path.metadata().and_then(|x| x.modified()).ok()
+    }
+
     /// Returns `schema`
     fn get_schema(&self) -> &JsonValue {
         &self.schema
     }
 }
 
+/// Walks the files of a cache folder depth first, reading only one directory listing per level
+/// at a time, and then the files in `outside`
+struct ObjectWalker {
+    stack: Vec<IntoIter<PathBuf>>,
+    /// The folder of the entrypoint
+    root: PathBuf,
+    /// The files of the System objects of other servers below `root`
+    skipped: HashSet<PathBuf>,
+    /// The System object and the list elements of the server that aren't stored below `root`
+    outside: IntoIter<PathBuf>,
+    cache_status_file: String,
+    type_filter: Option<Vec<String>>,
+}
+
+impl ObjectWalker {
+    /// Returns the object stored at `path` or `None` if it's a list or not of the filtered types
+    fn read(&self, path: &Path) -> Result<Option<JsonValue>, Box<Error>> {
+        let mut s = String::new();
+        File::open(path)?.read_to_string(&mut s)?;
+        // The type urls end with the type name, so files that don't contain any of the names
+        // can't match and aren't parsed
+        if let Some(ref type_filter) = self.type_filter {
+            if !type_filter.iter().any(|x| s.contains(&format!("/{}\"", x))) {
+                return Ok(None);
+            }
+        }
+        let object = json::parse(&s)?;
+        // External lists are stored as arrays of ids
+        if !object.is_object() || !object["id"].is_string() || !object["type"].is_string() {
+            return Ok(None);
+        }
+        if let Some(ref type_filter) = self.type_filter {
+            if !type_filter.contains(&split_type_url(&object["type"].to_string())?.1) {
+                return Ok(None);
+            }
+        }
+        Ok(Some(object))
+    }
+}
+
+impl Iterator for ObjectWalker {
+    type Item = Result<JsonValue, Box<Error>>;
+
+    fn next(&mut self) -> Option<Result<JsonValue, Box<Error>>> {
+        loop {
+            let path = match self.stack.last_mut().map(|x| x.next()) {
+                Some(Some(path)) => path,
+                Some(None) => {
+                    self.stack.pop();
+                    continue;
+                }
+                None => {
+                    match self.outside.next() {
+                        // The elements of lists may have been deleted by the garbage collection
+                        Some(path) => {
+                            if !path.is_file() {
+                                continue;
+                            }
+                            match self.read(&path) {
+                                Ok(Some(object)) => return Some(Ok(object)),
+                                Ok(None) => continue,
+                                Err(err) => return Some(Err(err)),
+                            }
+                        }
+                        None => return None,
+                    }
+                }
+            };
+
+            let name = path.file_name().and_then(|x| x.to_str()).unwrap_or("").to_string();
+            if path.is_dir() {
+                if name == SYNC_REPORTS_DIR || name == SNAPSHOTS_DIR {
+                    continue;
+                }
+                // Another server whose entrypoint is below the one of this server
+                if path != self.root && path.join(&self.cache_status_file).is_file() {
+                    self.skipped.insert(path.with_file_name(name + FILE_EXTENSION));
+                    continue;
+                }
+                let mut paths = vec![];
+                match path.read_dir() {
+                    Ok(entries) => {
+                        for entry in entries {
+                            match entry {
+                                Ok(entry) => paths.push(entry.path()),
+                                Err(err) => return Some(Err(From::from(err))),
+                            }
+                        }
+                    }
+                    Err(err) => return Some(Err(From::from(err))),
+                }
+                paths.sort();
+                self.stack.push(paths.into_iter());
+                continue;
+            }
+
+            if !name.ends_with(FILE_EXTENSION) || name.ends_with(REFERENCES_EXTENSION) ||
+               name.ends_with(TEXT_EXTENSION) || name.ends_with(MIRROR_EXTENSION) ||
+               name == self.cache_status_file || self.skipped.contains(&path) {
+                continue;
+            }
+
+            match self.read(&path) {
+                Ok(Some(object)) => return Some(Ok(object)),
+                Ok(None) => continue,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+impl<'a> ReferenceIndex for FileStorage<'a> {
+    fn add_reference(&self, target: &Url, reference: &Reference) -> Result<(), Box<Error>> {
+        self.change_references(target, &[], &[reference.clone()])
+    }
+
+    fn remove_reference(&self, target: &Url, reference: &Reference) -> Result<(), Box<Error>> {
+        self.change_references(target, &[reference.clone()], &[])
+    }
+
+    /// Reads and writes the references file of `target` once while holding the lock of `target`
+    fn change_references(&self,
+                         target: &Url,
+                         removed: &[Reference],
+                         added: &[Reference])
+                         -> Result<(), Box<Error>> {
+        let _guard = stripe(&self.references_locks, target).lock().unwrap();
+        let mut references = self.get_references(target)?;
+        let before = references.len();
+        references.retain(|x| !removed.contains(x));
+        let mut changed = references.len() != before;
+        for reference in added {
+            if !references.contains(reference) {
+                references.push(reference.clone());
+                changed = true;
+            }
+        }
+        if changed {
+            references.sort();
+            self.write_references(target, &references)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the references file next to the cached object. Objects that aren't referenced
+    /// don't have such a file
+    fn get_references(&self, target: &Url) -> Result<Vec<Reference>, Box<Error>> {
+        let path = self.url_to_path(target, REFERENCES_EXTENSION);
+        if !path.exists() {
+            return Ok(vec![]);
+        }
+        Ok(serde_json::from_reader(File::open(path)?)?)
+    }
+}
+
 impl<'a> FileStorage<'a> {
     /// Creates a new `Storage`
+    ///
+    /// `schema_dir` contains either the schema files or one folder with the schema files for each
+    /// supported OParl version, e.g. `1.0/` and `1.1/`
     pub fn new(schema_dir: &Path,
                cache_dir: PathBuf)
                -> Result<FileStorage<'a>, Box<Error>> {
-        // Load the schema
-        let mut schema = JsonValue::new_array();
-        for i in Path::new(schema_dir).read_dir()? {
-            let mut f: File = File::open(i?.path())?;
-            let mut s = String::new();
-            f.read_to_string(&mut s)?;
-            let x = json::parse(&s)?;
-            let y = x["title"].to_string();
-            schema[y] = x;
-        }
+        let schema = load_schema(schema_dir)?;
 
-        assert_eq!(schema.len(), 12, "Expected 12 Schema files");
         Ok(FileStorage {
             schema: schema,
             cache_dir: cache_dir,
             cache_status_file: CACHE_STATUS_FILE,
-            cached_servers_file: CACHED_SERVERS_FILE
+            cached_servers_file: CACHED_SERVERS_FILE,
+            object_filter: ObjectFilter::default(),
+            id_rewriter: IdRewriter::default(),
+            hooks: HashMap::new(),
+            object_locks: Arc::new((0..LOCK_STRIPES).map(|_| Mutex::new(())).collect()),
+            references_locks: Arc::new((0..LOCK_STRIPES).map(|_| Mutex::new(())).collect()),
+            progress_callback: None,
+            max_workers: None,
+            keep_snapshots: false,
+            max_snapshots: DEFAULT_MAX_SNAPSHOTS,
         })
     }
 
@@ -115,6 +405,85 @@ impl<'a> FileStorage<'a> {
         self.cache_dir.clone()
     }
 
+    /// Walks the folder of the entrypoint of `server` and the objects of the server outside of
+    /// it, returning only the objects of `type_filter`
+    fn walk_objects<'s>(&'s self, server: &Url, type_filter: Option<Vec<String>>) -> CacheIter<'s, JsonValue> {
+        let root = self.url_to_path(server, "");
+        let lists = match self.get_cache_status(server) {
+            Ok(Some(status)) => status.lists,
+            Ok(None) => vec![],
+            Err(err) => return Box::new(Some(Err(err)).into_iter()),
+        };
+
+        let mut outside = vec![];
+        let system = self.url_to_path(server, FILE_EXTENSION);
+        if !system.starts_with(&root) {
+            outside.push(system);
+        }
+        let mut seen = HashSet::new();
+        for list in lists {
+            let ids = match self.get(&list.url) {
+                Ok(ids) => ids,
+                Err(err) => return Box::new(Some(Err(err)).into_iter()),
+            };
+            for id in ids.members().filter_map(|x| x.as_str()).filter_map(|x| Url::parse(x).ok()) {
+                let path = self.url_to_path(&id, FILE_EXTENSION);
+                if !path.starts_with(&root) && seen.insert(path.clone()) {
+                    outside.push(path);
+                }
+            }
+        }
+
+        Box::new(ObjectWalker {
+            stack: if root.is_dir() { vec![vec![root.clone()].into_iter()] } else { vec![] },
+            root: root,
+            skipped: HashSet::new(),
+            outside: outside.into_iter(),
+            cache_status_file: self.cache_status_file.to_string(),
+            type_filter: type_filter,
+        })
+    }
+
+    /// Locks the file `name` in `LOCKS_DIR`, creating it if needed. Fails if another process or
+    /// another `LockFile` of this process holds the lock
+    pub fn create_lock(&self, name: &str) -> Result<LockFile, Box<Error>> {
+        let dir = self.cache_dir.join(LOCKS_DIR);
+        create_dir_all(&dir)?;
+        let path = dir.join(name.to_string() + LOCK_EXTENSION);
+        let file = OpenOptions::new().write(true).create(true).open(&path)?;
+        match file.try_lock_exclusive() {
+            Ok(()) => Ok(LockFile { _file: file }),
+            Err(err) => Err(From::from(format!("The lock {} is held by another process: {}", path.display(), err))),
+        }
+    }
+
+    /// Returns the names of the locks that are currently held, including those of this process.
+    /// Lock files left behind by processes that exited are skipped
+    pub fn get_locks(&self) -> Result<Vec<String>, Box<Error>> {
+        let dir = self.cache_dir.join(LOCKS_DIR);
+        if !dir.is_dir() {
+            return Ok(vec![]);
+        }
+        let mut names = vec![];
+        for entry in dir.read_dir()? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.ends_with(LOCK_EXTENSION) {
+                continue;
+            }
+            // Taking the lock only succeeds if nobody holds it. It is released right away
+            let held = match OpenOptions::new().write(true).open(entry.path()) {
+                Ok(file) => file.try_lock_exclusive().is_err(),
+                Err(_) => false,
+            };
+            if held {
+                names.push(name.trim_right_matches(LOCK_EXTENSION).to_string());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
     /// Returns `cache_status_file`
     pub fn get_cache_status_file(&self) -> &'a str {
         self.cache_status_file
@@ -130,17 +499,59 @@ impl<'a> FileStorage<'a> {
         self.cached_servers_file = cached_servers_file;
     }
 
+    /// Restricts the cache to the lists allowed by `object_filter`
+    pub fn set_object_filter(&mut self, object_filter: ObjectFilter) {
+        self.object_filter = object_filter;
+    }
+
+    /// Rewrites the ids and references of all objects with the rules of `id_rewriter`
+    pub fn set_id_rewriter(&mut self, id_rewriter: IdRewriter) {
+        self.id_rewriter = id_rewriter;
+    }
+
+    /// Sets the hooks that are triggered by the sync runs of the server with `entrypoint`
+    pub fn set_hooks(&mut self, entrypoint: Url, hooks: Vec<Hook>) {
+        self.hooks.insert(entrypoint, hooks);
+    }
+
+    /// Loads at most `max_workers` lists at the same time. `None` removes the limit
+    pub fn set_max_workers(&mut self, max_workers: Option<usize>) {
+        self.max_workers = max_workers;
+    }
+
+    /// Writes a snapshot of the objects of a server after every sync run, so the state at the
+    /// time of a run can be diffed later
+    pub fn set_keep_snapshots(&mut self, keep_snapshots: bool) {
+        self.keep_snapshots = keep_snapshots;
+    }
+
+    /// Deletes the oldest snapshots of a server after a run, so that at most `max_snapshots` are
+    /// kept. `0` keeps all snapshots. Defaults to `DEFAULT_MAX_SNAPSHOTS`
+    pub fn set_max_snapshots(&mut self, max_snapshots: usize) {
+        self.max_snapshots = max_snapshots;
+    }
+
+    /// Calls `callback` whenever a list of a sync run is started or finished, e.g. to show a
+    /// progress bar
+    pub fn set_progress_callback<F>(&mut self, callback: F)
+        where F: Fn(Progress) + Send + Sync + 'static
+    {
+        self.progress_callback = Some(Arc::new(callback));
+    }
+
     /// Takes an `url` and returns the corresponding cache path in the form
     /// <cachedir>/<scheme>[:<host>][:<port>][/<path>]<suffix>
+    ///
+    /// The url is rewritten with the rules of `id_rewriter` first, so that the entrypoint as
+    /// given by the user and the ids of the cached objects lead to the same folder
     pub fn url_to_path(&self, url: &Url, suffix: &str) -> PathBuf {
+        let url = &Url::parse(&self.id_rewriter.rewrite(url.as_str())).unwrap_or_else(|_| url.clone());
+
         // Remove the oparl filters
         // Those parameters shouldn't be on any object, but it's better to sanitize
         let url_binding: Url = url.clone();
         let query_without_filters = url_binding.query_pairs()
-            .filter(|&(ref arg_name, _)| arg_name != "modified_until")
-            .filter(|&(ref arg_name, _)| arg_name != "modified_since")
-            .filter(|&(ref arg_name, _)| arg_name != "created_since")
-            .filter(|&(ref arg_name, _)| arg_name != "created_until");
+            .filter(|&(ref arg_name, _)| !FILTER_PARAMETERS.iter().any(|x| x == arg_name));
 
         let mut url_clone = url.clone();
         let url: &mut Url = url_clone.query_pairs_mut()
@@ -211,28 +622,152 @@ impl<'a> FileStorage<'a> {
             Err(_) => vec![],
         });
     }
+
+    /// Replaces the list of the cached servers
+    pub fn set_cached_servers(&self, servers: &[Url]) -> Result<(), Box<Error>> {
+        let path = self.get_cache_dir().join(self.cached_servers_file);
+        serde_json::to_writer_pretty(File::create(&path)?, &servers)?;
+        Ok(())
+    }
+
+    /// Reads the cache status of the server with the given entrypoint. Returns `None` if the
+    /// server hasn't been cached yet
+    pub fn get_cache_status(&self, entrypoint: &Url) -> Result<Option<CacheStatusFile>, Box<Error>> {
+        let path = self.url_to_path(entrypoint, "").join(self.get_cache_status_file());
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let file = File::open(&path)?;
+        let status = match serde_json::from_reader(file)? {
+            AnyCacheStatusFile::Current(status) => status,
+            AnyCacheStatusFile::Legacy(lists) => CacheStatusFile {
+                oparl_version: None,
+                filter: ObjectFilter::default(),
+                id_rewriter: IdRewriter::default(),
+                lists: lists,
+            },
+        };
+
+        Ok(Some(status))
+    }
+
+    /// Returns the reports of all sync runs of a server, the latest one last
+    pub fn get_sync_reports(&self, entrypoint: &Url) -> Result<Vec<SyncReport>, Box<Error>> {
+        let dir = self.url_to_path(entrypoint, "").join(SYNC_REPORTS_DIR);
+        if !dir.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut paths = vec![];
+        for i in dir.read_dir()? {
+            paths.push(i?.path());
+        }
+        // The file names start with the UTC timestamp of the run
+        paths.sort();
+
+        let mut reports = vec![];
+        for path in paths {
+            reports.push(serde_json::from_reader(File::open(path)?)?);
+        }
+
+        Ok(reports)
+    }
+
+    /// Writes the references file of `target`, removing it if there are no references
+    fn write_references(&self, target: &Url, references: &[Reference]) -> Result<(), Box<Error>> {
+        let path = self.url_to_path(target, REFERENCES_EXTENSION);
+        if references.is_empty() {
+            return Ok(remove_file(path)?);
+        }
+
+        create_dir_all(path.parent().ok_or("Invalid cachepath for file")?)?;
+        serde_json::to_writer_pretty(File::create(path)?, references)?;
+        Ok(())
+    }
+
+    /// Stores the report of a sync run in the folder of the server
+    fn write_sync_report(&self, report: &SyncReport) -> Result<(), Box<Error>> {
+        let dir = self.url_to_path(&report.entrypoint, "").join(SYNC_REPORTS_DIR);
+        create_dir_all(&dir)?;
+        let file = File::create(dir.join(report.started.clone() + FILE_EXTENSION))?;
+        serde_json::to_writer_pretty(file, report)?;
+        Ok(())
+    }
 }
 
 impl<'a> Cacher for FileStorage<'a> {
     /// Loads the whole API to the cache or updates an existing cache
-    /// This function does only do the loading saving and forwards the actual work
     fn cache<U: Server>(&self, server: U) -> Result<(), Box<Error>> {
+        self.sync(server, &TimeWindow::default())
+    }
+
+    /// Loads the objects in `window` into an existing cache. The cache status is merged so that
+    /// the next regular update remains incremental
+    fn backfill<U: Server>(&self, server: U, window: &TimeWindow) -> Result<(), Box<Error>> {
+        self.sync(server, window)
+    }
+
+    /// Returns `object_filter`
+    fn get_object_filter(&self) -> &ObjectFilter {
+        &self.object_filter
+    }
+
+    /// Returns `id_rewriter`
+    fn get_id_rewriter(&self) -> &IdRewriter {
+        &self.id_rewriter
+    }
+
+    /// Returns `max_workers`
+    fn get_max_workers(&self) -> Option<usize> {
+        self.max_workers
+    }
+
+    /// Forwards the progress to the callback given with `set_progress_callback`
+    fn report_progress(&self, progress: Progress) {
+        if let Some(ref callback) = self.progress_callback {
+            callback(progress);
+        }
+    }
+
+    fn lock_object(&self, id: &Url) -> Option<MutexGuard<()>> {
+        Some(stripe(&self.object_locks, id).lock().unwrap())
+    }
+}
+
+impl<'a> FileStorage<'a> {
+    /// This function does only do the loading saving and forwards the actual work
+    fn sync<U: Server>(&self, server: U, window: &TimeWindow) -> Result<(), Box<Error>> {
+        // The lock is taken before checking for the garbage collection, which does the same the
+        // other way round, so that at least one of both notices the other
+        let _lock = self.create_lock(&sync_lock_name(&server.get_entrypoint()))?;
+        if self.get_locks()?.iter().any(|x| x == GC_LOCK) {
+            return Err(From::from("The garbage collection is running, try again once it's finished"));
+        }
+
         let entrypoint_path = self.url_to_path(&server.get_entrypoint(), "");
         let cache_status_filepath = entrypoint_path.join(self.get_cache_status_file());
         println!("Cache Status File: {}", &cache_status_filepath.display());
         let known_lists: CacheStatus;
 
-        if cache_status_filepath.exists() {
+        if let Some(status) = self.get_cache_status(&server.get_entrypoint())? {
             // We have a cache, so let's load it
             println!("Cache found, updating...");
-            let mut cache_status_file = File::open(&cache_status_filepath)?;
-            known_lists = serde_json::from_reader(&mut cache_status_file)?;
-
-            println!("External lists found in cache: {}", known_lists.len());
-            for i in known_lists.iter() {
+            println!("External lists found in cache: {}", status.lists.len());
+            for i in status.lists.iter() {
                 println!("{}: {:?}", i.url, i.last_sync);
             }
             println!();
+
+            if status.filter != self.object_filter {
+                println!("The filter has changed since the last run, reloading all lists");
+                known_lists = Vec::new();
+            } else if status.id_rewriter != self.id_rewriter {
+                println!("The id rewrite rules have changed since the last run, reloading all lists");
+                known_lists = Vec::new();
+            } else {
+                known_lists = status.lists;
+            }
         } else {
             // We don't have a cache, so let's use an empty template
             println!("No cache found, initializing...");
@@ -241,25 +776,65 @@ impl<'a> Cacher for FileStorage<'a> {
             known_lists = Vec::new();
         }
 
+        let started = Utc::now();
+        let started_instant = Instant::now();
+        let (mut new_cache_status, list_reports) =
+            self.load_all_external_lists(&server, &known_lists, window);
+
+        if !window.is_empty() {
+            // Lists that failed during a backfill keep their previous state
+            for i in known_lists {
+                if !new_cache_status.iter().any(|x| x.url == i.url) {
+                    new_cache_status.push(i);
+                }
+            }
+        }
+
+        let entrypoint = server.get_entrypoint();
+        let oparl_version = self.get(&entrypoint).ok().and_then(|x| self.check_oparl_version(&x).ok());
+
         // Write the results back to the cache
+        let new_cache_status = CacheStatusFile {
+            oparl_version: oparl_version,
+            filter: self.object_filter.clone(),
+            id_rewriter: self.id_rewriter.clone(),
+            lists: new_cache_status,
+        };
         let mut cache_status_file = File::create(&cache_status_filepath)?;
-        let new_cache_status = self.load_all_external_lists(&server, &known_lists);
         serde_json::to_writer_pretty(&mut cache_status_file, &new_cache_status)?;
 
+        let report = SyncReport {
+            entrypoint: entrypoint.clone(),
+            started: started.to_rfc3339(),
+            backfill: !window.is_empty(),
+            duration_ms: as_millis(started_instant.elapsed()),
+            lists: list_reports,
+        };
+        self.write_sync_report(&report)?;
+
+        if self.keep_snapshots {
+            let count = write_snapshot(self, &entrypoint, &report.started)?;
+            println!("Snapshot {} with {} objects written", report.started, count);
+            let pruned = prune_snapshots(self, &entrypoint, self.max_snapshots)?;
+            if pruned > 0 {
+                println!("Deleted {} old snapshots", pruned);
+            }
+        }
+
+        if let Some(hooks) = self.hooks.get(&entrypoint) {
+            for error in run_hooks(hooks, &report) {
+                println!("✗ {}", error);
+            }
+        }
+
         // After successful caching, add this server to the list of cached servers
         let mut servers = self.get_cached_servers()?;
 
-        let entrypoint = server.get_entrypoint();
         if !servers.contains(&entrypoint) {
             println!("Adding server to known servers");
             servers.push(entrypoint);
         }
 
-        let mut path = self.get_cache_dir();
-        path.push(self.cached_servers_file);
-        let file = File::create(&path)?;
-        serde_json::to_writer_pretty(file, &servers)?;
-
-        Ok(())
+        self.set_cached_servers(&servers)
     }
 }
@@ -0,0 +1,360 @@
+use std::error::Error;
+use std::fs::{File, create_dir_all};
+use std::io::{Read, Write};
+use std::path::Path;
+
+use reqwest::{IntoUrl, Url};
+use serde_json;
+use sha1::Sha1;
+
+use file_storage::{FileStorage, CONTENT_EXTENSION, MIRROR_EXTENSION, TEXT_EXTENSION};
+use storage::Storage;
+
+/// The number of mirrored documents
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MirrorReport {
+    /// The documents that were downloaded
+    pub downloaded: usize,
+    /// The documents that were already mirrored
+    pub unchanged: usize,
+    /// The documents that couldn't be downloaded
+    pub failed: usize,
+    /// The size of the downloaded documents in bytes
+    pub bytes: u64,
+}
+
+/// The number of documents the text was extracted from
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TextReport {
+    /// The documents the text was extracted from
+    pub extracted: usize,
+    /// The documents with the same checksum as on the last extraction
+    pub unchanged: usize,
+    /// The documents in a format without text extraction
+    pub unsupported: usize,
+    /// The documents the extraction failed for
+    pub failed: usize,
+}
+
+/// From where and in which version a document was mirrored, stored next to the File object
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MirroredDocument {
+    /// The url the document was downloaded from
+    pub url: String,
+    /// The `modified` date of the File object at the time of the download
+    #[serde(default)]
+    pub modified: Option<String>,
+}
+
+/// The text of a mirrored document, stored next to the File object
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ExtractedText {
+    /// The SHA1 checksum of the document the text was extracted from
+    pub checksum: String,
+    /// The format of the document, `pdf`, `html` or `text`
+    pub format: String,
+    /// The plain text, `None` if the extraction failed
+    #[serde(default)]
+    pub text: Option<String>,
+    /// Why the extraction failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The formats the text can be extracted from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// Portable Document Format, which requires the `pdf` feature
+    Pdf,
+    /// HTML, from which the tags are stripped
+    Html,
+    /// Plain text
+    Text,
+}
+
+impl Format {
+    /// Determines the format from the content, the `mimeType` or the `fileName` of the File object
+    pub fn detect(content: &[u8], mime_type: Option<&str>, file_name: Option<&str>) -> Option<Format> {
+        if content.starts_with(b"%PDF") {
+            return Some(Format::Pdf);
+        }
+        let mime_type = mime_type.unwrap_or("").split(';').next().unwrap_or("").trim().to_lowercase();
+        match mime_type.as_str() {
+            "application/pdf" => return Some(Format::Pdf),
+            "text/html" | "application/xhtml+xml" => return Some(Format::Html),
+            x if x.starts_with("text/") => return Some(Format::Text),
+            _ => {}
+        }
+        let file_name = file_name.unwrap_or("").to_lowercase();
+        if file_name.ends_with(".pdf") {
+            Some(Format::Pdf)
+        } else if file_name.ends_with(".html") || file_name.ends_with(".htm") {
+            Some(Format::Html)
+        } else if file_name.ends_with(".txt") {
+            Some(Format::Text)
+        } else {
+            None
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match *self {
+            Format::Pdf => "pdf",
+            Format::Html => "html",
+            Format::Text => "text",
+        }
+    }
+}
+
+/// Returns the SHA1 checksum of the content as hex
+pub fn checksum(content: &[u8]) -> String {
+    let mut sha1 = Sha1::new();
+    sha1.update(content);
+    sha1.digest().to_string()
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, Box<Error>> {
+    let mut content = vec![];
+    File::open(path)?.read_to_end(&mut content)?;
+    Ok(content)
+}
+
+/// Reads the record of a mirrored document. A missing or unreadable record is `None`, so the
+/// document is downloaded again
+fn read_mirrored(path: &Path) -> Option<MirroredDocument> {
+    File::open(path).ok().and_then(|x| serde_json::from_reader(x).ok())
+}
+
+/// Downloads the documents of the cached File objects of a server next to the objects.
+///
+/// A document is downloaded from `downloadUrl` or else `accessUrl` if it isn't mirrored yet or if
+/// it doesn't match the `sha1Checksum` of the File object. Without a checksum, it is downloaded
+/// again when the url or the `modified` date of the File object changed. With `force`, all
+/// documents are downloaded again. File objects with an invalid id or url count as failed
+pub fn mirror_files<F>(storage: &FileStorage,
+                       entrypoint: &Url,
+                       force: bool,
+                       download: F)
+                       -> Result<MirrorReport, Box<Error>>
+    where F: Fn(&Url) -> Result<Vec<u8>, Box<Error>>
+{
+    let mut report = MirrorReport::default();
+    for file in storage.iter_objects(entrypoint, Some("File")) {
+        let file = file?;
+        let id = match file["id"].to_string().into_url() {
+            Ok(id) => id,
+            Err(err) => {
+                println!("✗ Invalid id {}: {}", file["id"], err);
+                report.failed += 1;
+                continue;
+            }
+        };
+        let address = match file["downloadUrl"].as_str().or_else(|| file["accessUrl"].as_str()) {
+            Some(address) => address,
+            None => continue,
+        };
+        let url = match address.into_url() {
+            Ok(url) => url,
+            Err(err) => {
+                println!("✗ Invalid url {} of {}: {}", address, id, err);
+                report.failed += 1;
+                continue;
+            }
+        };
+
+        let path = storage.url_to_path(&id, CONTENT_EXTENSION);
+        let mirror_path = storage.url_to_path(&id, MIRROR_EXTENSION);
+        let mirrored = MirroredDocument {
+            url: url.to_string(),
+            modified: file["modified"].as_str().map(String::from),
+        };
+        if path.is_file() && !force {
+            let changed = match file["sha1Checksum"].as_str() {
+                Some(expected) => checksum(&read_file(&path)?) != expected.to_lowercase(),
+                None => read_mirrored(&mirror_path).as_ref() != Some(&mirrored),
+            };
+            if !changed {
+                report.unchanged += 1;
+                continue;
+            }
+        }
+
+        match download(&url) {
+            Ok(content) => {
+                create_dir_all(path.parent().ok_or("Invalid cachepath for file")?)?;
+                File::create(&path)?.write_all(&content)?;
+                serde_json::to_writer_pretty(File::create(&mirror_path)?, &mirrored)?;
+                report.downloaded += 1;
+                report.bytes += content.len() as u64;
+            }
+            Err(err) => {
+                println!("✗ Downloading {} failed: {}", url, err);
+                report.failed += 1;
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Extracts the text of a PDF. pdf-extract panics on many malformed documents, so a panic is
+/// turned into an error instead of aborting the whole run
+#[cfg(feature = "pdf")]
+fn pdf_to_text(path: &Path) -> Result<String, Box<Error>> {
+    match ::std::panic::catch_unwind(|| ::pdf_extract::extract_text(path)) {
+        Ok(result) => result.map_err(|err| From::from(format!("Invalid PDF: {:?}", err))),
+        Err(_) => Err(From::from("Invalid PDF: the extraction panicked")),
+    }
+}
+
+#[cfg(not(feature = "pdf"))]
+fn pdf_to_text(_: &Path) -> Result<String, Box<Error>> {
+    Err(From::from("This binary was built without the pdf feature"))
+}
+
+/// Decodes the most common character references
+fn decode_entities(text: &str) -> String {
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Returns the byte position of the ASCII `needle` in `haystack`, ignoring the case
+fn find_ignoring_case(haystack: &str, needle: &str) -> Option<usize> {
+    let lower = |x: &u8| if *x >= b'A' && *x <= b'Z' { *x + 32 } else { *x };
+    let needle = needle.as_bytes().iter().map(&lower).collect::<Vec<_>>();
+    haystack.as_bytes()
+        .windows(needle.len())
+        .position(|window| window.iter().map(&lower).eq(needle.iter().cloned()))
+}
+
+/// Strips the tags, scripts and styles from HTML. Block elements start a new line
+pub fn html_to_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest.find('>').map_or(rest.len(), |x| x + 1);
+        let closing_tag = rest[1..end].starts_with('/');
+        let tag = rest[1..end].trim_left_matches('/').to_lowercase();
+        let tag_name = tag.split(|c: char| !c.is_alphanumeric()).next().unwrap_or("").to_string();
+        rest = &rest[end..];
+
+        if !closing_tag && (tag_name == "script" || tag_name == "style") {
+            // Skip the content up to the closing tag
+            let closing = format!("</{}", tag_name);
+            rest = match find_ignoring_case(rest, &closing) {
+                Some(position) => &rest[position..],
+                None => "",
+            };
+            continue;
+        }
+        match tag_name.as_str() {
+            "br" | "p" | "div" | "li" | "tr" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "title" => text.push('\n'),
+            "td" | "th" => text.push(' '),
+            _ => {}
+        }
+    }
+    text.push_str(rest);
+
+    decode_entities(&text)
+        .lines()
+        .map(|x| x.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|x| !x.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn extract(path: &Path, content: &[u8], format: Format) -> Result<String, Box<Error>> {
+    match format {
+        Format::Pdf => pdf_to_text(path),
+        Format::Html => Ok(html_to_text(&String::from_utf8_lossy(content))),
+        Format::Text => Ok(String::from_utf8_lossy(content).into_owned()),
+    }
+}
+
+/// Reads the text extracted from the document of a File object
+pub fn read_text(storage: &FileStorage, file: &Url) -> Result<Option<ExtractedText>, Box<Error>> {
+    let path = storage.url_to_path(file, TEXT_EXTENSION);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_reader(File::open(path)?)?))
+}
+
+/// Extracts the text of the mirrored documents of a server into a file next to each File
+/// object, leaving the object as returned by the server.
+///
+/// The extraction only runs again when the checksum of the document changed, unless `force` is
+/// given. Failures are stored with the checksum, too, so broken documents aren't retried. File
+/// objects with an invalid id count as failed
+pub fn extract_texts(storage: &FileStorage, entrypoint: &Url, force: bool) -> Result<TextReport, Box<Error>> {
+    let mut report = TextReport::default();
+    for file in storage.iter_objects(entrypoint, Some("File")) {
+        let file = file?;
+        let id = match file["id"].to_string().into_url() {
+            Ok(id) => id,
+            Err(err) => {
+                println!("✗ Invalid id {}: {}", file["id"], err);
+                report.failed += 1;
+                continue;
+            }
+        };
+        let path = storage.url_to_path(&id, CONTENT_EXTENSION);
+        if !path.is_file() {
+            continue;
+        }
+
+        let content = read_file(&path)?;
+        let checksum = checksum(&content);
+        if !force {
+            if let Some(previous) = read_text(storage, &id)? {
+                if previous.checksum == checksum {
+                    report.unchanged += 1;
+                    continue;
+                }
+            }
+        }
+
+        let format = match Format::detect(&content, file["mimeType"].as_str(), file["fileName"].as_str()) {
+            Some(format) => format,
+            None => {
+                report.unsupported += 1;
+                continue;
+            }
+        };
+        // Once the binary is built with the feature, the PDFs should be extracted
+        if format == Format::Pdf && !cfg!(feature = "pdf") {
+            report.unsupported += 1;
+            continue;
+        }
+
+        let extracted = match extract(&path, &content, format) {
+            Ok(text) => {
+                report.extracted += 1;
+                ExtractedText {
+                    checksum: checksum,
+                    format: format.name().to_string(),
+                    text: Some(text),
+                    error: None,
+                }
+            }
+            Err(err) => {
+                println!("✗ Extracting the text of {} failed: {}", id, err);
+                report.failed += 1;
+                ExtractedText {
+                    checksum: checksum,
+                    format: format.name().to_string(),
+                    text: None,
+                    error: Some(err.to_string()),
+                }
+            }
+        };
+        let text_path = storage.url_to_path(&id, TEXT_EXTENSION);
+        serde_json::to_writer_pretty(File::create(text_path)?, &extracted)?;
+    }
+    Ok(report)
+}
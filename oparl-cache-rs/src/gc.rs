@@ -0,0 +1,303 @@
+use std::collections::{HashSet, VecDeque};
+use std::error::Error;
+use std::fs::{File, read_dir, remove_dir, remove_dir_all, remove_file};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use json::{self, JsonValue};
+use reqwest::{IntoUrl, Url};
+use serde_json;
+
+use file_storage::{FileStorage, LockFile, CONTENT_EXTENSION, FILE_EXTENSION, GC_LOCK, MIRROR_EXTENSION,
+                   REFERENCES_EXTENSION, SYNC_REPORTS_DIR, TEXT_EXTENSION};
+use refs::Reference;
+use snapshot::SNAPSHOTS_DIR;
+
+/// The files that were or in a dry run would be deleted
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GcReport {
+    /// The number of deleted files
+    pub files: usize,
+    /// The size of the deleted files in bytes
+    pub bytes: u64,
+}
+
+impl GcReport {
+    fn delete(&mut self, path: &Path, dry_run: bool) -> Result<(), Box<Error>> {
+        self.files += 1;
+        self.bytes += path.metadata()?.len();
+        if !dry_run {
+            remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    fn delete_dir(&mut self, dir: &Path, dry_run: bool) -> Result<(), Box<Error>> {
+        if !dir.is_dir() {
+            return Ok(());
+        }
+        for entry in read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                self.delete_dir(&path, true)?;
+            } else {
+                self.delete(&path, true)?;
+            }
+        }
+        if !dry_run {
+            remove_dir_all(dir)?;
+        }
+        Ok(())
+    }
+}
+
+/// The folders of all hosts in the cache, which are named `<scheme>:<host>[:<port>]`
+fn host_dirs(storage: &FileStorage) -> Result<Vec<PathBuf>, Box<Error>> {
+    let dir = storage.get_cache_dir();
+    if !dir.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut dirs = vec![];
+    for entry in read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() && path.file_name().and_then(|x| x.to_str()).map_or(false, |x| x.contains(':')) {
+            dirs.push(path);
+        }
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+/// Takes the lock of the garbage collection. Fails while a sync is running, as it would write
+/// objects that aren't marked
+fn lock_cache(storage: &FileStorage) -> Result<LockFile, Box<Error>> {
+    let lock = storage.create_lock(GC_LOCK)?;
+    let others = storage.get_locks()?.into_iter().filter(|x| x != GC_LOCK).collect::<Vec<_>>();
+    if !others.is_empty() {
+        return Err(From::from(format!("The cache is in use ({}), try again once the syncs and the daemon are \
+                                       finished",
+                                      others.join(", "))));
+    }
+    Ok(lock)
+}
+
+fn read_json(path: &Path) -> Result<JsonValue, Box<Error>> {
+    let mut s = String::new();
+    File::open(path)?.read_to_string(&mut s)?;
+    Ok(json::parse(&s)?)
+}
+
+/// Adds all strings in `value` to `queue`
+fn collect_strings(value: &JsonValue, queue: &mut VecDeque<String>) {
+    if let Some(string) = value.as_str() {
+        queue.push_back(string.to_string());
+    }
+    for member in value.members() {
+        collect_strings(member, queue);
+    }
+    for (_, member) in value.entries() {
+        collect_strings(member, queue);
+    }
+}
+
+/// Returns the files of the objects and lists reachable from the System objects of `servers`.
+/// Every string that is the url of a cached file counts as reference, so that no property of
+/// the schema can be missed
+fn mark(storage: &FileStorage, servers: &[Url]) -> Result<HashSet<PathBuf>, Box<Error>> {
+    let mut marked = HashSet::new();
+    let mut queue = VecDeque::new();
+    for server in servers {
+        // Without the System object, everything would be collected
+        if !storage.url_to_path(server, FILE_EXTENSION).is_file() {
+            return Err(From::from(format!("The System object of {} isn't cached", server)));
+        }
+        queue.push_back(server.to_string());
+        // The lists are roots, too, in case an object referencing them is broken
+        if let Some(status) = storage.get_cache_status(server)? {
+            queue.extend(status.lists.into_iter().map(|x| x.url.to_string()));
+        }
+    }
+
+    while let Some(url) = queue.pop_front() {
+        let url = match url.as_str().into_url() {
+            Ok(ref url) if url.scheme() == "http" || url.scheme() == "https" => url.clone(),
+            _ => continue,
+        };
+        let path = storage.url_to_path(&url, FILE_EXTENSION);
+        if marked.contains(&path) || !path.is_file() {
+            continue;
+        }
+        let value = read_json(&path)?;
+        marked.insert(path);
+        collect_strings(&value, &mut queue);
+    }
+
+    Ok(marked)
+}
+
+/// Deletes the objects in `dir` that aren't in `marked` and removes the references of deleted
+/// objects from the reference index. The cache status files and the sync reports are kept
+fn sweep(storage: &FileStorage,
+         dir: &Path,
+         marked: &HashSet<PathBuf>,
+         dry_run: bool,
+         report: &mut GcReport)
+         -> Result<(), Box<Error>> {
+    let mut deleted = HashSet::new();
+    sweep_objects(storage, dir, marked, dry_run, report, &mut deleted)?;
+    sweep_references(storage, dir, &deleted, dry_run, report)?;
+    if !dry_run {
+        remove_empty_dirs(dir)?;
+    }
+    Ok(())
+}
+
+fn sweep_objects(storage: &FileStorage,
+                 dir: &Path,
+                 marked: &HashSet<PathBuf>,
+                 dry_run: bool,
+                 report: &mut GcReport,
+                 deleted: &mut HashSet<PathBuf>)
+                 -> Result<(), Box<Error>> {
+    for entry in read_dir(dir)? {
+        let path = entry?.path();
+        let name = path.file_name().and_then(|x| x.to_str()).unwrap_or("").to_string();
+        if path.is_dir() {
+            if name != SYNC_REPORTS_DIR && name != SNAPSHOTS_DIR {
+                sweep_objects(storage, &path, marked, dry_run, report, deleted)?;
+            }
+            continue;
+        }
+
+        if !name.ends_with(FILE_EXTENSION) || name.ends_with(REFERENCES_EXTENSION) ||
+           name.ends_with(TEXT_EXTENSION) || name.ends_with(MIRROR_EXTENSION) ||
+           name == storage.get_cache_status_file() ||
+           marked.contains(&path) {
+            continue;
+        }
+        report.delete(&path, dry_run)?;
+        // The mirrored document, its version and its text belong to the object
+        let stem = name.trim_right_matches(FILE_EXTENSION).to_string();
+        for extension in &[CONTENT_EXTENSION, MIRROR_EXTENSION, TEXT_EXTENSION] {
+            let sidecar = path.with_file_name(stem.clone() + *extension);
+            if sidecar.is_file() {
+                report.delete(&sidecar, dry_run)?;
+            }
+        }
+        deleted.insert(path);
+    }
+    Ok(())
+}
+
+/// Deletes the reference files of deleted objects and removes the deleted objects from the
+/// remaining ones
+fn sweep_references(storage: &FileStorage,
+                    dir: &Path,
+                    deleted: &HashSet<PathBuf>,
+                    dry_run: bool,
+                    report: &mut GcReport)
+                    -> Result<(), Box<Error>> {
+    for entry in read_dir(dir)? {
+        let path = entry?.path();
+        let name = path.file_name().and_then(|x| x.to_str()).unwrap_or("").to_string();
+        if path.is_dir() {
+            if name != SYNC_REPORTS_DIR && name != SNAPSHOTS_DIR {
+                sweep_references(storage, &path, deleted, dry_run, report)?;
+            }
+            continue;
+        }
+        if !name.ends_with(REFERENCES_EXTENSION) {
+            continue;
+        }
+
+        let object = path.with_file_name(name.trim_right_matches(REFERENCES_EXTENSION).to_string() + FILE_EXTENSION);
+        if deleted.contains(&object) {
+            report.delete(&path, dry_run)?;
+            continue;
+        }
+
+        let references: Vec<Reference> = serde_json::from_reader(File::open(&path)?)?;
+        let remaining = references.iter()
+            .filter(|x| {
+                x.source
+                    .as_str()
+                    .into_url()
+                    .map(|url| !deleted.contains(&storage.url_to_path(&url, FILE_EXTENSION)))
+                    .unwrap_or(true)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        if remaining.is_empty() {
+            report.delete(&path, dry_run)?;
+        } else if remaining.len() != references.len() && !dry_run {
+            serde_json::to_writer_pretty(File::create(&path)?, &remaining)?;
+        }
+    }
+    Ok(())
+}
+
+fn remove_empty_dirs(dir: &Path) -> Result<(), Box<Error>> {
+    for entry in read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            remove_empty_dirs(&path)?;
+        }
+    }
+    if read_dir(dir)?.next().is_none() {
+        remove_dir(dir)?;
+    }
+    Ok(())
+}
+
+/// Deletes the objects that can't be reached from the System object of any cached server
+/// anymore, e.g. because they were removed from all lists. All servers are marked in one pass
+/// and every host folder is swept, so objects referenced across hosts are kept. With `dry_run`,
+/// nothing is deleted. Fails while a sync is running
+pub fn collect_garbage(storage: &FileStorage, dry_run: bool) -> Result<GcReport, Box<Error>> {
+    let _lock = lock_cache(storage)?;
+    let mut report = GcReport::default();
+    let servers = storage.get_cached_servers()?;
+    // Without any server, everything would be collected
+    if servers.is_empty() {
+        return Ok(report);
+    }
+
+    let marked = mark(storage, &servers)?;
+    for dir in host_dirs(storage)? {
+        sweep(storage, &dir, &marked, dry_run, &mut report)?;
+    }
+    Ok(report)
+}
+
+/// Removes a server from the list of the cached servers and deletes its cache status, its sync
+/// reports, its snapshots and all objects that can't be reached from another cached server.
+/// Fails while a sync is running
+pub fn remove_server(storage: &FileStorage, entrypoint: &Url, dry_run: bool) -> Result<GcReport, Box<Error>> {
+    let _lock = lock_cache(storage)?;
+    let servers = storage.get_cached_servers()?;
+    if !servers.contains(entrypoint) {
+        return Err(From::from(format!("{} isn't cached", entrypoint)));
+    }
+    let remaining = servers.iter().filter(|x| *x != entrypoint).cloned().collect::<Vec<_>>();
+
+    // Marking fails e.g. if the System object of a remaining server isn't cached, so it runs before
+    // anything is deleted
+    let marked = mark(storage, &remaining)?;
+
+    let mut report = GcReport::default();
+    let server_dir = storage.url_to_path(entrypoint, "");
+    let status_file = server_dir.join(storage.get_cache_status_file());
+    if status_file.is_file() {
+        report.delete(&status_file, dry_run)?;
+    }
+    report.delete_dir(&server_dir.join(SYNC_REPORTS_DIR), dry_run)?;
+    report.delete_dir(&server_dir.join(SNAPSHOTS_DIR), dry_run)?;
+    for dir in host_dirs(storage)? {
+        sweep(storage, &dir, &marked, dry_run, &mut report)?;
+    }
+
+    if !dry_run {
+        storage.set_cached_servers(&remaining)?;
+    }
+    Ok(report)
+}
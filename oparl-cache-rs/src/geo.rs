@@ -0,0 +1,179 @@
+use std::error::Error;
+
+use json::JsonValue;
+use reqwest::{IntoUrl, Url};
+
+use refs::ReferenceIndex;
+
+/// The types whose references to locations are added to the features
+pub const REFERENCING_TYPES: [&'static str; 3] = ["Body", "Meeting", "Paper"];
+
+/// A rectangle in WGS 84 coordinates
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundingBox {
+    /// The western edge
+    pub min_lon: f64,
+    /// The southern edge
+    pub min_lat: f64,
+    /// The eastern edge
+    pub max_lon: f64,
+    /// The northern edge
+    pub max_lat: f64,
+}
+
+impl BoundingBox {
+    /// Parses `<min lon>,<min lat>,<max lon>,<max lat>` as used by GeoJSON's `bbox`
+    pub fn parse(bbox: &str) -> Result<BoundingBox, Box<Error>> {
+        let values = bbox.split(',').map(|x| x.trim().parse::<f64>()).collect::<Result<Vec<_>, _>>()?;
+        if values.len() != 4 || values[0] > values[2] || values[1] > values[3] {
+            return Err(From::from(format!("Invalid bounding box, expected min lon,min lat,max lon,max lat: {}",
+                                          bbox)));
+        }
+
+        Ok(BoundingBox {
+            min_lon: values[0],
+            min_lat: values[1],
+            max_lon: values[2],
+            max_lat: values[3],
+        })
+    }
+
+    /// Returns the bounding box of a GeoJSON feature or geometry, or `None` if it contains no
+    /// coordinates
+    pub fn of_geojson(geojson: &JsonValue) -> Option<BoundingBox> {
+        let mut bbox = None;
+        extend_bbox(geojson, &mut bbox);
+        bbox
+    }
+
+    /// Whether the boxes overlap, including touching edges
+    pub fn intersects(&self, other: &BoundingBox) -> bool {
+        self.min_lon <= other.max_lon && other.min_lon <= self.max_lon && self.min_lat <= other.max_lat &&
+        other.min_lat <= self.max_lat
+    }
+}
+
+/// Extends `bbox` with the coordinates in a feature, a geometry or a coordinate array
+fn extend_bbox(value: &JsonValue, bbox: &mut Option<BoundingBox>) {
+    if value.has_key("geometry") {
+        return extend_bbox(&value["geometry"], bbox);
+    }
+    if value.has_key("geometries") {
+        for geometry in value["geometries"].members() {
+            extend_bbox(geometry, bbox);
+        }
+        return;
+    }
+    if value.has_key("coordinates") {
+        return extend_bbox(&value["coordinates"], bbox);
+    }
+
+    // A position is an array of numbers, everything else is nested deeper
+    if let (Some(lon), Some(lat)) = (value[0].as_f64(), value[1].as_f64()) {
+        *bbox = Some(match *bbox {
+            Some(current) => {
+                BoundingBox {
+                    min_lon: current.min_lon.min(lon),
+                    min_lat: current.min_lat.min(lat),
+                    max_lon: current.max_lon.max(lon),
+                    max_lat: current.max_lat.max(lat),
+                }
+            }
+            None => {
+                BoundingBox {
+                    min_lon: lon,
+                    min_lat: lat,
+                    max_lon: lon,
+                    max_lat: lat,
+                }
+            }
+        });
+        return;
+    }
+
+    for member in value.members() {
+        extend_bbox(member, bbox);
+    }
+}
+
+/// Converts a location to a GeoJSON feature with the address and the objects referencing the
+/// location as properties. Returns `None` if the location has no geojson
+pub fn location_feature<S: ReferenceIndex>(storage: &S, location: &JsonValue) -> Result<Option<JsonValue>, Box<Error>> {
+    let geojson = &location["geojson"];
+    if !geojson.is_object() {
+        return Ok(None);
+    }
+
+    // The geojson is usually a feature, but a bare geometry is valid too
+    let (geometry, mut properties) = if geojson["type"] == "Feature" {
+        let properties = if geojson["properties"].is_object() {
+            geojson["properties"].clone()
+        } else {
+            JsonValue::new_object()
+        };
+        (geojson["geometry"].clone(), properties)
+    } else {
+        (geojson.clone(), JsonValue::new_object())
+    };
+
+    for &key in &["id", "description", "streetAddress", "room", "postalCode", "subLocality", "locality"] {
+        if !location[key].is_null() {
+            properties[key] = location[key].clone();
+        }
+    }
+
+    let mut referenced_by = JsonValue::new_array();
+    let id = location["id"].as_str().ok_or("The location has no id")?.into_url()?;
+    for reference in storage.get_references(&id)? {
+        if !REFERENCING_TYPES.contains(&reference.source_type.as_str()) {
+            continue;
+        }
+        let name = match storage.get(&reference.source.as_str().into_url()?) {
+            Ok(object) => object["name"].clone(),
+            Err(_) => JsonValue::Null,
+        };
+        referenced_by.push(object!{
+                "id" => reference.source.as_str(),
+                "type" => reference.source_type.as_str(),
+                "name" => name
+            })?;
+    }
+    properties["referencedBy"] = referenced_by;
+
+    Ok(Some(object!{
+        "type" => "Feature",
+        "id" => location["id"].clone(),
+        "geometry" => geometry,
+        "properties" => properties
+    }))
+}
+
+/// Collects the cached locations of a server with geojson as a FeatureCollection, optionally
+/// only those intersecting `bbox`
+pub fn export_geojson<S: ReferenceIndex>(storage: &S,
+                                         entrypoint: &Url,
+                                         bbox: Option<&BoundingBox>)
+                                         -> Result<JsonValue, Box<Error>> {
+    let mut features = JsonValue::new_array();
+
+    for object in storage.iter_objects(entrypoint, Some("Location")) {
+        let feature = match location_feature(storage, &object?)? {
+            Some(feature) => feature,
+            None => continue,
+        };
+
+        if let Some(bbox) = bbox {
+            match BoundingBox::of_geojson(&feature) {
+                Some(ref extent) if bbox.intersects(extent) => {}
+                _ => continue,
+            }
+        }
+
+        features.push(feature)?;
+    }
+
+    Ok(object!{
+        "type" => "FeatureCollection",
+        "features" => features
+    })
+}
@@ -0,0 +1,705 @@
+//! A GraphQL endpoint for the cached objects
+//!
+//! Only the query subset of GraphQL is implemented: selections with aliases, arguments and
+//! variables. Fragments, directives and mutations aren't supported. The fields of the types are
+//! taken from the OParl schema. References and embedded objects are resolved with
+//! `Storage::get` if a selection is given, otherwise their id is returned. External lists are
+//! connections with the `first` and `after` arguments. Queries are limited to `MAX_DEPTH` levels
+//! and `MAX_LOADED_OBJECTS` objects, e.g.
+//!
+//! ```graphql
+//! {
+//!   meeting(id: "https://example.com/meeting/1") {
+//!     name
+//!     agendaItem { name consultation { paper { name auxiliaryFile { accessUrl } } } }
+//!   }
+//!   body(id: "https://example.com/body/1") {
+//!     paper(first: 10) { totalCount nodes { name } pageInfo { hasNextPage endCursor } }
+//!   }
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Write;
+
+use json::JsonValue;
+use reqwest::IntoUrl;
+
+use object_filter::list_item_type;
+use schema::{split_type_url, is_external_list};
+use storage::Storage;
+
+/// The number of list items returned if `first` isn't given
+pub const DEFAULT_PAGE_SIZE: usize = 20;
+
+/// The maximum value of `first`
+pub const MAX_PAGE_SIZE: usize = 100;
+
+/// The maximum nesting of selections in a query
+pub const MAX_DEPTH: usize = 10;
+
+/// The maximum number of objects a query may load from the cache
+pub const MAX_LOADED_OBJECTS: usize = 10000;
+
+/// A value in a query
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Variable(String),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Boolean(bool),
+    Null,
+    Enum(String),
+    List(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+/// A field of a selection set
+#[derive(Clone, Debug, PartialEq)]
+struct Field {
+    alias: Option<String>,
+    name: String,
+    arguments: Vec<(String, Value)>,
+    selection: Vec<Field>,
+}
+
+impl Field {
+    fn response_key(&self) -> &str {
+        self.alias.as_ref().unwrap_or(&self.name)
+    }
+}
+
+/// Fails once the selections, values or types are nested deeper than `MAX_DEPTH`. This is checked
+/// while parsing, as the recursion of the parser would otherwise overflow the stack on a
+/// malicious query
+fn check_depth(depth: usize) -> Result<(), Box<Error>> {
+    if depth > MAX_DEPTH {
+        return Err(From::from(format!("The query is nested deeper than {} levels", MAX_DEPTH)));
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Punctuator(char),
+    Spread,
+    Name(String),
+    Int(i64),
+    Float(f64),
+    String(String),
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>, Box<Error>> {
+    let chars: Vec<char> = query.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() || c == ',' || c == '\u{feff}' {
+            i += 1;
+        } else if c == '#' {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if "{}():!$=[]@".contains(c) {
+            tokens.push(Token::Punctuator(c));
+            i += 1;
+        } else if c == '.' {
+            if chars[i..].starts_with(&['.', '.', '.']) {
+                tokens.push(Token::Spread);
+                i += 3;
+            } else {
+                return Err(From::from("Unexpected ."));
+            }
+        } else if c == '"' {
+            let mut string = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None | Some(&'\n') => return Err(From::from("Unterminated string")),
+                    Some(&'"') => break,
+                    Some(&'\\') => {
+                        let escaped = match chars.get(i + 1) {
+                            Some(&'n') => '\n',
+                            Some(&'t') => '\t',
+                            Some(&'r') => '\r',
+                            Some(&'b') => '\u{8}',
+                            Some(&'f') => '\u{c}',
+                            Some(&'u') => {
+                                let hex: String = chars.iter().skip(i + 2).take(4).collect();
+                                let code = u32::from_str_radix(&hex, 16)?;
+                                i += 4;
+                                ::std::char::from_u32(code).ok_or("Invalid unicode escape")?
+                            }
+                            Some(&x) => x,
+                            None => return Err(From::from("Unterminated string")),
+                        };
+                        string.push(escaped);
+                        i += 2;
+                    }
+                    Some(&x) => {
+                        string.push(x);
+                        i += 1;
+                    }
+                }
+            }
+            tokens.push(Token::String(string));
+            i += 1;
+        } else if c == '-' || c.is_digit(10) {
+            let start = i;
+            i += 1;
+            while i < chars.len() && (chars[i].is_digit(10) || "+-.eE".contains(chars[i])) {
+                i += 1;
+            }
+            let number: String = chars[start..i].iter().collect();
+            if number.contains(|x: char| x == '.' || x == 'e' || x == 'E') {
+                tokens.push(Token::Float(number.parse()?));
+            } else {
+                tokens.push(Token::Int(number.parse()?));
+            }
+        } else if c == '_' || c.is_alphabetic() {
+            let start = i;
+            while i < chars.len() && (chars[i] == '_' || chars[i].is_alphanumeric()) {
+                i += 1;
+            }
+            tokens.push(Token::Name(chars[start..i].iter().collect()));
+        } else {
+            return Err(From::from(format!("Unexpected character {:?}", c)));
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// A recursive descent parser for the query subset
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<Token, Box<Error>> {
+        let token = self.tokens.get(self.position).cloned().ok_or("Unexpected end of the query")?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn is_punctuator(&self, punctuator: char) -> bool {
+        self.peek() == Some(&Token::Punctuator(punctuator))
+    }
+
+    fn expect(&mut self, punctuator: char) -> Result<(), Box<Error>> {
+        match self.next()? {
+            Token::Punctuator(x) if x == punctuator => Ok(()),
+            other => Err(From::from(format!("Expected {}, found {:?}", punctuator, other))),
+        }
+    }
+
+    fn name(&mut self) -> Result<String, Box<Error>> {
+        match self.next()? {
+            Token::Name(name) => Ok(name),
+            other => Err(From::from(format!("Expected a name, found {:?}", other))),
+        }
+    }
+
+    /// Parses a document with a single query. Returns the selection and the default values of
+    /// the variables
+    fn document(&mut self) -> Result<(Vec<Field>, BTreeMap<String, Value>), Box<Error>> {
+        let mut defaults = BTreeMap::new();
+
+        if let Some(Token::Name(keyword)) = self.peek().cloned() {
+            if keyword != "query" {
+                return Err(From::from(format!("Only queries are supported, found {}", keyword)));
+            }
+            self.position += 1;
+            if let Some(&Token::Name(_)) = self.peek() {
+                self.position += 1;
+            }
+            if self.is_punctuator('(') {
+                self.position += 1;
+                while !self.is_punctuator(')') {
+                    self.expect('$')?;
+                    let name = self.name()?;
+                    self.expect(':')?;
+                    self.skip_type(1)?;
+                    if self.is_punctuator('=') {
+                        self.position += 1;
+                        defaults.insert(name, self.value(1)?);
+                    }
+                }
+                self.expect(')')?;
+            }
+        }
+
+        let selection = self.selection_set(1)?;
+        if let Some(token) = self.peek() {
+            return Err(From::from(format!("Only a single operation is supported, found {:?}", token)));
+        }
+
+        Ok((selection, defaults))
+    }
+
+    /// Skips a type such as `[String!]!`, as the variables are not type checked
+    fn skip_type(&mut self, depth: usize) -> Result<(), Box<Error>> {
+        check_depth(depth)?;
+        if self.is_punctuator('[') {
+            self.position += 1;
+            self.skip_type(depth + 1)?;
+            self.expect(']')?;
+        } else {
+            self.name()?;
+        }
+        if self.is_punctuator('!') {
+            self.position += 1;
+        }
+        Ok(())
+    }
+
+    /// Parses a selection set whose fields are at the level `depth`, where the top level is 1
+    fn selection_set(&mut self, depth: usize) -> Result<Vec<Field>, Box<Error>> {
+        // Without a limit, a single query could walk the whole reference graph
+        check_depth(depth)?;
+        self.expect('{')?;
+        let mut fields = vec![];
+        while !self.is_punctuator('}') {
+            match self.peek() {
+                Some(&Token::Spread) => return Err(From::from("Fragments are not supported")),
+                Some(&Token::Punctuator('@')) => return Err(From::from("Directives are not supported")),
+                _ => fields.push(self.field(depth)?),
+            }
+        }
+        self.expect('}')?;
+        Ok(fields)
+    }
+
+    fn field(&mut self, depth: usize) -> Result<Field, Box<Error>> {
+        let mut name = self.name()?;
+        let mut alias = None;
+        if self.is_punctuator(':') {
+            self.position += 1;
+            alias = Some(name);
+            name = self.name()?;
+        }
+
+        let mut arguments = vec![];
+        if self.is_punctuator('(') {
+            self.position += 1;
+            while !self.is_punctuator(')') {
+                let argument = self.name()?;
+                self.expect(':')?;
+                arguments.push((argument, self.value(1)?));
+            }
+            self.expect(')')?;
+        }
+
+        let selection = if self.is_punctuator('{') {
+            self.selection_set(depth + 1)?
+        } else {
+            vec![]
+        };
+
+        Ok(Field {
+            alias: alias,
+            name: name,
+            arguments: arguments,
+            selection: selection,
+        })
+    }
+
+    /// Parses a value nested `depth` levels deep in lists and objects
+    fn value(&mut self, depth: usize) -> Result<Value, Box<Error>> {
+        check_depth(depth)?;
+        Ok(match self.next()? {
+            Token::Punctuator('$') => Value::Variable(self.name()?),
+            Token::Int(x) => Value::Int(x),
+            Token::Float(x) => Value::Float(x),
+            Token::String(x) => Value::String(x),
+            Token::Name(ref x) if x == "true" => Value::Boolean(true),
+            Token::Name(ref x) if x == "false" => Value::Boolean(false),
+            Token::Name(ref x) if x == "null" => Value::Null,
+            Token::Name(x) => Value::Enum(x),
+            Token::Punctuator('[') => {
+                let mut values = vec![];
+                while !self.is_punctuator(']') {
+                    values.push(self.value(depth + 1)?);
+                }
+                self.expect(']')?;
+                Value::List(values)
+            }
+            Token::Punctuator('{') => {
+                let mut fields = vec![];
+                while !self.is_punctuator('}') {
+                    let name = self.name()?;
+                    self.expect(':')?;
+                    fields.push((name, self.value(depth + 1)?));
+                }
+                self.expect('}')?;
+                Value::Object(fields)
+            }
+            other => return Err(From::from(format!("Expected a value, found {:?}", other))),
+        })
+    }
+}
+
+/// Executes queries against a storage
+struct Executor<'a, S: 'a + Storage> {
+    storage: &'a S,
+    variables: BTreeMap<String, JsonValue>,
+    errors: Vec<String>,
+    /// The number of objects loaded so far, limited to `MAX_LOADED_OBJECTS`
+    loaded: usize,
+}
+
+impl<'a, S: 'a + Storage> Executor<'a, S> {
+    fn argument(&self, field: &Field, name: &str) -> JsonValue {
+        field.arguments
+            .iter()
+            .find(|&&(ref x, _)| x == name)
+            .map(|&(_, ref value)| self.to_json(value))
+            .unwrap_or(JsonValue::Null)
+    }
+
+    fn to_json(&self, value: &Value) -> JsonValue {
+        match *value {
+            Value::Variable(ref name) => self.variables.get(name).cloned().unwrap_or(JsonValue::Null),
+            Value::Int(x) => x.into(),
+            Value::Float(x) => x.into(),
+            Value::String(ref x) | Value::Enum(ref x) => x.as_str().into(),
+            Value::Boolean(x) => x.into(),
+            Value::Null => JsonValue::Null,
+            Value::List(ref values) => JsonValue::Array(values.iter().map(|x| self.to_json(x)).collect()),
+            Value::Object(ref fields) => {
+                let mut object = JsonValue::new_object();
+                for &(ref name, ref value) in fields {
+                    object[name.as_str()] = self.to_json(value);
+                }
+                object
+            }
+        }
+    }
+
+    fn query(&mut self, selection: &[Field]) -> JsonValue {
+        let mut data = JsonValue::new_object();
+        for field in selection {
+            let value = if field.name == "__typename" {
+                "Query".into()
+            } else {
+                self.root_field(field)
+            };
+            data[field.response_key()] = value;
+        }
+        data
+    }
+
+    /// Root fields are named like the types with a lowercase first letter and take an `id`
+    fn root_field(&mut self, field: &Field) -> JsonValue {
+        let oparl_type = upper_first(&field.name);
+        let storage = self.storage;
+        let known = storage.get_schema().entries().any(|(_, types)| types.has_key(&oparl_type));
+        if !known {
+            self.errors.push(format!("Unknown field {} on Query", field.name));
+            return JsonValue::Null;
+        }
+
+        let id = match self.argument(field, "id").as_str() {
+            Some(id) => id.to_string(),
+            None => {
+                self.errors.push(format!("{} requires the argument id", field.name));
+                return JsonValue::Null;
+            }
+        };
+
+        let object = match self.load(&id) {
+            Some(object) => object,
+            None => return JsonValue::Null,
+        };
+
+        match split_type_url(&object["type"].to_string()) {
+            Ok((_, ref actual)) if *actual == oparl_type => self.object(&object, &field.selection),
+            _ => {
+                self.errors.push(format!("{} is a {}, not a {}", id, object["type"], oparl_type));
+                JsonValue::Null
+            }
+        }
+    }
+
+    fn load(&mut self, id: &str) -> Option<JsonValue> {
+        if self.loaded >= MAX_LOADED_OBJECTS {
+            let error = format!("The query loads more than {} objects", MAX_LOADED_OBJECTS);
+            if !self.errors.contains(&error) {
+                self.errors.push(error);
+            }
+            return None;
+        }
+        self.loaded += 1;
+
+        let url = match id.into_url() {
+            Ok(url) => url,
+            Err(err) => {
+                self.errors.push(format!("{} is not a valid url: {}", id, err));
+                return None;
+            }
+        };
+
+        match self.storage.get(&url) {
+            Ok(object) => Some(object),
+            Err(err) => {
+                self.errors.push(format!("{} is not cached: {}", id, err));
+                None
+            }
+        }
+    }
+
+    fn object(&mut self, object: &JsonValue, selection: &[Field]) -> JsonValue {
+        let (version, oparl_type) = match split_type_url(&object["type"].to_string()) {
+            Ok(ok) => ok,
+            Err(err) => {
+                self.errors.push(format!("Invalid type of {}: {}", object["id"], err));
+                return JsonValue::Null;
+            }
+        };
+
+        if selection.is_empty() {
+            self.errors.push(format!("A selection is required for {}", oparl_type));
+            return JsonValue::Null;
+        }
+
+        let storage = self.storage;
+        let properties = &storage.get_schema()[&version][&oparl_type]["properties"];
+        let mut result = JsonValue::new_object();
+
+        for field in selection {
+            let definition = &properties[&field.name];
+            let value = if field.name == "__typename" {
+                oparl_type.as_str().into()
+            } else if definition.is_null() && !object.has_key(&field.name) {
+                self.errors.push(format!("Unknown field {} on {}", field.name, oparl_type));
+                JsonValue::Null
+            } else if is_external_list(definition) {
+                self.connection(&object[&field.name], field)
+            } else {
+                self.value(&object[&field.name], definition, &field.selection)
+            };
+            result[field.response_key()] = value;
+        }
+
+        result
+    }
+
+    fn value(&mut self, value: &JsonValue, definition: &JsonValue, selection: &[Field]) -> JsonValue {
+        if value.is_null() {
+            return JsonValue::Null;
+        }
+
+        if definition["type"] == "array" {
+            let members = value.members().map(|x| self.value(x, &definition["items"], selection)).collect();
+            return JsonValue::Array(members);
+        }
+
+        let is_reference = definition["type"] == "object" || definition["references"].is_string();
+        match value.as_str() {
+            Some(id) if is_reference && !selection.is_empty() => {
+                match self.load(id) {
+                    Some(object) => self.object(&object, selection),
+                    None => JsonValue::Null,
+                }
+            }
+            _ => value.clone(),
+        }
+    }
+
+    /// Resolves an external list with the `first` and `after` arguments. The cursors are the
+    /// offsets into the cached list
+    fn connection(&mut self, url: &JsonValue, field: &Field) -> JsonValue {
+        let ids = match url.as_str().and_then(|x| self.load(x)) {
+            Some(ids) => ids,
+            None => return JsonValue::Null,
+        };
+
+        let first = self.argument(field, "first").as_usize().unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+        let start = match self.argument(field, "after") {
+            JsonValue::Null => 0,
+            after => after.as_str().and_then(|x| x.parse().ok()).unwrap_or(ids.len()),
+        };
+        let start = start.min(ids.len());
+        let end = start.saturating_add(first).min(ids.len());
+
+        let mut result = JsonValue::new_object();
+        for subfield in &field.selection {
+            let value = match subfield.name.as_str() {
+                "__typename" => "Connection".into(),
+                "totalCount" => ids.len().into(),
+                "nodes" => {
+                    let nodes = (start..end)
+                        .map(|i| match self.load(&ids[i].to_string()) {
+                            Some(object) => self.object(&object, &subfield.selection),
+                            None => JsonValue::Null,
+                        })
+                        .collect();
+                    JsonValue::Array(nodes)
+                }
+                "pageInfo" => {
+                    object!{
+                        "hasNextPage" => end < ids.len(),
+                        "endCursor" => end.to_string()
+                    }
+                }
+                other => {
+                    self.errors.push(format!("Unknown field {} on Connection", other));
+                    JsonValue::Null
+                }
+            };
+            result[subfield.response_key()] = value;
+        }
+
+        result
+    }
+}
+
+/// Executes `query` with `variables` (a json object or null) against the cache and returns the
+/// GraphQL response with `data` and, if there were any, `errors`
+pub fn execute<S: Storage>(storage: &S, query: &str, variables: &JsonValue) -> JsonValue {
+    let parsed = tokenize(query).and_then(|tokens| Parser { tokens: tokens, position: 0 }.document());
+    let (selection, defaults) = match parsed {
+        Ok(ok) => ok,
+        Err(err) => return object!{ "errors" => array![object!{ "message" => err.to_string() }] },
+    };
+
+    let mut executor = Executor {
+        storage: storage,
+        variables: BTreeMap::new(),
+        errors: vec![],
+        loaded: 0,
+    };
+    for (name, value) in defaults {
+        let value = executor.to_json(&value);
+        executor.variables.insert(name, value);
+    }
+    for (name, value) in variables.entries() {
+        executor.variables.insert(name.to_string(), value.clone());
+    }
+
+    let data = executor.query(&selection);
+    let mut response = object!{ "data" => data };
+    if !executor.errors.is_empty() {
+        response["errors"] = executor.errors
+            .iter()
+            .map(|x| object!{ "message" => x.as_str() })
+            .collect::<Vec<_>>()
+            .into();
+    }
+    response
+}
+
+/// Renders the GraphQL schema derived from the OParl schema in the schema definition language.
+/// Types that exist in several OParl versions get the properties of all versions
+pub fn render_schema(schema: &JsonValue) -> String {
+    let mut types: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+    let mut lists = vec![];
+
+    for (_, version_types) in schema.entries() {
+        for (oparl_type, type_schema) in version_types.entries() {
+            let fields = types.entry(oparl_type.to_string()).or_insert_with(BTreeMap::new);
+            for (property, definition) in type_schema["properties"].entries() {
+                let field_type = if is_external_list(definition) {
+                    let item_type = list_item_type(property);
+                    if !lists.contains(&item_type) {
+                        lists.push(item_type.clone());
+                    }
+                    format!("{}Connection(first: Int, after: String)", item_type)
+                } else {
+                    graphql_type(definition)
+                };
+                fields.insert(property.to_string(), field_type);
+            }
+        }
+    }
+
+    let mut output = String::new();
+    writeln!(output, "scalar JSON\n").unwrap();
+
+    writeln!(output, "type Query {{").unwrap();
+    for oparl_type in types.keys() {
+        writeln!(output, "  {}(id: ID!): {}", lower_first(oparl_type), oparl_type).unwrap();
+    }
+    writeln!(output, "}}\n").unwrap();
+
+    writeln!(output, "type PageInfo {{\n  hasNextPage: Boolean!\n  endCursor: String\n}}\n").unwrap();
+
+    lists.sort();
+    for item_type in &lists {
+        writeln!(output,
+                 "type {}Connection {{\n  totalCount: Int!\n  nodes: [{}]\n  pageInfo: PageInfo!\n}}\n",
+                 item_type,
+                 item_type)
+            .unwrap();
+    }
+
+    for (oparl_type, fields) in &types {
+        writeln!(output, "type {} {{", oparl_type).unwrap();
+        for (property, field_type) in fields {
+            // Field arguments are written directly after the name
+            match field_type.find('(') {
+                Some(position) => {
+                    writeln!(output,
+                             "  {}{}: {}",
+                             property,
+                             &field_type[position..],
+                             &field_type[..position])
+                        .unwrap()
+                }
+                None => writeln!(output, "  {}: {}", property, field_type).unwrap(),
+            }
+        }
+        writeln!(output, "}}\n").unwrap();
+    }
+
+    output
+}
+
+/// Maps the json schema of a property to a GraphQL type
+fn graphql_type(definition: &JsonValue) -> String {
+    if let Some(references) = definition["references"].as_str() {
+        return references.to_string();
+    }
+
+    match definition["type"].as_str() {
+        Some("array") => format!("[{}]", graphql_type(&definition["items"])),
+        Some("object") => {
+            // Embedded objects link to their schema file, e.g. `File.json`
+            definition["schema"]
+                .as_str()
+                .or(definition["$ref"].as_str())
+                .and_then(|x| x.rsplit('/').next())
+                .map(|x| x.trim_right_matches(".json").to_string())
+                .unwrap_or_else(|| "JSON".to_string())
+        }
+        Some("integer") => "Int".to_string(),
+        Some("number") => "Float".to_string(),
+        Some("boolean") => "Boolean".to_string(),
+        _ => "String".to_string(),
+    }
+}
+
+fn upper_first(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn lower_first(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_lowercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
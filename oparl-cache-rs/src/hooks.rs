@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use json::JsonValue;
+use reqwest;
+use reqwest::IntoUrl;
+use reqwest::Url;
+use reqwest::header::ContentType;
+
+use serde_json;
+
+use sync_report::SyncReport;
+
+/// The seconds a webhook may take to respond before the sync continues without it
+pub const WEBHOOK_TIMEOUT_SECS: u64 = 30;
+
+/// The seconds a command may run by default before it is killed and the sync continues
+pub const COMMAND_TIMEOUT_SECS: u64 = 300;
+
+/// The events a hook can be triggered by
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum HookEvent {
+    /// A sync run of the server has finished, regardless of its outcome
+    SyncFinished,
+    /// Objects of the watched types, including embedded ones, were added or differ from their
+    /// cached version after a sync run
+    ObjectsChanged,
+}
+
+/// What to do when a hook is triggered
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum HookAction {
+    /// POST a json payload with the event and the ids of the changed objects
+    Webhook {
+        /// The url receiving the payload
+        url: Url,
+    },
+    /// Run a program with the ids of the changed objects on stdin, one per line
+    ///
+    /// The event and the entrypoint are passed as `OPARL_CACHE_EVENT` and
+    /// `OPARL_CACHE_ENTRYPOINT` environment variables
+    Command {
+        /// The program to run
+        program: String,
+        /// The arguments for the program
+        #[serde(default)]
+        args: Vec<String>,
+        /// The seconds the program may run before it is killed, `COMMAND_TIMEOUT_SECS` by default
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timeout_secs: Option<u64>,
+    },
+}
+
+/// An action that is triggered by sync events of a server
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Hook {
+    /// The event triggering the hook
+    pub event: HookEvent,
+    /// The types that are watched, e.g. `Paper`. An empty list watches all types
+    #[serde(default)]
+    pub types: Vec<String>,
+    /// What to do
+    pub action: HookAction,
+}
+
+impl Hook {
+    /// Returns the ids of the changed objects that are relevant for this hook
+    fn changed_ids(&self, report: &SyncReport) -> Vec<String> {
+        report.lists
+            .iter()
+            .flat_map(|x| x.changed.iter())
+            .filter(|x| self.types.is_empty() || self.types.contains(&x.oparl_type))
+            .map(|x| x.id.clone())
+            .collect()
+    }
+
+    fn event_name(&self) -> &'static str {
+        match self.event {
+            HookEvent::SyncFinished => "sync_finished",
+            HookEvent::ObjectsChanged => "objects_changed",
+        }
+    }
+}
+
+/// Loads a json file mapping the entrypoints to their hooks, e.g.
+///
+/// ```json
+/// {
+///   "https://example.com/oparl/v1.0": [
+///     {"event": "objects_changed", "types": ["Paper"],
+///      "action": {"kind": "webhook", "url": "https://example.org/notify"}}
+///   ]
+/// }
+/// ```
+pub fn load_hooks(path: &Path) -> Result<HashMap<Url, Vec<Hook>>, Box<Error>> {
+    let hooks: HashMap<String, Vec<Hook>> = serde_json::from_reader(File::open(path)?)?;
+
+    let mut by_entrypoint = HashMap::new();
+    for (entrypoint, hooks) in hooks {
+        by_entrypoint.insert(entrypoint.into_url()?, hooks);
+    }
+
+    Ok(by_entrypoint)
+}
+
+/// Triggers the hooks for a finished sync run described by `report`. Failing hooks don't stop the
+/// others, their errors are returned instead
+pub fn run_hooks(hooks: &[Hook], report: &SyncReport) -> Vec<String> {
+    let mut errors = vec![];
+
+    for hook in hooks {
+        let ids = hook.changed_ids(report);
+        if hook.event == HookEvent::ObjectsChanged && ids.is_empty() {
+            continue;
+        }
+
+        let result = match hook.action {
+            HookAction::Webhook { ref url } => post_webhook(url, hook, report, &ids),
+            HookAction::Command { ref program, ref args, timeout_secs } => {
+                let timeout = Duration::from_secs(timeout_secs.unwrap_or(COMMAND_TIMEOUT_SECS));
+                run_command(program, args, timeout, hook, report, &ids)
+            }
+        };
+
+        if let Err(err) = result {
+            errors.push(format!("The {} hook {:?} failed: {}", hook.event_name(), hook.action, err));
+        }
+    }
+
+    errors
+}
+
+fn post_webhook(url: &Url, hook: &Hook, report: &SyncReport, ids: &[String]) -> Result<(), Box<Error>> {
+    let payload = object!{
+        "event" => hook.event_name(),
+        "entrypoint" => report.entrypoint.as_str(),
+        "started" => report.started.clone(),
+        "ids" => ids.iter().map(|x| JsonValue::from(x.as_str())).collect::<Vec<_>>()
+    };
+
+    let client = reqwest::Client::builder()?.timeout(Duration::from_secs(WEBHOOK_TIMEOUT_SECS)).build()?;
+    let response = client.post(url.clone())
+        .header(ContentType::json())
+        .body(payload.dump())
+        .send()?;
+
+    if !response.status().is_success() {
+        return Err(From::from(format!("Bad status code returned for request: {}", response.status())));
+    }
+
+    Ok(())
+}
+
+/// Waits for the child to exit. Returns `None` if it still runs after `timeout`
+fn wait_timeout(child: &mut Child, timeout: Duration) -> io::Result<Option<ExitStatus>> {
+    let started = Instant::now();
+    while started.elapsed() < timeout {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    child.try_wait()
+}
+
+/// Runs the command and kills it after `timeout`. The child is always waited for, so that no
+/// zombie process is left behind
+fn run_command(program: &str,
+               args: &[String],
+               timeout: Duration,
+               hook: &Hook,
+               report: &SyncReport,
+               ids: &[String])
+               -> Result<(), Box<Error>> {
+    let mut child = Command::new(program).args(args)
+        .env("OPARL_CACHE_EVENT", hook.event_name())
+        .env("OPARL_CACHE_ENTRYPOINT", report.entrypoint.as_str())
+        .stdin(Stdio::piped())
+        .spawn()?;
+
+    // The ids are written from another thread, as a program that doesn't read them would block
+    // the write once the pipe is full. Dropping stdin at the end closes it, so that the program
+    // sees the end
+    let mut stdin = match child.stdin.take() {
+        Some(stdin) => stdin,
+        None => {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(From::from("Could not open stdin of the hook"));
+        }
+    };
+    let lines = ids.to_vec();
+    let writer = thread::spawn(move || -> Result<(), String> {
+        for id in lines {
+            writeln!(stdin, "{}", id).map_err(|err| err.to_string())?;
+        }
+        Ok(())
+    });
+
+    let status = match wait_timeout(&mut child, timeout) {
+        Ok(Some(status)) => status,
+        result => {
+            // The writer isn't joined, as it may block if a child of the program keeps stdin open
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(match result {
+                Err(err) => From::from(err),
+                _ => From::from(format!("The command was killed after {} seconds", timeout.as_secs())),
+            });
+        }
+    };
+
+    if !status.success() {
+        return Err(From::from(format!("The command exited with {}", status)));
+    }
+    match writer.join() {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(err)) => Err(From::from(format!("Could not write the ids to the command: {}", err))),
+        Err(_) => Err(From::from("Could not write the ids to the command")),
+    }
+}
@@ -0,0 +1,31 @@
+use hyper::header::ContentType;
+use hyper::server::{Request, Response};
+use hyper::status::StatusCode;
+use hyper::uri::RequestUri;
+use reqwest::Url;
+
+/// Returns the requested url resolved against `http://localhost`, so that path and query can
+/// be accessed conveniently
+pub fn request_url(request: &Request) -> Option<Url> {
+    match request.uri {
+        RequestUri::AbsolutePath(ref path) => {
+            Url::parse("http://localhost").and_then(|x| x.join(path)).ok()
+        }
+        RequestUri::AbsoluteUri(ref url) => Some(url.clone()),
+        _ => None,
+    }
+}
+
+/// Sends `body` with the given status code and content type
+pub fn respond(mut response: Response, status: StatusCode, content_type: ContentType, body: &[u8]) {
+    *response.status_mut() = status;
+    response.headers_mut().set(content_type);
+    if let Err(err) = response.send(body) {
+        println!("Failed to send a response: {}", err);
+    }
+}
+
+/// Sends a plain text 404
+pub fn not_found(response: Response) {
+    respond(response, StatusCode::NotFound, ContentType::plaintext(), b"Not Found\n");
+}
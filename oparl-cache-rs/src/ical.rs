@@ -0,0 +1,209 @@
+use std::error::Error;
+use std::fs::{File, create_dir_all};
+use std::io::{Read, Write};
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use json::JsonValue;
+use reqwest::{IntoUrl, Url};
+
+use refs::ReferenceIndex;
+use schema::split_type_url;
+
+/// The file extension of the exported calendars
+pub const CALENDAR_EXTENSION: &'static str = ".ics";
+
+/// Converts an RFC 3339 timestamp to the UTC form of iCalendar, e.g. `20170101T090000Z`
+fn ical_time(timestamp: &str) -> Option<String> {
+    DateTime::parse_from_rfc3339(timestamp)
+        .ok()
+        .map(|x| x.with_timezone(&Utc).format("%Y%m%dT%H%M%SZ").to_string())
+}
+
+/// Escapes a text value as required by RFC 5545
+fn escape_text(text: &str) -> String {
+    text.replace("\\", "\\\\")
+        .replace(";", "\\;")
+        .replace(",", "\\,")
+        .replace("\r\n", "\\n")
+        .replace("\n", "\\n")
+}
+
+/// Writes a content line, folding it after 75 octets
+fn content_line(output: &mut String, name: &str, value: &str) {
+    let line = format!("{}:{}", name, value);
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            output.push_str("\r\n ");
+            length = 1;
+        }
+        output.push(c);
+        length += c.len_utf8();
+    }
+    output.push_str("\r\n");
+}
+
+/// Loads an object referenced by id, ignoring objects that aren't cached
+fn load<S: ReferenceIndex>(storage: &S, id: &str) -> Option<JsonValue> {
+    id.into_url().ok().and_then(|x| storage.get(&x).ok())
+}
+
+/// Converts a meeting to a `VEVENT`. Returns `None` for meetings without a start. The `DTSTAMP`
+/// is `modified`, `created` or the start, in this order
+pub fn meeting_event<S: ReferenceIndex>(storage: &S, meeting: &JsonValue) -> Option<String> {
+    let start = match meeting["start"].as_str().and_then(ical_time) {
+        Some(start) => start,
+        None => return None,
+    };
+    // Falls back to the start, so that unchanged meetings always render the same event
+    let stamp = meeting["modified"]
+        .as_str()
+        .or(meeting["created"].as_str())
+        .and_then(ical_time)
+        .unwrap_or_else(|| start.clone());
+
+    let mut event = String::new();
+    content_line(&mut event, "BEGIN", "VEVENT");
+    content_line(&mut event, "UID", &escape_text(&meeting["id"].to_string()));
+    content_line(&mut event, "DTSTAMP", &stamp);
+    content_line(&mut event, "DTSTART", &start);
+    if let Some(end) = meeting["end"].as_str().and_then(ical_time) {
+        content_line(&mut event, "DTEND", &end);
+    }
+    if let Some(modified) = meeting["modified"].as_str().and_then(ical_time) {
+        content_line(&mut event, "LAST-MODIFIED", &modified);
+    }
+    content_line(&mut event, "SUMMARY", &escape_text(meeting["name"].as_str().unwrap_or("Meeting")));
+
+    if let Some(location) = meeting["location"].as_str().and_then(|x| load(storage, x)) {
+        let parts = ["description", "room", "streetAddress", "postalCode", "locality"]
+            .iter()
+            .filter_map(|&x| location[x].as_str())
+            .collect::<Vec<_>>();
+        if !parts.is_empty() {
+            content_line(&mut event, "LOCATION", &escape_text(&parts.join(", ")));
+        }
+    }
+
+    let cancelled = meeting["cancelled"].as_bool().unwrap_or(false);
+    content_line(&mut event, "STATUS", if cancelled { "CANCELLED" } else { "CONFIRMED" });
+
+    let agenda = meeting["agendaItem"]
+        .members()
+        .filter_map(|x| x.as_str().and_then(|x| load(storage, x)))
+        .map(|item| {
+            match (item["number"].as_str(), item["name"].as_str()) {
+                (Some(number), Some(name)) => format!("{} {}", number, name),
+                (None, Some(name)) => name.to_string(),
+                (Some(number), None) => number.to_string(),
+                (None, None) => String::new(),
+            }
+        })
+        .filter(|x| !x.is_empty())
+        .collect::<Vec<_>>();
+    if !agenda.is_empty() {
+        content_line(&mut event, "DESCRIPTION", &escape_text(&agenda.join("\n")));
+    }
+
+    if let Some(web) = meeting["web"].as_str() {
+        content_line(&mut event, "URL", web);
+    }
+    content_line(&mut event, "END", "VEVENT");
+
+    Some(event)
+}
+
+/// Returns the ids of the cached meetings of a body or an organization. For a body these are the
+/// items of its `meeting` list, for an organization the meetings referencing it
+pub fn calendar_meetings<S: ReferenceIndex>(storage: &S, object: &JsonValue) -> Result<Vec<String>, Box<Error>> {
+    let (_, oparl_type) = split_type_url(&object["type"].to_string())?;
+    match oparl_type.as_str() {
+        "Body" => {
+            Ok(object["meeting"]
+                .as_str()
+                .and_then(|x| load(storage, x))
+                .map(|ids| ids.members().map(|x| x.to_string()).collect())
+                .unwrap_or_default())
+        }
+        "Organization" => storage.get_referencing(&object["id"].to_string().into_url()?, "Meeting"),
+        other => Err(From::from(format!("There are no calendars for the type {}", other))),
+    }
+}
+
+/// Renders the calendar of a body or an organization
+pub fn calendar<S: ReferenceIndex>(storage: &S, object: &JsonValue) -> Result<String, Box<Error>> {
+    let mut output = String::new();
+    content_line(&mut output, "BEGIN", "VCALENDAR");
+    content_line(&mut output, "VERSION", "2.0");
+    content_line(&mut output, "PRODID", "-//oparl-cache-rs//OParl Cache//EN");
+    content_line(&mut output, "CALSCALE", "GREGORIAN");
+    if let Some(name) = object["name"].as_str() {
+        content_line(&mut output, "X-WR-CALNAME", &escape_text(name));
+    }
+
+    for id in calendar_meetings(storage, object)? {
+        let meeting = match load(storage, &id) {
+            Some(meeting) => meeting,
+            None => continue,
+        };
+        if let Some(event) = meeting_event(storage, &meeting) {
+            output.push_str(&event);
+        }
+    }
+
+    content_line(&mut output, "END", "VCALENDAR");
+    Ok(output)
+}
+
+/// Turns a url into a file name without the extension, e.g. `oparl-v1-0-body-1` for
+/// `https://example.com/oparl/v1.0/body/1`
+pub fn url_slug(url: &Url) -> String {
+    let mut name = url.path()
+        .trim_matches('/')
+        .chars()
+        .map(|x| if x.is_alphanumeric() { x } else { '-' })
+        .collect::<String>()
+        .to_lowercase();
+    if let Some(query) = url.query() {
+        name.push('-');
+        name.extend(query.chars().map(|x| if x.is_alphanumeric() { x } else { '-' }));
+    }
+    name
+}
+
+/// Returns the file name of the calendar of an object, e.g. `oparl-v1-0-body-1.ics`
+pub fn calendar_file_name(id: &Url) -> String {
+    url_slug(id) + CALENDAR_EXTENSION
+}
+
+/// Writes the calendars of all cached bodies and organizations of a server to `dir`. Files
+/// whose content didn't change are left untouched, so running this after every sync only
+/// updates the changed calendars. Returns the number of calendars and the number of changed
+/// files
+pub fn export_calendars<S: ReferenceIndex>(storage: &S,
+                                           entrypoint: &Url,
+                                           dir: &Path)
+                                           -> Result<(usize, usize), Box<Error>> {
+    create_dir_all(dir)?;
+    let mut calendars = 0;
+    let mut changed = 0;
+
+    for object in storage.iter_objects_of(entrypoint, &["Body", "Organization"]) {
+        let object = object?;
+        let content = calendar(storage, &object)?;
+        let path = dir.join(calendar_file_name(&object["id"].to_string().into_url()?));
+        calendars += 1;
+
+        let mut existing = String::new();
+        if let Ok(mut file) = File::open(&path) {
+            file.read_to_string(&mut existing)?;
+        }
+        if existing != content {
+            File::create(&path)?.write_all(content.as_bytes())?;
+            changed += 1;
+        }
+    }
+
+    Ok((calendars, changed))
+}
@@ -27,26 +27,89 @@
 #[macro_use]
 extern crate json;
 extern crate reqwest;
+extern crate hyper;
 extern crate chrono;
 extern crate crossbeam;
+extern crate fs2;
 extern crate serde;
 extern crate serde_json;
+extern crate toml;
 #[macro_use] extern crate serde_derive;
+#[cfg(feature = "sqlite")]
+extern crate rusqlite;
+#[cfg(feature = "postgresql")]
+extern crate postgres;
+#[cfg(feature = "pdf")]
+extern crate pdf_extract;
+extern crate sha1;
+extern crate rand;
 
 /// Contains the FileStorage struct with some associated constants
 pub mod file_storage;
 /// Contains the Cacher trait with a Message type
 pub mod cacher;
+/// Contains the reports with counters and timings of sync runs
+pub mod sync_report;
+/// Contains the Prometheus metrics of the daemon
+pub mod metrics;
+/// Contains the daemon that syncs servers on a schedule
+pub mod daemon;
+/// Contains the webhooks and commands triggered by sync events
+pub mod hooks;
+/// Contains servers for recording real APIs and replaying them in tests
+pub mod replay;
+/// Contains a generator for synthetic OParl servers
+pub mod synthetic;
+/// Contains the checks of servers for conformance with the OParl specification
+pub mod conformance;
+/// Contains the GraphQL query executor for the cached objects
+pub mod graphql;
+/// Contains the server exposing the cache over http
+pub mod cache_server;
+/// Contains the export of cached servers as rdf
+pub mod rdf;
+/// Contains the import of cached servers into relational databases
+pub mod sql;
+/// Contains the GeoJSON export of the cached locations
+pub mod geo;
+/// Contains the iCalendar feeds of the cached meetings
+pub mod ical;
+/// Contains the Atom feeds of new and changed papers
+pub mod atom;
+/// Contains the typed structs of the OParl types
+pub mod model;
+/// Contains the removal of servers and the garbage collection of the cache
+pub mod gc;
+/// Contains the layered configuration of the CLI
+pub mod config;
+/// Contains the snapshots of the cached objects of a server taken after sync runs
+pub mod snapshot;
+/// Contains the comparison of two cached servers or two snapshots of the same server
+pub mod diff;
+/// Contains the linking of identical persons, organizations and bodies across servers
+pub mod linking;
+/// Contains the mirroring of the documents of File objects and the extraction of their text
+pub mod files;
 
 mod storage;
+mod refs;
 mod server;
 mod external_list;
+mod time_window;
+mod object_filter;
+mod canonical;
+mod schema;
+mod http;
 
 pub use file_storage::FileStorage;
-pub use storage::Storage;
-pub use server::{Server, CommonServer};
+pub use storage::{Storage, CacheIter};
+pub use refs::{ReferenceIndex, Reference};
+pub use server::{Server, CommonServer, Auth};
 pub use external_list::ExternalList;
-pub use cacher::{Cacher};
+pub use time_window::TimeWindow;
+pub use object_filter::ObjectFilter;
+pub use canonical::{IdRewriter, Quirk};
+pub use cacher::{Cacher, Progress};
 
 /// Reexported from reqwest
 pub use reqwest::IntoUrl;
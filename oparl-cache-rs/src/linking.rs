@@ -0,0 +1,497 @@
+use std::cmp::max;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs::{self, File};
+
+use json::JsonValue;
+use rand;
+use reqwest::Url;
+use serde_json;
+
+use file_storage::FileStorage;
+use schema::split_type_url;
+use storage::Storage;
+
+/// The file in the cache folder that contains the link table
+pub const LINKS_FILE: &'static str = "links.json";
+
+/// Words that aren't part of a name, such as academic titles and forms of address
+const NAME_STOPWORDS: &[&str] = &["dr", "prof", "dipl", "ing", "med", "phil", "rer", "nat", "jur", "herr", "frau",
+                                  "mdb", "mdl"];
+
+/// Decides which entities are linked
+#[derive(Clone, Debug, PartialEq)]
+pub struct LinkOptions {
+    /// The minimal similarity of two normalized names between 0 and 1 for a link
+    pub threshold: f64,
+    /// The types that are linked by name, e.g. `Person`. Links through `equivalent` are always
+    /// followed
+    pub types: Vec<String>,
+}
+
+impl Default for LinkOptions {
+    fn default() -> LinkOptions {
+        LinkOptions {
+            threshold: 0.9,
+            types: vec!["Body".to_string(),
+                        "LegislativeTerm".to_string(),
+                        "Organization".to_string(),
+                        "Person".to_string()],
+        }
+    }
+}
+
+/// Why two entities were linked
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkReason {
+    /// One lists the other in `equivalent`
+    Equivalent,
+    /// Both bodies have the same official municipality key
+    Ags,
+    /// The names are similar and the dates don't contradict each other
+    Name,
+}
+
+/// A link between two entities, kept as evidence for the cluster
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Link {
+    /// The id of the first entity
+    pub source: String,
+    /// The id or the `equivalent` url of the second entity
+    pub target: String,
+    /// Why the entities were linked
+    pub reason: LinkReason,
+    /// The similarity of the names for links by name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub similarity: Option<f64>,
+}
+
+/// A group of ids describing the same entity
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Cluster {
+    /// The smallest id of the members, which identifies the cluster
+    pub id: String,
+    /// The OParl type of the entity, e.g. `Person`
+    #[serde(rename = "type")]
+    pub oparl_type: String,
+    /// The ids of the cached objects and the `equivalent` urls, sorted
+    pub members: Vec<String>,
+    /// The links that formed the cluster
+    pub links: Vec<Link>,
+    /// The sorted entrypoints of the linking pass that built the cluster
+    #[serde(default)]
+    pub servers: Vec<String>,
+}
+
+/// The clusters of identical entities on one or several servers.
+///
+/// Linking passes over different sets of servers are kept side by side, so an id can be in
+/// several clusters. `cluster` and `linked` then use the last one
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct LinkTable {
+    /// The clusters sorted by their id
+    pub clusters: Vec<Cluster>,
+    #[serde(skip)]
+    index: HashMap<String, usize>,
+}
+
+impl LinkTable {
+    fn new(clusters: Vec<Cluster>) -> LinkTable {
+        let mut table = LinkTable {
+            clusters: clusters,
+            index: HashMap::new(),
+        };
+        table.reindex();
+        table
+    }
+
+    fn reindex(&mut self) {
+        self.index.clear();
+        for (i, cluster) in self.clusters.iter().enumerate() {
+            for member in &cluster.members {
+                self.index.insert(member.clone(), i);
+            }
+        }
+    }
+
+    /// Reads the link table of the cache. Without a linking pass, the table is empty
+    pub fn load(storage: &FileStorage) -> Result<LinkTable, Box<Error>> {
+        let path = storage.get_cache_dir().join(LINKS_FILE);
+        if !path.exists() {
+            return Ok(LinkTable::default());
+        }
+        let mut table: LinkTable = serde_json::from_reader(File::open(path)?)?;
+        table.reindex();
+        Ok(table)
+    }
+
+    /// Merges the clusters into the link table of the cache. The clusters of the server sets
+    /// in this table replace the stored ones, while those of other server sets are kept. The
+    /// file is replaced atomically, so readers never see a partially written table
+    pub fn save(&self, storage: &FileStorage) -> Result<(), Box<Error>> {
+        let mut merged = LinkTable::load(storage)?;
+        merged.clusters.retain(|x| !self.clusters.iter().any(|y| y.servers == x.servers));
+        merged.clusters.extend(self.clusters.iter().cloned());
+        merged.clusters.sort_by(|a, b| a.id.cmp(&b.id).then_with(|| a.servers.cmp(&b.servers)));
+
+        let path = storage.get_cache_dir().join(LINKS_FILE);
+        let temporary = storage.get_cache_dir().join(format!("{}.{}.tmp", LINKS_FILE, rand::random::<u32>()));
+        serde_json::to_writer_pretty(File::create(&temporary)?, &merged)?;
+        if let Err(err) = fs::rename(&temporary, &path) {
+            let _ = fs::remove_file(&temporary);
+            return Err(From::from(err));
+        }
+        Ok(())
+    }
+
+    /// Returns the cluster containing the id
+    pub fn cluster(&self, id: &str) -> Option<&Cluster> {
+        self.index.get(id).map(|&i| &self.clusters[i])
+    }
+
+    /// Returns the other ids of the entity with this id
+    pub fn linked(&self, id: &str) -> Vec<String> {
+        self.cluster(id)
+            .map(|x| x.members.iter().filter(|x| *x != id).cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// A union-find over ids
+#[derive(Default)]
+struct Clusters {
+    parents: HashMap<String, String>,
+    links: Vec<Link>,
+}
+
+impl Clusters {
+    fn find(&mut self, id: &str) -> String {
+        let parent = match self.parents.get(id) {
+            Some(parent) => parent.clone(),
+            None => return id.to_string(),
+        };
+        if parent == id {
+            return parent;
+        }
+        let root = self.find(&parent);
+        self.parents.insert(id.to_string(), root.clone());
+        root
+    }
+
+    /// Joins the clusters of both ids and records the link unless they already were joined
+    fn link(&mut self, source: &str, target: &str, reason: LinkReason, similarity: Option<f64>) {
+        let (a, b) = (self.find(source), self.find(target));
+        if a == b {
+            return;
+        }
+        self.parents.insert(a.clone(), a.clone());
+        self.parents.insert(b.clone(), a);
+        self.links.push(Link {
+            source: source.to_string(),
+            target: target.to_string(),
+            reason: reason,
+            similarity: similarity,
+        });
+    }
+}
+
+/// Lowercases a name, spells out umlauts, removes titles and punctuation and sorts the words,
+/// so `Dr. Müller, Hans` and `Hans Mueller` are the same
+pub fn normalize_name(name: &str) -> String {
+    let mut spelled = String::new();
+    for c in name.to_lowercase().chars() {
+        match c {
+            'ä' => spelled.push_str("ae"),
+            'ö' => spelled.push_str("oe"),
+            'ü' => spelled.push_str("ue"),
+            'ß' => spelled.push_str("ss"),
+            c if c.is_alphanumeric() => spelled.push(c),
+            _ => spelled.push(' '),
+        }
+    }
+    let mut words = spelled.split_whitespace()
+        .filter(|x| !NAME_STOPWORDS.contains(x))
+        .collect::<Vec<_>>();
+    words.sort();
+    words.join(" ")
+}
+
+/// Returns the similarity of two strings between 0 and 1 based on the Levenshtein distance
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let a = a.chars().collect::<Vec<_>>();
+    let b = b.chars().collect::<Vec<_>>();
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+
+    let mut previous = (0..b.len() + 1).collect::<Vec<_>>();
+    for (i, x) in a.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, y) in b.iter().enumerate() {
+            let substitution = previous[j] + if x == y { 0 } else { 1 };
+            let value = *[previous[j + 1] + 1, current[j] + 1, substitution].iter().min().unwrap();
+            current.push(value);
+        }
+        previous = current;
+    }
+
+    1.0 - previous[b.len()] as f64 / max(a.len(), b.len()) as f64
+}
+
+/// A cached object prepared for the comparisons
+struct Entity<'o> {
+    id: String,
+    oparl_type: String,
+    name: String,
+    object: &'o JsonValue,
+}
+
+impl<'o> Entity<'o> {
+    fn new(object: &'o JsonValue) -> Option<Entity<'o>> {
+        let id = match object["id"].as_str() {
+            Some(id) => id.to_string(),
+            None => return None,
+        };
+        let oparl_type = match split_type_url(&object["type"].to_string()) {
+            Ok((_, oparl_type)) => oparl_type,
+            Err(_) => return None,
+        };
+        // The parts are more reliable than `name`, which often contains the title
+        let name = match (object["givenName"].as_str(), object["familyName"].as_str()) {
+            (Some(given_name), Some(family_name)) => format!("{} {}", given_name, family_name),
+            _ => object["name"].as_str().unwrap_or("").to_string(),
+        };
+        Some(Entity {
+            id: id,
+            oparl_type: oparl_type,
+            name: normalize_name(&name),
+            object: object,
+        })
+    }
+
+    /// Returns false if both have the property with different values
+    fn agrees(&self, other: &Entity, property: &str) -> bool {
+        match (self.object[property].as_str(), other.object[property].as_str()) {
+            (Some(a), Some(b)) => a == b,
+            _ => true,
+        }
+    }
+
+    /// Returns the digits of the official municipality key of a body
+    fn ags(&self) -> Option<String> {
+        let ags = self.object["ags"]
+            .as_str()
+            .unwrap_or("")
+            .chars()
+            .filter(|x| x.is_digit(10))
+            .collect::<String>();
+        if ags.is_empty() { None } else { Some(ags) }
+    }
+}
+
+/// The bodies and membership periods of a person, which tell apart persons with the same name
+#[derive(Default)]
+struct Affiliation {
+    /// The roots of the clusters of the person's bodies and of the bodies of their organizations
+    bodies: Vec<String>,
+    /// The start and end dates of the memberships, where `None` is an ongoing membership
+    periods: Vec<(String, Option<String>)>,
+}
+
+impl Affiliation {
+    fn new(person: &Entity, objects: &HashMap<&str, &JsonValue>, clusters: &mut Clusters) -> Affiliation {
+        let mut affiliation = Affiliation::default();
+        if let Some(body) = person.object["body"].as_str() {
+            affiliation.bodies.push(clusters.find(body));
+        }
+        // Cached persons reference their memberships, while embedded ones are still objects
+        for membership in person.object["membership"].members() {
+            let membership = match membership.as_str() {
+                Some(id) => {
+                    match objects.get(id) {
+                        Some(membership) => *membership,
+                        None => continue,
+                    }
+                }
+                None => membership,
+            };
+            let organization = membership["organization"].as_str().and_then(|x| objects.get(x));
+            if let Some(body) = organization.and_then(|x| x["body"].as_str()) {
+                affiliation.bodies.push(clusters.find(body));
+            }
+            if let Some(start) = membership["startDate"].as_str() {
+                affiliation.periods.push((start.to_string(), membership["endDate"].as_str().map(String::from)));
+            }
+        }
+        affiliation
+    }
+
+    /// Returns true if both share a body or were members at the same time. The dates are
+    /// compared as strings, which works for ISO 8601 dates
+    fn overlaps(&self, other: &Affiliation) -> bool {
+        if self.bodies.iter().any(|x| other.bodies.contains(x)) {
+            return true;
+        }
+        let before = |start: &String, end: &Option<String>| end.as_ref().map(|x| start <= x).unwrap_or(true);
+        self.periods.iter().any(|&(ref a_start, ref a_end)| {
+            other.periods.iter().any(|&(ref b_start, ref b_end)| before(a_start, b_end) && before(b_start, a_end))
+        })
+    }
+}
+
+/// Returns the entities of the type if the type is linked by name
+fn of_type<'e, 'o>(entities: &'e [Entity<'o>], options: &LinkOptions, oparl_type: &str) -> Vec<&'e Entity<'o>> {
+    if !options.types.iter().any(|x| x == oparl_type) {
+        return vec![];
+    }
+    entities.iter().filter(|x| x.oparl_type == oparl_type).collect()
+}
+
+/// Links the entities in `group` with similar names for which `compatible` holds
+fn link_by_name<F>(clusters: &mut Clusters, group: &[&Entity], threshold: f64, compatible: F)
+    where F: Fn(&Entity, &Entity) -> bool
+{
+    for (i, a) in group.iter().enumerate() {
+        for b in &group[i + 1..] {
+            if a.name.is_empty() || b.name.is_empty() || clusters.find(&a.id) == clusters.find(&b.id) {
+                continue;
+            }
+            let score = similarity(&a.name, &b.name);
+            if score >= threshold && compatible(*a, *b) {
+                clusters.link(&a.id, &b.id, LinkReason::Name, Some(score));
+            }
+        }
+    }
+}
+
+/// Builds the clusters of identical entities from the objects of one or several servers.
+///
+/// First the `equivalent` links are followed, then bodies are linked by their AGS or their name.
+/// Legislative terms and organizations are only linked within the same body, i.e. the same
+/// council on two servers, and need matching dates. Persons are linked if their names are
+/// similar, their family names and genders don't contradict each other and they either share a
+/// (linked) body or have overlapping memberships, as common names recur across a region
+pub fn link_objects(objects: &[JsonValue], options: &LinkOptions) -> LinkTable {
+    let entities = objects.iter().filter_map(Entity::new).collect::<Vec<_>>();
+    let types = entities.iter()
+        .map(|x| (x.id.clone(), x.oparl_type.clone()))
+        .collect::<HashMap<_, _>>();
+    let mut clusters = Clusters::default();
+
+    for entity in &entities {
+        for equivalent in entity.object["equivalent"].members().filter_map(|x| x.as_str()) {
+            clusters.link(&entity.id, equivalent, LinkReason::Equivalent, None);
+        }
+    }
+
+    let bodies = of_type(&entities, options, "Body");
+    for (i, a) in bodies.iter().enumerate() {
+        for b in &bodies[i + 1..] {
+            match (a.ags(), b.ags()) {
+                (Some(x), Some(y)) => {
+                    if x == y {
+                        clusters.link(&a.id, &b.id, LinkReason::Ags, None);
+                    }
+                }
+                _ => link_by_name(&mut clusters, &[*a, *b], options.threshold, |_, _| true),
+            }
+        }
+    }
+
+    for oparl_type in &["LegislativeTerm", "Organization"] {
+        let mut by_body: BTreeMap<String, Vec<&Entity>> = BTreeMap::new();
+        for entity in of_type(&entities, options, oparl_type) {
+            if let Some(body) = entity.object["body"].as_str() {
+                by_body.entry(clusters.find(body)).or_insert_with(Vec::new).push(entity);
+            }
+        }
+        for group in by_body.values() {
+            link_by_name(&mut clusters, group, options.threshold, |a, b| {
+                a.agrees(b, "startDate") && a.agrees(b, "endDate") && a.agrees(b, "classification")
+            });
+        }
+    }
+
+    let by_id = objects.iter()
+        .filter_map(|x| x["id"].as_str().map(|id| (id, x)))
+        .collect::<HashMap<_, _>>();
+    let mut affiliations = HashMap::new();
+    // Only persons sharing a word of their names are compared
+    let mut by_word: BTreeMap<String, Vec<&Entity>> = BTreeMap::new();
+    for entity in of_type(&entities, options, "Person") {
+        affiliations.insert(entity.id.clone(), Affiliation::new(entity, &by_id, &mut clusters));
+        for word in entity.name.split_whitespace() {
+            by_word.entry(word.to_string()).or_insert_with(Vec::new).push(entity);
+        }
+    }
+    for group in by_word.values() {
+        link_by_name(&mut clusters, group, options.threshold, |a, b| {
+            let family_names = match (a.object["familyName"].as_str(), b.object["familyName"].as_str()) {
+                (Some(x), Some(y)) => normalize_name(x) == normalize_name(y),
+                _ => true,
+            };
+            family_names && a.agrees(b, "gender") && affiliations[&a.id].overlaps(&affiliations[&b.id])
+        });
+    }
+
+    // Collect the members of the clusters with more than one member
+    let ids = clusters.parents.keys().cloned().collect::<Vec<_>>();
+    let mut members: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for id in ids {
+        let root = clusters.find(&id);
+        members.entry(root).or_insert_with(Vec::new).push(id);
+    }
+
+    let mut links: HashMap<String, Vec<Link>> = HashMap::new();
+    for link in clusters.links.clone() {
+        let root = clusters.find(&link.source);
+        links.entry(root).or_insert_with(Vec::new).push(link);
+    }
+
+    let mut result = vec![];
+    for (root, mut members) in members {
+        if members.len() < 2 {
+            continue;
+        }
+        members.sort();
+        let oparl_type = members.iter()
+            .filter_map(|x| types.get(x))
+            .next()
+            .cloned()
+            .unwrap_or_default();
+        result.push(Cluster {
+            id: members[0].clone(),
+            oparl_type: oparl_type,
+            members: members,
+            links: links.remove(&root).unwrap_or_default(),
+            servers: vec![],
+        });
+    }
+    result.sort_by(|a, b| a.id.cmp(&b.id));
+
+    LinkTable::new(result)
+}
+
+/// Runs the linking pass over the cached objects of the servers
+pub fn link_servers<S: Storage>(storage: &S, servers: &[Url], options: &LinkOptions) -> Result<LinkTable, Box<Error>> {
+    // Objects shared by several servers are only kept once
+    let mut objects = BTreeMap::new();
+    for server in servers {
+        for object in storage.iter_objects(server, None) {
+            let object = object?;
+            if let Some(id) = object["id"].as_str().map(String::from) {
+                objects.insert(id, object);
+            }
+        }
+    }
+    let mut table = link_objects(&objects.into_iter().map(|x| x.1).collect::<Vec<_>>(), options);
+
+    let mut entrypoints = servers.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+    entrypoints.sort();
+    entrypoints.dedup();
+    for cluster in &mut table.clusters {
+        cluster.servers = entrypoints.clone();
+    }
+    Ok(table)
+}
@@ -0,0 +1,161 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::Utc;
+use hyper::header::ContentType;
+use hyper::server::{Server as HttpServer, Listening, Request, Response};
+use hyper::status::StatusCode;
+use reqwest::Url;
+
+use http::{request_url, respond, not_found};
+use sync_report::{SyncReport, as_millis};
+
+/// The metrics of a single server
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ServerMetrics {
+    /// The number of finished sync runs
+    pub syncs: u64,
+    /// The number of sync runs that returned an error or had failed lists
+    pub failed_syncs: u64,
+    /// The number of distinct objects in the cached external lists of the server after the last
+    /// run, not counting embedded objects
+    pub objects_cached: usize,
+    /// The number of failed lists and invalid objects over all runs
+    pub errors: u64,
+    /// Unix timestamp of the end of the last successful run
+    pub last_success: Option<i64>,
+    /// The duration of the last run in seconds
+    pub last_duration: f64,
+}
+
+/// The metrics of all servers of a daemon, keyed by the entrypoint
+#[derive(Clone, Debug, Default)]
+pub struct Metrics {
+    servers: BTreeMap<String, ServerMetrics>,
+}
+
+impl Metrics {
+    /// Creates empty metrics for the given entrypoints so that they show up before the first run
+    pub fn new(entrypoints: &[Url]) -> Metrics {
+        let mut metrics = Metrics::default();
+        for entrypoint in entrypoints {
+            metrics.servers.insert(entrypoint.to_string(), ServerMetrics::default());
+        }
+        metrics
+    }
+
+    /// Returns the metrics of a server
+    pub fn get(&self, entrypoint: &Url) -> Option<&ServerMetrics> {
+        self.servers.get(entrypoint.as_str())
+    }
+
+    /// Records a finished sync run. `report` is the report written by that run, if any, and
+    /// `cached` the number of objects in the lists of the server afterwards, if it could be counted
+    pub fn record(&mut self,
+                  entrypoint: &Url,
+                  success: bool,
+                  report: Option<&SyncReport>,
+                  cached: Option<usize>,
+                  duration: Duration) {
+        let server = self.servers.entry(entrypoint.to_string()).or_insert_with(Default::default);
+
+        server.syncs += 1;
+        server.last_duration = as_millis(duration) as f64 / 1000.0;
+
+        if let Some(cached) = cached {
+            server.objects_cached = cached;
+        }
+        if let Some(report) = report {
+            server.errors += (report.failed_lists() + report.invalid_objects()) as u64;
+        }
+
+        if success {
+            server.last_success = Some(Utc::now().timestamp());
+        } else {
+            server.failed_syncs += 1;
+        }
+    }
+
+    /// Renders the metrics in the Prometheus text format
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+
+        self.render_metric(&mut output,
+                           "oparl_cache_syncs_total",
+                           "counter",
+                           "Number of finished sync runs",
+                           |x| Some(x.syncs as f64));
+        self.render_metric(&mut output,
+                           "oparl_cache_failed_syncs_total",
+                           "counter",
+                           "Number of sync runs that returned an error or had failed lists",
+                           |x| Some(x.failed_syncs as f64));
+        self.render_metric(&mut output,
+                           "oparl_cache_objects_cached",
+                           "gauge",
+                           "Number of distinct objects in the cached external lists of the server",
+                           |x| Some(x.objects_cached as f64));
+        self.render_metric(&mut output,
+                           "oparl_cache_errors_total",
+                           "counter",
+                           "Number of failed lists and invalid objects",
+                           |x| Some(x.errors as f64));
+        self.render_metric(&mut output,
+                           "oparl_cache_last_success_timestamp_seconds",
+                           "gauge",
+                           "Unix timestamp of the last successful sync run",
+                           |x| x.last_success.map(|x| x as f64));
+        self.render_metric(&mut output,
+                           "oparl_cache_sync_duration_seconds",
+                           "gauge",
+                           "Duration of the last sync run",
+                           |x| Some(x.last_duration));
+
+        output
+    }
+
+    fn render_metric<F>(&self, output: &mut String, name: &str, kind: &str, help: &str, value: F)
+        where F: Fn(&ServerMetrics) -> Option<f64>
+    {
+        writeln!(output, "# HELP {} {}", name, help).unwrap();
+        writeln!(output, "# TYPE {} {}", name, kind).unwrap();
+        for (entrypoint, server) in &self.servers {
+            if let Some(value) = value(server) {
+                writeln!(output,
+                         "{}{{entrypoint=\"{}\"}} {}",
+                         name,
+                         escape_label(entrypoint),
+                         value)
+                    .unwrap();
+            }
+        }
+    }
+}
+
+/// Escapes a label value as required by the Prometheus text format
+fn escape_label(value: &str) -> String {
+    value.replace("\\", "\\\\").replace("\"", "\\\"").replace("\n", "\\n")
+}
+
+/// Serves `/metrics` in the Prometheus text format and `/healthz` on `address` in background
+/// threads
+pub fn serve_metrics(address: &str, metrics: Arc<Mutex<Metrics>>) -> Result<Listening, Box<Error>> {
+    let handler = move |request: Request, response: Response| {
+        let path = request_url(&request).map(|x| x.path().to_string());
+        match path.as_ref().map(String::as_str) {
+            Some("/metrics") => {
+                let body = metrics.lock().unwrap().render();
+                respond(response, StatusCode::Ok, ContentType::plaintext(), body.as_bytes());
+            }
+            Some("/healthz") => {
+                respond(response, StatusCode::Ok, ContentType::plaintext(), b"ok\n");
+            }
+            _ => not_found(response),
+        }
+    };
+
+    Ok(HttpServer::http(address)?.handle(handler)?)
+}
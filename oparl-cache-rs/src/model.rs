@@ -0,0 +1,685 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use json::{self, JsonValue};
+use reqwest::{IntoUrl, Url};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::{DeserializeOwned, Error as DeError};
+use serde_json::{self, Value};
+
+use schema::split_type_url;
+use storage::Storage;
+
+/// An OParl type with a typed representation
+pub trait OParlObject: Serialize + DeserializeOwned {
+    /// The name of the type without the schema url, e.g. `Paper`
+    fn type_name() -> &'static str;
+    /// The url of the object
+    fn id(&self) -> &str;
+}
+
+/// Whether a property can be left out when serializing
+trait Empty {
+    fn is_empty(&self) -> bool;
+}
+
+impl<T> Empty for Option<T> {
+    fn is_empty(&self) -> bool {
+        self.is_none()
+    }
+}
+
+impl<T> Empty for Vec<T> {
+    fn is_empty(&self) -> bool {
+        Vec::is_empty(self)
+    }
+}
+
+/// A reference to another object by its url
+///
+/// Embedded objects are accepted as well, but only their id is kept. Use `resolve` to load the
+/// referenced object from the cache
+pub struct ObjectRef<T> {
+    id: String,
+    oparl_type: PhantomData<T>,
+}
+
+impl<T> ObjectRef<T> {
+    /// Creates a reference to the object with the url `id`
+    pub fn new<S: Into<String>>(id: S) -> ObjectRef<T> {
+        ObjectRef {
+            id: id.into(),
+            oparl_type: PhantomData,
+        }
+    }
+
+    /// The url of the referenced object
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The url of the referenced object, parsed
+    pub fn url(&self) -> Result<Url, Box<Error>> {
+        Ok(self.id.as_str().into_url()?)
+    }
+}
+
+impl<T: OParlObject> ObjectRef<T> {
+    /// Loads the referenced object from the cache
+    pub fn resolve<S: Storage>(&self, storage: &S) -> Result<T, Box<Error>> {
+        storage.get_typed(&self.url()?)
+    }
+}
+
+impl<T> Clone for ObjectRef<T> {
+    fn clone(&self) -> ObjectRef<T> {
+        ObjectRef::new(self.id.clone())
+    }
+}
+
+impl<T> PartialEq for ObjectRef<T> {
+    fn eq(&self, other: &ObjectRef<T>) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T> fmt::Debug for ObjectRef<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ObjectRef({:?})", self.id)
+    }
+}
+
+impl<T> Serialize for ObjectRef<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.id)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for ObjectRef<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<ObjectRef<T>, D::Error> {
+        match Value::deserialize(deserializer)? {
+            Value::String(id) => Ok(ObjectRef::new(id)),
+            Value::Object(ref object) => {
+                match object.get("id").and_then(|x| x.as_str()) {
+                    Some(id) => Ok(ObjectRef::new(id)),
+                    None => Err(D::Error::custom("The embedded object has no id")),
+                }
+            }
+            _ => Err(D::Error::custom("Expected the url or an embedded object")),
+        }
+    }
+}
+
+/// A reference to an external list, e.g. the papers of a body
+pub struct ListRef<T> {
+    url: String,
+    oparl_type: PhantomData<T>,
+}
+
+impl<T> ListRef<T> {
+    /// Creates a reference to the external list at `url`
+    pub fn new<S: Into<String>>(url: S) -> ListRef<T> {
+        ListRef {
+            url: url.into(),
+            oparl_type: PhantomData,
+        }
+    }
+
+    /// The url of the list
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Returns the references to the items of the cached list
+    pub fn resolve<S: Storage>(&self, storage: &S) -> Result<Vec<ObjectRef<T>>, Box<Error>> {
+        let list = storage.get(&self.url.as_str().into_url()?)?;
+        Ok(list.members().filter_map(|x| x.as_str()).map(ObjectRef::new).collect())
+    }
+}
+
+impl<T> Clone for ListRef<T> {
+    fn clone(&self) -> ListRef<T> {
+        ListRef::new(self.url.clone())
+    }
+}
+
+impl<T> PartialEq for ListRef<T> {
+    fn eq(&self, other: &ListRef<T>) -> bool {
+        self.url == other.url
+    }
+}
+
+impl<T> fmt::Debug for ListRef<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ListRef({:?})", self.url)
+    }
+}
+
+impl<T> Serialize for ListRef<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.url)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for ListRef<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<ListRef<T>, D::Error> {
+        Ok(ListRef::new(String::deserialize(deserializer)?))
+    }
+}
+
+/// Reads a date or a timestamp. Malformed values, e.g. a date where a timestamp is expected, are
+/// read as `None` instead of failing the whole object
+fn lenient<'de, D: Deserializer<'de>, T: DeserializeOwned>(deserializer: D) -> Result<Option<T>, D::Error> {
+    Ok(serde_json::from_value(Value::deserialize(deserializer)?).ok())
+}
+
+/// Defines the struct of an OParl type with the properties all types share
+///
+/// All properties are optional, as servers frequently leave out required ones, and malformed
+/// dates are read as `None`. Properties that aren't part of the specification are kept in
+/// `extra`. Converting an object back with `to_json` keeps all properties except empty lists,
+/// nulls and malformed dates, which are left out. Timestamps keep their time, but may be written
+/// differently, e.g. `+00:00` instead of `Z`
+macro_rules! oparl_type {
+    ($(#[$meta:meta])* $name:ident { $($(#[$field_meta:meta])* $field:ident: $field_type:ty,)* }) => {
+        $(#[$meta])*
+        #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+        #[serde(rename_all = "camelCase")]
+        pub struct $name {
+            /// The url of the object
+            pub id: String,
+            /// The type url, e.g. `https://schema.oparl.org/1.0/Paper`
+            #[serde(rename = "type")]
+            pub oparl_type: String,
+            $(
+                $(#[$field_meta])*
+                #[serde(default, skip_serializing_if = "Empty::is_empty")]
+                pub $field: $field_type,
+            )*
+            /// The url of the license of the object
+            #[serde(default, skip_serializing_if = "Empty::is_empty")]
+            pub license: Option<String>,
+            /// Keywords
+            #[serde(default, skip_serializing_if = "Empty::is_empty")]
+            pub keyword: Vec<String>,
+            /// The time the object was created
+            #[serde(default, skip_serializing_if = "Empty::is_empty", deserialize_with = "lenient")]
+            pub created: Option<DateTime<FixedOffset>>,
+            /// The time the object was last changed
+            #[serde(default, skip_serializing_if = "Empty::is_empty", deserialize_with = "lenient")]
+            pub modified: Option<DateTime<FixedOffset>>,
+            /// The url of the html page of the object
+            #[serde(default, skip_serializing_if = "Empty::is_empty")]
+            pub web: Option<String>,
+            /// Whether the object was deleted
+            #[serde(default, skip_serializing_if = "Empty::is_empty")]
+            pub deleted: Option<bool>,
+            /// The properties that aren't part of the specification, e.g. vendor extensions
+            #[serde(flatten)]
+            pub extra: BTreeMap<String, Value>,
+        }
+
+        impl OParlObject for $name {
+            fn type_name() -> &'static str {
+                stringify!($name)
+            }
+
+            fn id(&self) -> &str {
+                &self.id
+            }
+        }
+    }
+}
+
+oparl_type! {
+    /// The entrypoint of an OParl server
+    System {
+        /// The url of the OParl version, e.g. `https://schema.oparl.org/1.0/`
+        oparl_version: Option<String>,
+        /// The same server in other OParl versions
+        other_oparl_versions: Vec<ObjectRef<System>>,
+        /// The list of the bodies
+        body: Option<ListRef<Body>>,
+        /// The name of the server
+        name: Option<String>,
+        /// The mail address of the operator
+        contact_email: Option<String>,
+        /// The name of the operator
+        contact_name: Option<String>,
+        /// The website of the operator
+        website: Option<String>,
+        /// The website of the vendor of the software
+        vendor: Option<String>,
+        /// The website of the software
+        product: Option<String>,
+    }
+}
+
+oparl_type! {
+    /// A municipality, district or other body
+    Body {
+        /// The system the body belongs to
+        system: Option<ObjectRef<System>>,
+        /// The short name
+        short_name: Option<String>,
+        /// The name
+        name: Option<String>,
+        /// The website
+        website: Option<String>,
+        /// Since when the license applies
+        #[serde(deserialize_with = "lenient")]
+        license_valid_since: Option<DateTime<FixedOffset>>,
+        /// Since when the body is available over OParl
+        #[serde(deserialize_with = "lenient")]
+        oparl_since: Option<DateTime<FixedOffset>>,
+        /// The official municipality key
+        ags: Option<String>,
+        /// The regional key
+        rgs: Option<String>,
+        /// Urls of the body in other datasets, e.g. Wikidata
+        equivalent: Vec<String>,
+        /// The contact mail address
+        contact_email: Option<String>,
+        /// The contact name
+        contact_name: Option<String>,
+        /// The list of the organizations
+        organization: Option<ListRef<Organization>>,
+        /// The list of the persons
+        person: Option<ListRef<Person>>,
+        /// The list of the meetings
+        meeting: Option<ListRef<Meeting>>,
+        /// The list of the papers
+        paper: Option<ListRef<Paper>>,
+        /// The legislative terms
+        legislative_term: Vec<ObjectRef<LegislativeTerm>>,
+        /// The kind of the body, e.g. `Kreis`
+        classification: Option<String>,
+        /// The location of the body
+        location: Option<ObjectRef<Location>>,
+    }
+}
+
+oparl_type! {
+    /// A legislative term of a body
+    LegislativeTerm {
+        /// The body
+        body: Option<ObjectRef<Body>>,
+        /// The name
+        name: Option<String>,
+        /// The first day
+        #[serde(deserialize_with = "lenient")]
+        start_date: Option<NaiveDate>,
+        /// The last day
+        #[serde(deserialize_with = "lenient")]
+        end_date: Option<NaiveDate>,
+    }
+}
+
+oparl_type! {
+    /// A group of persons, e.g. a committee or a faction
+    Organization {
+        /// The body
+        body: Option<ObjectRef<Body>>,
+        /// The name
+        name: Option<String>,
+        /// The memberships
+        membership: Vec<ObjectRef<Membership>>,
+        /// The list of the meetings
+        meeting: Option<ListRef<Meeting>>,
+        /// The short name
+        short_name: Option<String>,
+        /// The positions, e.g. `Chair`
+        post: Vec<String>,
+        /// The parent organization
+        sub_organization_of: Option<ObjectRef<Organization>>,
+        /// The kind of the organization, e.g. `Gremium`
+        organization_type: Option<String>,
+        /// A finer classification, e.g. `Ausschuss`
+        classification: Option<String>,
+        /// The day the organization was founded
+        #[serde(deserialize_with = "lenient")]
+        start_date: Option<NaiveDate>,
+        /// The day the organization was dissolved
+        #[serde(deserialize_with = "lenient")]
+        end_date: Option<NaiveDate>,
+        /// The website
+        website: Option<String>,
+        /// The location
+        location: Option<ObjectRef<Location>>,
+        /// The body represented by the organization
+        external_body: Option<ObjectRef<Body>>,
+    }
+}
+
+oparl_type! {
+    /// A natural person
+    Person {
+        /// The body
+        body: Option<ObjectRef<Body>>,
+        /// The full name
+        name: Option<String>,
+        /// The family name
+        family_name: Option<String>,
+        /// The given name
+        given_name: Option<String>,
+        /// The form of address, e.g. `Ratsfrau`
+        form_of_address: Option<String>,
+        /// An affix to the name, e.g. `von`
+        affix: Option<String>,
+        /// Academic titles
+        title: Vec<String>,
+        /// The gender
+        gender: Option<String>,
+        /// Phone numbers
+        phone: Vec<String>,
+        /// Mail addresses
+        email: Vec<String>,
+        /// The contact address
+        location: Option<ObjectRef<Location>>,
+        /// Roles, e.g. `Mayor`
+        status: Vec<String>,
+        /// The memberships
+        membership: Vec<ObjectRef<Membership>>,
+        /// A short biography
+        life: Option<String>,
+        /// The source of the biography
+        life_source: Option<String>,
+    }
+}
+
+oparl_type! {
+    /// The membership of a person in an organization
+    Membership {
+        /// The person
+        person: Option<ObjectRef<Person>>,
+        /// The organization
+        organization: Option<ObjectRef<Organization>>,
+        /// The role, e.g. `Chair`
+        role: Option<String>,
+        /// Whether the person may vote
+        voting_right: Option<bool>,
+        /// The first day
+        #[serde(deserialize_with = "lenient")]
+        start_date: Option<NaiveDate>,
+        /// The last day
+        #[serde(deserialize_with = "lenient")]
+        end_date: Option<NaiveDate>,
+        /// The organization the person represents, e.g. a faction
+        on_behalf_of: Option<ObjectRef<Organization>>,
+    }
+}
+
+oparl_type! {
+    /// A meeting of one or more organizations
+    Meeting {
+        /// The name
+        name: Option<String>,
+        /// The state, e.g. `eingeladen`
+        meeting_state: Option<String>,
+        /// Whether the meeting was cancelled
+        cancelled: Option<bool>,
+        /// The start
+        #[serde(deserialize_with = "lenient")]
+        start: Option<DateTime<FixedOffset>>,
+        /// The end
+        #[serde(deserialize_with = "lenient")]
+        end: Option<DateTime<FixedOffset>>,
+        /// The location
+        location: Option<ObjectRef<Location>>,
+        /// The organizations holding the meeting
+        organization: Vec<ObjectRef<Organization>>,
+        /// The participants
+        participant: Vec<ObjectRef<Person>>,
+        /// The invitation
+        invitation: Option<ObjectRef<File>>,
+        /// The protocol of the results
+        results_protocol: Option<ObjectRef<File>>,
+        /// The verbatim protocol
+        verbatim_protocol: Option<ObjectRef<File>>,
+        /// Other files
+        auxiliary_file: Vec<ObjectRef<File>>,
+        /// The agenda
+        agenda_item: Vec<ObjectRef<AgendaItem>>,
+    }
+}
+
+oparl_type! {
+    /// An item on the agenda of a meeting
+    AgendaItem {
+        /// The meeting
+        meeting: Option<ObjectRef<Meeting>>,
+        /// The number, e.g. `10.1`
+        number: Option<String>,
+        /// The name
+        name: Option<String>,
+        /// Whether the item is discussed in public
+        public: Option<bool>,
+        /// The consultation of a paper discussed under this item
+        consultation: Option<ObjectRef<Consultation>>,
+        /// The result, e.g. `Unverändert beschlossen`
+        result: Option<String>,
+        /// The text of the resolution
+        resolution_text: Option<String>,
+        /// The resolution as file
+        resolution_file: Option<ObjectRef<File>>,
+        /// Other files
+        auxiliary_file: Vec<ObjectRef<File>>,
+        /// The start of the discussion
+        #[serde(deserialize_with = "lenient")]
+        start: Option<DateTime<FixedOffset>>,
+        /// The end of the discussion
+        #[serde(deserialize_with = "lenient")]
+        end: Option<DateTime<FixedOffset>>,
+    }
+}
+
+oparl_type! {
+    /// A document such as a motion or a request
+    Paper {
+        /// The body
+        body: Option<ObjectRef<Body>>,
+        /// The title
+        name: Option<String>,
+        /// The reference number, e.g. `2017/001`
+        reference: Option<String>,
+        /// The day of publication
+        #[serde(deserialize_with = "lenient")]
+        date: Option<NaiveDate>,
+        /// The kind of paper, e.g. `Antrag`
+        paper_type: Option<String>,
+        /// Related papers
+        related_paper: Vec<ObjectRef<Paper>>,
+        /// Papers this paper belongs to
+        superordinated_paper: Vec<ObjectRef<Paper>>,
+        /// Papers belonging to this paper
+        subordinated_paper: Vec<ObjectRef<Paper>>,
+        /// The main file
+        main_file: Option<ObjectRef<File>>,
+        /// Other files
+        auxiliary_file: Vec<ObjectRef<File>>,
+        /// The locations the paper is about
+        location: Vec<ObjectRef<Location>>,
+        /// The persons who wrote the paper
+        originator_person: Vec<ObjectRef<Person>>,
+        /// The organizations in charge
+        under_direction_of: Vec<ObjectRef<Organization>>,
+        /// The organizations that wrote the paper
+        originator_organization: Vec<ObjectRef<Organization>>,
+        /// The consultations
+        consultation: Vec<ObjectRef<Consultation>>,
+    }
+}
+
+oparl_type! {
+    /// The discussion of a paper by an organization
+    Consultation {
+        /// The paper
+        paper: Option<ObjectRef<Paper>>,
+        /// The agenda item
+        agenda_item: Option<ObjectRef<AgendaItem>>,
+        /// The meeting
+        meeting: Option<ObjectRef<Meeting>>,
+        /// The consulting organizations
+        organization: Vec<ObjectRef<Organization>>,
+        /// Whether a final decision is made
+        authoritative: Option<bool>,
+        /// The role of the consultation, e.g. `Anhörung`
+        role: Option<String>,
+    }
+}
+
+oparl_type! {
+    /// A document, e.g. a pdf
+    File {
+        /// The title
+        name: Option<String>,
+        /// The file name
+        file_name: Option<String>,
+        /// The mime type
+        mime_type: Option<String>,
+        /// The day of the document
+        #[serde(deserialize_with = "lenient")]
+        date: Option<NaiveDate>,
+        /// The size in bytes
+        size: Option<u64>,
+        /// The SHA1 checksum of the content
+        sha1_checksum: Option<String>,
+        /// The extracted text
+        text: Option<String>,
+        /// The url of the content
+        access_url: Option<String>,
+        /// The url that starts a download of the content
+        download_url: Option<String>,
+        /// A url to an external viewer
+        external_service_url: Option<String>,
+        /// The file this one was derived from
+        master_file: Option<ObjectRef<File>>,
+        /// Files derived from this one
+        derivative_file: Vec<ObjectRef<File>>,
+        /// The license of the content
+        file_license: Option<String>,
+        /// The meetings using the file
+        meeting: Vec<ObjectRef<Meeting>>,
+        /// The agenda items using the file
+        agenda_item: Vec<ObjectRef<AgendaItem>>,
+        /// The papers using the file
+        paper: Vec<ObjectRef<Paper>>,
+    }
+}
+
+oparl_type! {
+    /// A place, e.g. an address or an area
+    Location {
+        /// A description of the place
+        description: Option<String>,
+        /// The geometry as GeoJSON
+        geojson: Option<Value>,
+        /// The street and house number
+        street_address: Option<String>,
+        /// The room
+        room: Option<String>,
+        /// The postal code
+        postal_code: Option<String>,
+        /// The district
+        sub_locality: Option<String>,
+        /// The city
+        locality: Option<String>,
+        /// The bodies at the place
+        bodies: Vec<ObjectRef<Body>>,
+        /// The organizations at the place
+        organizations: Vec<ObjectRef<Organization>>,
+        /// The persons at the place
+        persons: Vec<ObjectRef<Person>>,
+        /// The meetings at the place
+        meetings: Vec<ObjectRef<Meeting>>,
+        /// The papers about the place
+        papers: Vec<ObjectRef<Paper>>,
+    }
+}
+
+/// An object of any OParl type
+#[derive(Clone, Debug, PartialEq)]
+pub enum AnyObject {
+    /// A System
+    System(System),
+    /// A Body
+    Body(Body),
+    /// A LegislativeTerm
+    LegislativeTerm(LegislativeTerm),
+    /// An Organization
+    Organization(Organization),
+    /// A Person
+    Person(Person),
+    /// A Membership
+    Membership(Membership),
+    /// A Meeting
+    Meeting(Meeting),
+    /// An AgendaItem
+    AgendaItem(AgendaItem),
+    /// A Paper
+    Paper(Paper),
+    /// A Consultation
+    Consultation(Consultation),
+    /// A File
+    File(File),
+    /// A Location
+    Location(Location),
+}
+
+impl AnyObject {
+    /// Converts an object to the struct of its type
+    pub fn from_json(object: &JsonValue) -> Result<AnyObject, Box<Error>> {
+        let (_, oparl_type) = split_type_url(&object["type"].to_string())?;
+        let json = object.dump();
+        Ok(match oparl_type.as_str() {
+            "System" => AnyObject::System(serde_json::from_str(&json)?),
+            "Body" => AnyObject::Body(serde_json::from_str(&json)?),
+            "LegislativeTerm" => AnyObject::LegislativeTerm(serde_json::from_str(&json)?),
+            "Organization" => AnyObject::Organization(serde_json::from_str(&json)?),
+            "Person" => AnyObject::Person(serde_json::from_str(&json)?),
+            "Membership" => AnyObject::Membership(serde_json::from_str(&json)?),
+            "Meeting" => AnyObject::Meeting(serde_json::from_str(&json)?),
+            "AgendaItem" => AnyObject::AgendaItem(serde_json::from_str(&json)?),
+            "Paper" => AnyObject::Paper(serde_json::from_str(&json)?),
+            "Consultation" => AnyObject::Consultation(serde_json::from_str(&json)?),
+            "File" => AnyObject::File(serde_json::from_str(&json)?),
+            "Location" => AnyObject::Location(serde_json::from_str(&json)?),
+            other => return Err(From::from(format!("Unknown type {} of {}", other, object["id"]))),
+        })
+    }
+
+    /// The url of the object
+    pub fn id(&self) -> &str {
+        match *self {
+            AnyObject::System(ref x) => &x.id,
+            AnyObject::Body(ref x) => &x.id,
+            AnyObject::LegislativeTerm(ref x) => &x.id,
+            AnyObject::Organization(ref x) => &x.id,
+            AnyObject::Person(ref x) => &x.id,
+            AnyObject::Membership(ref x) => &x.id,
+            AnyObject::Meeting(ref x) => &x.id,
+            AnyObject::AgendaItem(ref x) => &x.id,
+            AnyObject::Paper(ref x) => &x.id,
+            AnyObject::Consultation(ref x) => &x.id,
+            AnyObject::File(ref x) => &x.id,
+            AnyObject::Location(ref x) => &x.id,
+        }
+    }
+}
+
+/// Converts an object to the struct of its type. Fails if the object has a different type
+pub fn from_json<T: OParlObject>(object: &JsonValue) -> Result<T, Box<Error>> {
+    let (_, oparl_type) = split_type_url(&object["type"].to_string())?;
+    if oparl_type != T::type_name() {
+        return Err(From::from(format!("Expected a {}, but {} is a {}", T::type_name(), object["id"], oparl_type)));
+    }
+    Ok(serde_json::from_str(&object.dump())?)
+}
+
+/// Converts a typed object back to json
+pub fn to_json<T: OParlObject>(object: &T) -> Result<JsonValue, Box<Error>> {
+    Ok(json::parse(&serde_json::to_string(object)?)?)
+}
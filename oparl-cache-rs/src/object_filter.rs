@@ -0,0 +1,82 @@
+/// Include and exclude rules for caching only a part of a server
+///
+/// The rules are checked whenever an external list is found. A list is followed if it passes
+/// all rules, where an empty include rule lets everything pass:
+///
+///  - The bodies are matched against the id of the body a list belongs to, i.e. the lists of a
+///    Body object and of objects referencing a body. Lists without a body pass
+///  - The types are matched against the type of the objects in the list, e.g. `Paper`
+///  - The lists are matched against the property name of the list, e.g. `paper`
+///
+/// The `body` list of the System object is exempt from the include rules as it's required for
+/// finding the lists of the bodies.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct ObjectFilter {
+    /// Only follow the lists of these bodies
+    pub include_bodies: Vec<String>,
+    /// Don't follow the lists of these bodies
+    pub exclude_bodies: Vec<String>,
+    /// Only follow lists with objects of these types
+    pub include_types: Vec<String>,
+    /// Don't follow lists with objects of these types
+    pub exclude_types: Vec<String>,
+    /// Only follow lists with these property names
+    pub include_lists: Vec<String>,
+    /// Don't follow lists with these property names
+    pub exclude_lists: Vec<String>,
+}
+
+impl ObjectFilter {
+    /// Returns true if no rule is set
+    pub fn is_empty(&self) -> bool {
+        *self == ObjectFilter::default()
+    }
+
+    /// Decides whether the list under `property` of an object belonging to `body` should be
+    /// followed
+    pub fn allows_list(&self, property: &str, body: Option<&str>) -> bool {
+        let oparl_type = list_item_type(property);
+        let structural = property == "body";
+
+        if !structural && !included(&self.include_lists, property) ||
+           self.exclude_lists.iter().any(|x| x == property) {
+            return false;
+        }
+
+        if !structural && !included(&self.include_types, &oparl_type) ||
+           self.exclude_types.iter().any(|x| *x == oparl_type) {
+            return false;
+        }
+
+        if let Some(body) = body {
+            if !included(&self.include_bodies, body) ||
+               self.exclude_bodies.iter().any(|x| x == body) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// An empty include rule includes everything
+fn included(rule: &[String], value: &str) -> bool {
+    rule.is_empty() || rule.iter().any(|x| x == value)
+}
+
+/// Derives the type of the objects in a list from the property name, e.g. `Paper` from `paper` or
+/// `Location` from `locationList`, as OParl names the list properties after the types
+pub fn list_item_type(property: &str) -> String {
+    let property = if property.ends_with("List") {
+        &property[..property.len() - "List".len()]
+    } else {
+        property
+    };
+
+    let mut chars = property.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use json::JsonValue;
+use reqwest::Url;
+use serde_json;
+
+use schema::{split_type_url, is_external_list};
+use storage::Storage;
+
+const RDF: &'static str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+const XSD: &'static str = "http://www.w3.org/2001/XMLSchema#";
+
+/// The serialization of the exported triples
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RdfFormat {
+    /// One triple per line, see https://www.w3.org/TR/n-triples/
+    NTriples,
+    /// Triples grouped by subject with prefixes, see https://www.w3.org/TR/turtle/
+    Turtle,
+}
+
+impl RdfFormat {
+    /// Parses `ntriples` or `turtle`
+    pub fn parse(format: &str) -> Result<RdfFormat, Box<Error>> {
+        match format {
+            "ntriples" | "nt" => Ok(RdfFormat::NTriples),
+            "turtle" | "ttl" => Ok(RdfFormat::Turtle),
+            _ => Err(From::from(format!("Unknown rdf format: {}", format))),
+        }
+    }
+}
+
+/// Maps OParl types and properties to rdf classes and predicates
+///
+/// Without a mapping, the type url is used as class and the property is appended to the schema
+/// url of the type, e.g. `https://schema.oparl.org/1.0/name`. Values may use the prefixes, e.g.
+/// `foaf:name`
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct RdfMapping {
+    /// Prefixes for the mapping and the turtle output, e.g. `foaf` => `http://xmlns.com/foaf/0.1/`
+    pub prefixes: BTreeMap<String, String>,
+    /// Maps OParl types to classes, e.g. `Person` => `foaf:Person`
+    pub classes: BTreeMap<String, String>,
+    /// Maps properties to predicates, either for all types as `name` or for one type as
+    /// `Person.name`, which takes precedence
+    pub predicates: BTreeMap<String, String>,
+    /// Properties that aren't exported, given like the keys of `predicates`
+    pub skip: Vec<String>,
+}
+
+impl RdfMapping {
+    /// Reads a mapping from a json file
+    pub fn load(path: &Path) -> Result<RdfMapping, Box<Error>> {
+        Ok(serde_json::from_reader(File::open(path)?)?)
+    }
+
+    /// Resolves `prefix:name` against the prefixes
+    fn expand(&self, iri: &str) -> String {
+        let mut parts = iri.splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some(prefix), Some(name)) if !name.starts_with("//") => {
+                match self.all_prefixes().get(prefix) {
+                    Some(namespace) => format!("{}{}", namespace, name),
+                    None => iri.to_string(),
+                }
+            }
+            _ => iri.to_string(),
+        }
+    }
+
+    /// The configured prefixes together with `rdf` and `xsd`
+    fn all_prefixes(&self) -> BTreeMap<String, String> {
+        let mut prefixes = self.prefixes.clone();
+        prefixes.entry("rdf".to_string()).or_insert_with(|| RDF.to_string());
+        prefixes.entry("xsd".to_string()).or_insert_with(|| XSD.to_string());
+        prefixes
+    }
+
+    fn predicate(&self, oparl_type: &str, property: &str) -> Option<String> {
+        self.predicates
+            .get(&format!("{}.{}", oparl_type, property))
+            .or_else(|| self.predicates.get(property))
+            .map(|x| self.expand(x))
+    }
+
+    fn is_skipped(&self, oparl_type: &str, property: &str) -> bool {
+        self.skip.iter().any(|x| *x == property || *x == format!("{}.{}", oparl_type, property))
+    }
+}
+
+/// The object of a triple
+#[derive(Clone, Debug, PartialEq)]
+pub enum Term {
+    /// A reference to another resource
+    Iri(String),
+    /// A value with its datatype, `None` meaning a plain string
+    Literal(String, Option<String>),
+}
+
+/// A statement about a cached object
+#[derive(Clone, Debug, PartialEq)]
+pub struct Triple {
+    /// The id of the object
+    pub subject: String,
+    /// The predicate of the property
+    pub predicate: String,
+    /// The value of the property
+    pub object: Term,
+}
+
+/// Converts a cached object to triples. References, urls and embedded objects become iris,
+/// everything else typed literals. External lists are left out, as their items reference the
+/// object anyway
+pub fn object_triples(object: &JsonValue, schema: &JsonValue, mapping: &RdfMapping) -> Result<Vec<Triple>, Box<Error>> {
+    let subject = object["id"].as_str().ok_or("The object has no id")?.to_string();
+    let type_url = object["type"].as_str().ok_or("The object has no type")?;
+    let (version, oparl_type) = split_type_url(type_url)?;
+    let namespace = &type_url[..type_url.trim_right_matches('/').len() - oparl_type.len()];
+    let properties = &schema[&version][&oparl_type]["properties"];
+
+    let class = match mapping.classes.get(&oparl_type) {
+        Some(class) => mapping.expand(class),
+        None => type_url.to_string(),
+    };
+    let mut triples = vec![Triple {
+                               subject: subject.clone(),
+                               predicate: format!("{}type", RDF),
+                               object: Term::Iri(class),
+                           }];
+
+    for (property, value) in object.entries() {
+        let definition = &properties[property];
+        if property == "id" || property == "type" || value.is_null() ||
+           mapping.is_skipped(&oparl_type, property) ||
+           is_external_list(definition) {
+            continue;
+        }
+
+        let predicate = mapping.predicate(&oparl_type, property)
+            .unwrap_or_else(|| format!("{}{}", namespace, property));
+
+        let (values, definition) = if value.is_array() {
+            (value.members().collect::<Vec<_>>(), &definition["items"])
+        } else {
+            (vec![value], definition)
+        };
+
+        for value in values {
+            triples.push(Triple {
+                subject: subject.clone(),
+                predicate: predicate.clone(),
+                object: term(value, definition),
+            });
+        }
+    }
+
+    Ok(triples)
+}
+
+/// Converts a value using the json schema of its property
+fn term(value: &JsonValue, definition: &JsonValue) -> Term {
+    let is_iri = definition["type"] == "object" || definition["references"].is_string() ||
+                 definition["format"] == "url";
+    let xsd = |name: &str| Some(format!("{}{}", XSD, name));
+
+    match *value {
+        JsonValue::Short(_) | JsonValue::String(_) => {
+            let string = value.as_str().unwrap_or("").to_string();
+            if is_iri {
+                return Term::Iri(string);
+            }
+            let datatype = match definition["format"].as_str() {
+                Some("date-time") => xsd("dateTime"),
+                Some("date") => xsd("date"),
+                _ => None,
+            };
+            Term::Literal(string, datatype)
+        }
+        JsonValue::Number(_) => {
+            let datatype = if definition["type"] == "integer" { xsd("integer") } else { xsd("decimal") };
+            Term::Literal(value.dump(), datatype)
+        }
+        JsonValue::Boolean(_) => Term::Literal(value.dump(), xsd("boolean")),
+        // e.g. the geojson of a location
+        _ => Term::Literal(value.dump(), Some(format!("{}JSON", RDF))),
+    }
+}
+
+/// Writes the triples of all cached objects of the server with the given entrypoint to `output`
+/// and returns the number of objects
+pub fn export_rdf<S: Storage, W: Write>(storage: &S,
+                                        entrypoint: &Url,
+                                        mapping: &RdfMapping,
+                                        format: RdfFormat,
+                                        output: &mut W)
+                                        -> Result<usize, Box<Error>> {
+    if format == RdfFormat::Turtle {
+        for (prefix, namespace) in mapping.all_prefixes() {
+            writeln!(output, "@prefix {}: <{}> .", prefix, escape_iri(&namespace))?;
+        }
+        writeln!(output)?;
+    }
+
+    let mut count = 0;
+    for object in storage.iter_objects(entrypoint, None) {
+        let triples = object_triples(&object?, storage.get_schema(), mapping)?;
+        match format {
+            RdfFormat::NTriples => write_ntriples(&triples, output)?,
+            RdfFormat::Turtle => write_turtle(&triples, mapping, output)?,
+        }
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+/// Writes one triple per line
+pub fn write_ntriples<W: Write>(triples: &[Triple], output: &mut W) -> Result<(), Box<Error>> {
+    for triple in triples {
+        writeln!(output,
+                 "<{}> <{}> {} .",
+                 escape_iri(&triple.subject),
+                 escape_iri(&triple.predicate),
+                 format_term(&triple.object, &BTreeMap::new()))?;
+    }
+    Ok(())
+}
+
+/// Writes the triples of one subject as a turtle block, compacting the iris with the prefixes
+pub fn write_turtle<W: Write>(triples: &[Triple], mapping: &RdfMapping, output: &mut W) -> Result<(), Box<Error>> {
+    let prefixes = mapping.all_prefixes();
+    let subject = match triples.first() {
+        Some(triple) => &triple.subject,
+        None => return Ok(()),
+    };
+
+    writeln!(output, "{}", compact(subject, &prefixes))?;
+    for (i, triple) in triples.iter().enumerate() {
+        let predicate = if triple.predicate == format!("{}type", RDF) {
+            "a".to_string()
+        } else {
+            compact(&triple.predicate, &prefixes)
+        };
+        writeln!(output,
+                 "    {} {} {}",
+                 predicate,
+                 format_term(&triple.object, &prefixes),
+                 if i + 1 == triples.len() { "." } else { ";" })?;
+    }
+    writeln!(output)?;
+    Ok(())
+}
+
+fn format_term(term: &Term, prefixes: &BTreeMap<String, String>) -> String {
+    match *term {
+        Term::Iri(ref iri) => compact(iri, prefixes),
+        Term::Literal(ref value, None) => format!("\"{}\"", escape_literal(value)),
+        Term::Literal(ref value, Some(ref datatype)) => {
+            format!("\"{}\"^^{}", escape_literal(value), compact(datatype, prefixes))
+        }
+    }
+}
+
+/// Writes `iri` as prefixed name if possible, otherwise in angle brackets
+fn compact(iri: &str, prefixes: &BTreeMap<String, String>) -> String {
+    for (prefix, namespace) in prefixes {
+        if iri.starts_with(namespace.as_str()) {
+            let name = &iri[namespace.len()..];
+            if !name.is_empty() && name.chars().all(|x| x.is_alphanumeric() || x == '_' || x == '-') {
+                return format!("{}:{}", prefix, name);
+            }
+        }
+    }
+    format!("<{}>", escape_iri(iri))
+}
+
+/// Escapes the characters that aren't allowed in iris
+fn escape_iri(iri: &str) -> String {
+    iri.chars()
+        .map(|x| match x {
+            '\u{0}'...' ' | '<' | '>' | '"' | '{' | '}' | '|' | '^' | '`' | '\\' => format!("\\u{:04X}", x as u32),
+            _ => x.to_string(),
+        })
+        .collect()
+}
+
+fn escape_literal(value: &str) -> String {
+    value.replace("\\", "\\\\")
+        .replace("\"", "\\\"")
+        .replace("\n", "\\n")
+        .replace("\r", "\\r")
+}
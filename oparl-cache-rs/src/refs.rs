@@ -0,0 +1,213 @@
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::error::Error;
+
+use json::JsonValue;
+use reqwest::Url;
+use reqwest::IntoUrl;
+
+use schema::split_type_url;
+use storage::{CacheIter, Storage};
+
+/// An object referencing another one
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Reference {
+    /// The id of the referencing object
+    pub source: String,
+    /// The type of the referencing object without the schema url, e.g. `Consultation`
+    pub source_type: String,
+    /// The property containing the reference, e.g. `paper`
+    pub property: String,
+}
+
+/// A storage that keeps an index of which objects reference an object
+///
+/// The index is maintained by `Cacher::parse_object`, so it covers everything in the cache
+pub trait ReferenceIndex: Storage {
+    /// Records that `reference` points to `target`
+    fn add_reference(&self, target: &Url, reference: &Reference) -> Result<(), Box<Error>>;
+
+    /// Removes a reference recorded with `add_reference`
+    fn remove_reference(&self, target: &Url, reference: &Reference) -> Result<(), Box<Error>>;
+
+    /// Removes `removed` from and adds `added` to the references of `target` in one step.
+    /// Storages should override this to update the index of a target only once per object
+    fn change_references(&self,
+                         target: &Url,
+                         removed: &[Reference],
+                         added: &[Reference])
+                         -> Result<(), Box<Error>> {
+        for reference in removed {
+            self.remove_reference(target, reference)?;
+        }
+        for reference in added {
+            self.add_reference(target, reference)?;
+        }
+        Ok(())
+    }
+
+    /// Returns all objects referencing `target`, sorted by their id
+    fn get_references(&self, target: &Url) -> Result<Vec<Reference>, Box<Error>>;
+
+    /// Returns the ids of the objects of type `source_type` that reference `target`, e.g. the
+    /// consultations of a paper
+    fn get_referencing(&self, target: &Url, source_type: &str) -> Result<Vec<String>, Box<Error>> {
+        Ok(self.get_references(target)?
+            .into_iter()
+            .filter(|x| x.source_type == source_type)
+            .map(|x| x.source)
+            .collect())
+    }
+
+    /// Updates the index after the object `source` changed from `old` to `new`. Both are given
+    /// as returned by `collect_references`, i.e. as (target, property). The changes are grouped
+    /// by the target, so that each target is updated once
+    fn update_references(&self,
+                         source: &Url,
+                         source_type: &str,
+                         old: &[(String, String)],
+                         new: &[(String, String)])
+                         -> Result<(), Box<Error>> {
+        let reference = |property: &str| {
+            Reference {
+                source: source.to_string(),
+                source_type: source_type.to_string(),
+                property: property.to_string(),
+            }
+        };
+
+        // target => (removed, added)
+        let mut changes: BTreeMap<&str, (Vec<Reference>, Vec<Reference>)> = BTreeMap::new();
+        for &(ref target, ref property) in old {
+            if !new.contains(&(target.clone(), property.clone())) {
+                changes.entry(target.as_str()).or_insert_with(Default::default).0.push(reference(property));
+            }
+        }
+        for &(ref target, ref property) in new {
+            if !old.contains(&(target.clone(), property.clone())) {
+                changes.entry(target.as_str()).or_insert_with(Default::default).1.push(reference(property));
+            }
+        }
+
+        for (target, (removed, added)) in changes {
+            self.change_references(&target.into_url()?, &removed, &added)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the references of an object as (target, property), where `properties` is the schema
+/// of its type. Embedded objects must already be replaced by their id, as it is done before
+/// writing them to the cache. External lists aren't included
+pub fn collect_references(object: &JsonValue, properties: &JsonValue) -> Vec<(String, String)> {
+    let mut references = vec![];
+    for (key, value) in object.entries() {
+        collect_entry(key, value, &properties[key], &mut references);
+    }
+    references
+}
+
+fn collect_entry(key: &str,
+                 value: &JsonValue,
+                 definition: &JsonValue,
+                 references: &mut Vec<(String, String)>) {
+    if definition["type"] == "array" {
+        for i in value.members() {
+            collect_entry(key, i, &definition["items"], references);
+        }
+        return;
+    }
+
+    let is_reference = definition["type"] == "object" ||
+                       (definition["references"].is_string() && definition["references"] != "externalList");
+    if !is_reference {
+        return;
+    }
+
+    if let Some(target) = value.as_str() {
+        let reference = (target.to_string(), key.to_string());
+        if target.into_url().is_ok() && !references.contains(&reference) {
+            references.push(reference);
+        }
+    }
+}
+
+/// Iterates over the cached objects that can be reached from the System object or the external
+/// lists of the server with the entrypoint `server`. Unlike `Storage::iter_objects`, this
+/// excludes objects that were removed from all lists and includes the embedded objects stored
+/// outside the folder of the entrypoint. References to objects that aren't cached are skipped
+pub fn iter_reachable<'s, S: Storage>(storage: &'s S, server: &Url) -> CacheIter<'s, JsonValue> {
+    let mut reachable = ReachableObjects {
+        storage: storage,
+        lists: storage.iter_lists(server),
+        queue: VecDeque::new(),
+        visited: HashSet::new(),
+    };
+    reachable.push(server.as_str());
+    Box::new(reachable)
+}
+
+/// Walks the references breadth first, reading the next list once the queue is empty
+struct ReachableObjects<'s, S: 's + Storage> {
+    storage: &'s S,
+    lists: CacheIter<'s, (Url, JsonValue)>,
+    queue: VecDeque<String>,
+    visited: HashSet<String>,
+}
+
+impl<'s, S: 's + Storage> ReachableObjects<'s, S> {
+    fn push(&mut self, id: &str) {
+        if self.visited.insert(id.to_string()) {
+            self.queue.push_back(id.to_string());
+        }
+    }
+
+    /// Reads an object and queues its references. Returns `None` for objects that aren't cached
+    fn visit(&mut self, id: &str) -> Result<Option<JsonValue>, Box<Error>> {
+        let storage = self.storage;
+        let object = match id.into_url().ok().and_then(|x| storage.get(&x).ok()) {
+            Some(object) => object,
+            None => return Ok(None),
+        };
+        // External lists are stored as arrays of ids
+        if !object.is_object() {
+            return Ok(None);
+        }
+        let (version, oparl_type) = split_type_url(&object["type"].to_string())?;
+        let properties = &storage.get_schema()[&version][&oparl_type]["properties"];
+        for (target, _) in collect_references(&object, properties) {
+            self.push(&target);
+        }
+        Ok(Some(object))
+    }
+}
+
+impl<'s, S: 's + Storage> Iterator for ReachableObjects<'s, S> {
+    type Item = Result<JsonValue, Box<Error>>;
+
+    fn next(&mut self) -> Option<Result<JsonValue, Box<Error>>> {
+        loop {
+            let id = match self.queue.pop_front() {
+                Some(id) => id,
+                None => {
+                    match self.lists.next() {
+                        Some(Ok((_, ids))) => {
+                            for id in ids.members().filter_map(|x| x.as_str()) {
+                                self.push(id);
+                            }
+                            continue;
+                        }
+                        Some(Err(err)) => return Some(Err(err)),
+                        None => return None,
+                    }
+                }
+            };
+
+            match self.visit(&id) {
+                Ok(Some(object)) => return Some(Ok(object)),
+                Ok(None) => continue,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs::{File, create_dir_all};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread::sleep;
+use std::time::Duration;
+
+use json;
+use json::JsonValue;
+use reqwest::Url;
+use serde_json;
+
+use server::Server;
+
+/// The file in a fixture directory that lists the recorded exchanges
+pub const FIXTURE_INDEX_FILE: &'static str = "index.json";
+
+/// A single recorded request
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Exchange {
+    /// The file with the response body, relative to the fixture directory
+    pub file: Option<String>,
+    /// The error message if the request failed
+    pub error: Option<String>,
+}
+
+/// The contents of the index file of a fixture directory
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct FixtureIndex {
+    /// The entrypoint of the recorded server
+    pub entrypoint: Option<Url>,
+    /// The recorded exchanges keyed by the requested url
+    pub exchanges: BTreeMap<String, Exchange>,
+}
+
+impl FixtureIndex {
+    /// Reads the index of the fixture directory `directory`
+    pub fn load(directory: &Path) -> Result<FixtureIndex, Box<Error>> {
+        let file = File::open(directory.join(FIXTURE_INDEX_FILE))?;
+        Ok(serde_json::from_reader(file)?)
+    }
+}
+
+/// Wraps a server and writes every exchange to a fixture directory, which can be served by a
+/// `ReplayServer` later on
+///
+/// The index is rewritten after every request, so the directory is usable even if the run is
+/// aborted
+pub struct RecordingServer<T: Server> {
+    server: T,
+    directory: PathBuf,
+    index: Mutex<FixtureIndex>,
+}
+
+impl<T: Server> RecordingServer<T> {
+    /// Creates a new `RecordingServer` writing to `directory`. Exchanges that were recorded
+    /// before are kept
+    pub fn new(server: T, directory: PathBuf) -> Result<RecordingServer<T>, Box<Error>> {
+        create_dir_all(&directory)?;
+        let mut index = FixtureIndex::load(&directory).unwrap_or_default();
+        index.entrypoint = Some(server.get_entrypoint());
+
+        Ok(RecordingServer {
+            server: server,
+            directory: directory,
+            index: Mutex::new(index),
+        })
+    }
+
+    /// Returns the wrapped server
+    pub fn into_inner(self) -> T {
+        self.server
+    }
+
+    fn record(&self, url: &Url, result: &Result<JsonValue, Box<Error>>) -> Result<(), Box<Error>> {
+        let mut index = self.index.lock().unwrap();
+
+        let exchange = match *result {
+            Ok(ref json) => {
+                // Requesting a url again overwrites the old response
+                let file = match index.exchanges.get(url.as_str()).and_then(|x| x.file.clone()) {
+                    Some(file) => file,
+                    None => {
+                        (index.exchanges.len()..)
+                            .map(|x| format!("{:05}.json", x))
+                            .find(|x| !index.exchanges.values().any(|y| y.file.as_ref() == Some(x)))
+                            .unwrap()
+                    }
+                };
+                let mut output = File::create(self.directory.join(&file))?;
+                json.write_pretty(&mut output, 4)?;
+                Exchange { file: Some(file), error: None }
+            }
+            Err(ref err) => Exchange { file: None, error: Some(err.to_string()) },
+        };
+
+        index.exchanges.insert(url.to_string(), exchange);
+
+        let file = File::create(self.directory.join(FIXTURE_INDEX_FILE))?;
+        serde_json::to_writer_pretty(file, &*index)?;
+        Ok(())
+    }
+}
+
+impl<T: Server> Server for RecordingServer<T> {
+    fn get_json(&self, url: Url) -> Result<JsonValue, Box<Error>> {
+        let result = self.server.get_json(url.clone());
+        if let Err(err) = self.record(&url, &result) {
+            println!("Failed to record the response for {}: {}", url, err);
+        }
+        result
+    }
+
+    fn get_entrypoint(&self) -> Url {
+        self.server.get_entrypoint()
+    }
+}
+
+/// An error a `ReplayServer` returns instead of the recorded response
+#[derive(Clone, Debug, PartialEq)]
+pub enum Fault {
+    /// The server answers with this http status code
+    Status(u16),
+    /// The request times out after the given time
+    Timeout(Duration),
+    /// The response is cut off in the middle and can't be parsed
+    MalformedJson,
+}
+
+/// Serves the exchanges recorded by a `RecordingServer`
+///
+/// Unknown urls return an error instead of panicking, just like a real server would return a 404
+pub struct ReplayServer {
+    entrypoint: Url,
+    directory: PathBuf,
+    exchanges: HashMap<String, Exchange>,
+    faults: HashMap<String, Fault>,
+}
+
+impl ReplayServer {
+    /// Loads the fixture directory `directory`
+    pub fn new(directory: PathBuf) -> Result<ReplayServer, Box<Error>> {
+        let index = FixtureIndex::load(&directory)?;
+        let entrypoint = index.entrypoint.ok_or("The fixture index doesn't contain an entrypoint")?;
+
+        Ok(ReplayServer {
+            entrypoint: entrypoint,
+            directory: directory,
+            exchanges: index.exchanges.into_iter().collect(),
+            faults: HashMap::new(),
+        })
+    }
+
+    /// Makes requests for `url` fail with `fault`
+    pub fn add_fault(&mut self, url: &Url, fault: Fault) {
+        self.faults.insert(url.to_string(), fault);
+    }
+
+    /// Makes requests for `url` fail with `fault`
+    pub fn with_fault(mut self, url: &Url, fault: Fault) -> ReplayServer {
+        self.add_fault(url, fault);
+        self
+    }
+
+    /// Removes all injected faults
+    pub fn clear_faults(&mut self) {
+        self.faults.clear();
+    }
+
+    fn read_body(&self, url: &Url) -> Result<String, Box<Error>> {
+        let exchange = self.exchanges
+            .get(url.as_str())
+            .ok_or_else(|| format!("No recorded response for {}", url))?;
+
+        if let Some(ref error) = exchange.error {
+            return Err(From::from(error.clone()));
+        }
+
+        let file = exchange.file.as_ref().ok_or_else(|| format!("No recorded response for {}", url))?;
+        let mut body = String::new();
+        File::open(self.directory.join(file))?.read_to_string(&mut body)?;
+        Ok(body)
+    }
+}
+
+impl Server for ReplayServer {
+    fn get_json(&self, url: Url) -> Result<JsonValue, Box<Error>> {
+        let body = match self.faults.get(url.as_str()) {
+            Some(&Fault::Status(status)) => {
+                return Err(From::from(format!("Bad status code returned for request: {}", status)));
+            }
+            Some(&Fault::Timeout(duration)) => {
+                sleep(duration);
+                return Err(From::from(format!("The request for {} timed out", url)));
+            }
+            Some(&Fault::MalformedJson) => {
+                let body = self.read_body(&url)?;
+                let half = body.len() / 2;
+                // Don't cut a multibyte character in half
+                let cut = (0..half + 1).rev().find(|&x| body.is_char_boundary(x)).unwrap_or(0);
+                body[..cut].to_string()
+            }
+            None => self.read_body(&url)?,
+        };
+
+        Ok(json::parse(&body)?)
+    }
+
+    fn get_entrypoint(&self) -> Url {
+        self.entrypoint.clone()
+    }
+}
@@ -0,0 +1,102 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use json;
+use json::JsonValue;
+
+/// The version assumed for schema folders without version subfolders and for type urls without
+/// a version
+pub const DEFAULT_VERSION: &'static str = "1.0";
+
+/// Loads the schema files of all versions into one json dict with the structure
+/// `{<version>: {<type>: <schema>}}`
+///
+/// `schema_dir` either contains one folder per version (e.g. `1.0/` and `1.1/`) or directly the
+/// schema files, which are then taken as `DEFAULT_VERSION`. Other folders such as `.git` are
+/// ignored
+pub fn load_schema(schema_dir: &Path) -> Result<JsonValue, Box<Error>> {
+    let mut schema = JsonValue::new_object();
+
+    let mut version_dirs = vec![];
+    for i in schema_dir.read_dir()? {
+        let path = i?.path();
+        let version = match path.file_name().and_then(|x| x.to_str()) {
+            Some(name) if path.is_dir() && is_version(name) => name.to_string(),
+            _ => continue,
+        };
+        version_dirs.push((version, path));
+    }
+
+    if version_dirs.is_empty() {
+        schema[DEFAULT_VERSION] = load_version(schema_dir)?;
+    } else {
+        for (version, path) in version_dirs {
+            schema[version] = load_version(&path)?;
+        }
+    }
+
+    Ok(schema)
+}
+
+/// Loads the schema files of a single version, keyed by the type
+fn load_version(dir: &Path) -> Result<JsonValue, Box<Error>> {
+    let mut schema = JsonValue::new_object();
+    for i in dir.read_dir()? {
+        let path = i?.path();
+        if path.extension().map_or(true, |x| x != "json") {
+            continue;
+        }
+
+        let mut f: File = File::open(path)?;
+        let mut s = String::new();
+        f.read_to_string(&mut s)?;
+        let x = json::parse(&s)?;
+        let y = x["title"].to_string();
+        schema[y] = x;
+    }
+
+    if !schema.has_key("System") {
+        return Err(From::from(format!("No schema for System found in {}", dir.display())));
+    }
+
+    Ok(schema)
+}
+
+/// Splits a type url such as `https://schema.oparl.org/1.1/Paper` into version and type
+pub fn split_type_url(type_url: &str) -> Result<(String, String), Box<Error>> {
+    let mut segments = type_url.trim_right_matches("/").rsplit("/");
+    let oparl_type = segments.next().ok_or("Invalid type url")?;
+
+    let version = match segments.next() {
+        Some(version) if is_version(version) => version,
+        _ => DEFAULT_VERSION,
+    };
+
+    Ok((version.to_string(), oparl_type.to_string()))
+}
+
+/// Extracts the version from the `oparlVersion` of a System object, e.g. `1.1` from
+/// `https://schema.oparl.org/1.1/`
+pub fn version_from_url(oparl_version: &str) -> Option<String> {
+    match oparl_version.trim_right_matches("/").rsplit("/").next() {
+        Some(version) if is_version(version) => Some(version.to_string()),
+        _ => None,
+    }
+}
+
+/// Version segments look like `1.0`
+fn is_version(segment: &str) -> bool {
+    let mut parts = segment.split('.');
+    let major = parts.next().unwrap_or("");
+    let minor = parts.next().unwrap_or("");
+    parts.next().is_none() && !major.is_empty() && !minor.is_empty() &&
+    major.chars().chain(minor.chars()).all(|x| x.is_digit(10))
+}
+
+/// Decides whether a property of an object contains the url of an external list, given the
+/// definition of the property in the schema of the object's version
+pub fn is_external_list(property_def: &JsonValue) -> bool {
+    property_def["references"] == "externalList"
+}
@@ -1,8 +1,12 @@
 use json;
 use json::JsonValue;
 use std::error::Error;
+use std::sync::Mutex;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
 use reqwest;
 use reqwest::Url;
+use reqwest::header::{Authorization, Basic, Bearer};
 
 use std::io::Read;
 
@@ -16,15 +20,108 @@ pub trait Server: Send + Sync {
     fn get_entrypoint(&self) -> Url;
 }
 
+/// The credentials sent to a server
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Auth {
+    /// HTTP basic authentication
+    Basic {
+        /// The user name
+        username: String,
+        /// The password
+        #[serde(default)]
+        password: Option<String>,
+    },
+    /// A token sent as `Authorization: Bearer <token>`
+    Bearer {
+        /// The token
+        token: String,
+    },
+}
+
 /// A OParl server that is defined by its entrypoint url
 pub struct CommonServer {
     entrypoint: Url,
+    auth: Option<Auth>,
+    /// The minimal time between the starts of two requests
+    interval: Option<Duration>,
+    /// The earliest time the next request may start. Shared by all workers
+    next_request: Mutex<Instant>,
 }
 
 impl CommonServer {
     /// Creates a new CommonServer
     pub fn new(entrypoint: Url) -> CommonServer {
-        CommonServer { entrypoint: entrypoint }
+        CommonServer {
+            entrypoint: entrypoint,
+            auth: None,
+            interval: None,
+            next_request: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Sends the credentials with every request
+    pub fn with_auth(mut self, auth: Auth) -> CommonServer {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Limits the requests of all workers together to `requests_per_second`
+    pub fn with_rate_limit(mut self, requests_per_second: f64) -> CommonServer {
+        if requests_per_second > 0.0 {
+            let nanos = (1e9 / requests_per_second) as u64;
+            self.interval = Some(Duration::new(nanos / 1_000_000_000, (nanos % 1_000_000_000) as u32));
+        }
+        self
+    }
+
+    /// Blocks until the rate limit allows the next request
+    fn wait_for_rate_limit(&self) {
+        let interval = match self.interval {
+            Some(interval) => interval,
+            None => return,
+        };
+        // The lock is held while sleeping, so the waiting workers are served one by one
+        let mut next_request = self.next_request.lock().unwrap();
+        let now = Instant::now();
+        if *next_request > now {
+            sleep(*next_request - now);
+        }
+        *next_request = Instant::now() + interval;
+    }
+
+    /// Sends a GET request with the credentials, respecting the rate limit
+    fn request(&self, url: Url) -> Result<reqwest::Response, Box<Error>> {
+        self.wait_for_rate_limit();
+
+        let client = reqwest::Client::new()?;
+        let mut request = client.get(url);
+        match self.auth {
+            Some(Auth::Basic { ref username, ref password }) => {
+                request = request.header(Authorization(Basic {
+                    username: username.clone(),
+                    password: password.clone(),
+                }));
+            }
+            Some(Auth::Bearer { ref token }) => {
+                request = request.header(Authorization(Bearer { token: token.clone() }));
+            }
+            None => {}
+        }
+        let reponse = request.send()?;
+        if !reponse.status().is_success() {
+            return Err(From::from(format!("Bad status code returned for request: {}",
+                                          reponse.status())));
+        }
+        Ok(reponse)
+    }
+
+    /// Downloads a file such as the document of a File object
+    pub fn download(&self, url: &Url) -> Result<Vec<u8>, Box<Error>> {
+        println!("Downloading: {:?}", url);
+        let mut content = vec![];
+        self.request(url.clone())?.read_to_end(&mut content)?;
+        Ok(content)
     }
 }
 
@@ -32,11 +129,7 @@ impl Server for CommonServer {
     /// Downloads an object and returns its parsed json
     fn get_json(&self, url: Url) -> Result<JsonValue, Box<Error>> {
         println!("Loading: {:?}", &url);
-        let mut reponse = reqwest::get(url)?;
-        if !reponse.status().is_success() {
-            return Err(From::from(format!("Bad status code returned for request: {}",
-                                          reponse.status())));
-        }
+        let mut reponse = self.request(url)?;
 
         let mut json_string = String::new();
         reponse.read_to_string(&mut json_string)?;
@@ -0,0 +1,87 @@
+use std::error::Error;
+use std::fs::{File, create_dir_all, remove_file};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+use json::{self, JsonValue};
+use reqwest::Url;
+
+use file_storage::FileStorage;
+use refs::iter_reachable;
+
+/// The folder inside the folder of a server that contains its snapshots
+pub const SNAPSHOTS_DIR: &'static str = "snapshots";
+/// The file extension of the snapshots, which store one object per line
+pub const SNAPSHOT_EXTENSION: &'static str = ".jsonl";
+/// The number of snapshots kept per server if no other limit is set
+pub const DEFAULT_MAX_SNAPSHOTS: usize = 30;
+
+fn snapshot_dir(storage: &FileStorage, entrypoint: &Url) -> PathBuf {
+    storage.url_to_path(entrypoint, "").join(SNAPSHOTS_DIR)
+}
+
+/// Writes the cached objects reachable from the server to a snapshot called `name`, which is the
+/// start of the sync run when taken by the `FileStorage`. Returns the number of objects
+pub fn write_snapshot(storage: &FileStorage, entrypoint: &Url, name: &str) -> Result<usize, Box<Error>> {
+    let dir = snapshot_dir(storage, entrypoint);
+    create_dir_all(&dir)?;
+    let mut file = File::create(dir.join(name.to_string() + SNAPSHOT_EXTENSION))?;
+
+    let mut count = 0;
+    for object in iter_reachable(storage, entrypoint) {
+        writeln!(file, "{}", object?.dump())?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Returns the names of the snapshots of the server, the latest one last
+pub fn list_snapshots(storage: &FileStorage, entrypoint: &Url) -> Result<Vec<String>, Box<Error>> {
+    let dir = snapshot_dir(storage, entrypoint);
+    if !dir.is_dir() {
+        return Ok(vec![]);
+    }
+
+    let mut names = vec![];
+    for entry in dir.read_dir()? {
+        let name = entry?.file_name().to_string_lossy().to_string();
+        if name.ends_with(SNAPSHOT_EXTENSION) {
+            names.push(name.trim_right_matches(SNAPSHOT_EXTENSION).to_string());
+        }
+    }
+    // The names start with the UTC timestamp of the run
+    names.sort();
+    Ok(names)
+}
+
+/// Deletes the oldest snapshots of the server, so that at most `max_snapshots` are left. `0`
+/// keeps all snapshots. Returns the number of deleted snapshots
+pub fn prune_snapshots(storage: &FileStorage, entrypoint: &Url, max_snapshots: usize) -> Result<usize, Box<Error>> {
+    let names = list_snapshots(storage, entrypoint)?;
+    if max_snapshots == 0 || names.len() <= max_snapshots {
+        return Ok(0);
+    }
+
+    let dir = snapshot_dir(storage, entrypoint);
+    let outdated = &names[..names.len() - max_snapshots];
+    for name in outdated {
+        remove_file(dir.join(name.to_string() + SNAPSHOT_EXTENSION))?;
+    }
+    Ok(outdated.len())
+}
+
+/// Reads the objects of a snapshot
+pub fn read_snapshot(storage: &FileStorage, entrypoint: &Url, name: &str) -> Result<Vec<JsonValue>, Box<Error>> {
+    let path = snapshot_dir(storage, entrypoint).join(name.to_string() + SNAPSHOT_EXTENSION);
+    let file = File::open(&path)
+        .map_err(|err| format!("No snapshot {} of {}: {}", name, entrypoint, err))?;
+
+    let mut objects = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            objects.push(json::parse(&line)?);
+        }
+    }
+    Ok(objects)
+}
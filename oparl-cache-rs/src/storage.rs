@@ -1,8 +1,16 @@
 use std::error::Error;
+use std::iter::empty;
+use std::time::SystemTime;
 
 use json::JsonValue;
 use reqwest::Url;
 
+use model::{OParlObject, from_json};
+
+/// A lazy iterator over the contents of a storage. Errors are yielded as items, so a broken file
+/// doesn't end the iteration
+pub type CacheIter<'s, T> = Box<Iterator<Item = Result<T, Box<Error>>> + 's>;
+
 /// Defines a storage for saving objects
 ///
 /// An Implementation can be any kind of storage, be it a file storage, a database or even the ram
@@ -11,7 +19,40 @@ pub trait Storage {
     fn write_to_cache(&self, url: &Url, object: &JsonValue) -> Result<(), Box<Error>>;
     /// Retrieves a cached object
     fn get(&self, url: &Url) -> Result<JsonValue, Box<Error>>;
-    /// Return the schema as one json dict
+    /// Retrieves a cached object as the struct of its type, e.g. `get_typed::<Paper>(&url)`
+    fn get_typed<T: OParlObject>(&self, url: &Url) -> Result<T, Box<Error>> {
+        from_json(&self.get(url)?)
+    }
+    /// Iterates over the cached objects of the server with the entrypoint `server`. With a
+    /// `type_filter` such as `Paper`, only objects of that type are returned. Storages that can't
+    /// enumerate their objects yield a single error
+    fn iter_objects<'s>(&'s self, server: &Url, _type_filter: Option<&str>) -> CacheIter<'s, JsonValue> {
+        let err = format!("The storage can't enumerate the objects of {}", server);
+        Box::new(Some(Err(From::from(err))).into_iter())
+    }
+    /// Iterates over the cached objects of all the given types, e.g. `&["Body", "Organization"]`.
+    /// Storages should override this to read the objects in a single pass
+    fn iter_objects_of<'s>(&'s self, server: &Url, types: &[&str]) -> CacheIter<'s, JsonValue> {
+        let mut objects: CacheIter<'s, JsonValue> = Box::new(empty());
+        for oparl_type in types {
+            objects = Box::new(objects.chain(self.iter_objects(server, Some(*oparl_type))));
+        }
+        objects
+    }
+    /// Iterates over the cached external lists of the server with the entrypoint `server` as
+    /// pairs of the url and the ids of the elements. Storages that can't enumerate their lists
+    /// yield a single error
+    fn iter_lists<'s>(&'s self, server: &Url) -> CacheIter<'s, (Url, JsonValue)> {
+        let err = format!("The storage can't enumerate the lists of {}", server);
+        Box::new(Some(Err(From::from(err))).into_iter())
+    }
+    /// Returns when the cache of the server with the entrypoint `server` was last updated. It is
+    /// used to invalidate indexes that are built from the cached objects. Storages that don't
+    /// know it return `None`, so such indexes are only built once
+    fn last_updated(&self, _server: &Url) -> Option<SystemTime> {
+        None
+    }
+    /// Return the schema as one json dict of the form `{<version>: {<type>: <schema>}}`
     /// TODO: Untangle schema and storage
     fn get_schema(&self) -> &JsonValue;
 }
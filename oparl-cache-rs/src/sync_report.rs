@@ -0,0 +1,208 @@
+use std::error::Error;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use json::JsonValue;
+use reqwest::Url;
+
+use server::Server;
+
+/// The number of error messages kept for each list
+pub const MAX_ERROR_SAMPLES: usize = 10;
+
+/// An object that was added or changed in a sync run
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChangedObject {
+    /// The id of the object
+    pub id: String,
+    /// The type of the object without the schema url, e.g. `Paper`
+    pub oparl_type: String,
+}
+
+/// Counters and timings for downloading a single external list
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ListReport {
+    /// The url of the list without filters
+    pub url: String,
+    /// Whether the list could not be downloaded completely
+    pub failed: bool,
+    /// The number of pages fetched successfully
+    pub pages: usize,
+    /// The number of objects in the list, including the invalid ones
+    pub objects: usize,
+    /// The number of objects that were skipped because they couldn't be parsed
+    pub invalid_objects: usize,
+    /// The number of objects whose id didn't have its canonical form and was rewritten
+    #[serde(default)]
+    pub rewritten_ids: usize,
+    /// The number of objects whose id is on another host than the list they were fetched from
+    /// even after rewriting, or for the System object, differs from the entrypoint
+    #[serde(default)]
+    pub id_mismatches: usize,
+    /// The size of the fetched pages in bytes, measured as serialized json
+    pub bytes: usize,
+    /// The time it took to download and parse the whole list
+    pub duration_ms: u64,
+    /// The median of the time it took to fetch a page
+    pub latency_p50_ms: u64,
+    /// The 90th percentile of the time it took to fetch a page
+    pub latency_p90_ms: u64,
+    /// The 99th percentile of the time it took to fetch a page
+    pub latency_p99_ms: u64,
+    /// The first `MAX_ERROR_SAMPLES` errors
+    pub errors: Vec<String>,
+    /// The objects of the list and their embedded objects that are new or differ from their
+    /// cached version. They are not stored with the report, but can be used for the hooks
+    #[serde(skip)]
+    pub changed: Vec<ChangedObject>,
+    /// The latencies of all pages, which are only kept until `finish` is called
+    #[serde(skip)]
+    latencies_ms: Vec<u64>,
+}
+
+impl ListReport {
+    /// Creates an empty report for the list at `url`
+    pub fn new(url: &Url) -> ListReport {
+        ListReport { url: url.to_string(), ..ListReport::default() }
+    }
+
+    /// Records a successfully fetched page
+    pub fn add_page(&mut self, latency: Duration, bytes: usize) {
+        self.pages += 1;
+        self.bytes += bytes;
+        self.latencies_ms.push(as_millis(latency));
+    }
+
+    /// Records an error, keeping only the first `MAX_ERROR_SAMPLES` messages
+    pub fn add_error(&mut self, error: String) {
+        if self.errors.len() < MAX_ERROR_SAMPLES {
+            self.errors.push(error);
+        }
+    }
+
+    /// Adds the page counters of `other`, which was used for measuring the requests
+    pub fn add_pages_of(&mut self, other: ListReport) {
+        self.pages += other.pages;
+        self.bytes += other.bytes;
+        self.latencies_ms.extend(other.latencies_ms);
+    }
+
+    /// Sets the duration and computes the latency percentiles
+    pub fn finish(&mut self, duration: Duration) {
+        self.duration_ms = as_millis(duration);
+        self.latencies_ms.sort();
+        self.latency_p50_ms = percentile(&self.latencies_ms, 50);
+        self.latency_p90_ms = percentile(&self.latencies_ms, 90);
+        self.latency_p99_ms = percentile(&self.latencies_ms, 99);
+    }
+}
+
+/// The report of one run of `Cacher::cache` or `Cacher::backfill`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SyncReport {
+    /// The entrypoint of the server
+    pub entrypoint: Url,
+    /// The start of the run as RFC 3339 timestamp in UTC
+    pub started: String,
+    /// Whether this was a backfill of a time window
+    pub backfill: bool,
+    /// The time the whole run took
+    pub duration_ms: u64,
+    /// The reports for the single lists
+    pub lists: Vec<ListReport>,
+}
+
+impl SyncReport {
+    /// The number of objects whose id was rewritten over all lists
+    pub fn rewritten_ids(&self) -> usize {
+        self.lists.iter().map(|x| x.rewritten_ids).sum()
+    }
+
+    /// The number of ids that didn't match the url they were fetched from over all lists
+    pub fn id_mismatches(&self) -> usize {
+        self.lists.iter().map(|x| x.id_mismatches).sum()
+    }
+
+    /// The number of fetched pages over all lists
+    pub fn pages(&self) -> usize {
+        self.lists.iter().map(|x| x.pages).sum()
+    }
+
+    /// The number of fetched objects over all lists
+    pub fn objects(&self) -> usize {
+        self.lists.iter().map(|x| x.objects).sum()
+    }
+
+    /// The number of invalid objects over all lists
+    pub fn invalid_objects(&self) -> usize {
+        self.lists.iter().map(|x| x.invalid_objects).sum()
+    }
+
+    /// The number of fetched bytes over all lists
+    pub fn bytes(&self) -> usize {
+        self.lists.iter().map(|x| x.bytes).sum()
+    }
+
+    /// The number of lists that failed
+    pub fn failed_lists(&self) -> usize {
+        self.lists.iter().filter(|x| x.failed).count()
+    }
+}
+
+/// Wraps a server and records the latency and the size of every successful request. Failed
+/// requests aren't recorded, as they fail the list, whose error is recorded instead. The url is
+/// added to their error for that
+pub struct MeasuringServer<'a, T: 'a + Server> {
+    server: &'a T,
+    report: Mutex<ListReport>,
+}
+
+impl<'a, T: 'a + Server> MeasuringServer<'a, T> {
+    /// Creates a new `MeasuringServer`
+    pub fn new(server: &'a T) -> MeasuringServer<'a, T> {
+        MeasuringServer {
+            server: server,
+            report: Mutex::new(ListReport::default()),
+        }
+    }
+
+    /// Returns a report with the pages, bytes and latencies of all successful requests
+    pub fn into_report(self) -> ListReport {
+        self.report.into_inner().unwrap()
+    }
+}
+
+impl<'a, T: 'a + Server> Server for MeasuringServer<'a, T> {
+    fn get_json(&self, url: Url) -> Result<JsonValue, Box<Error>> {
+        let start = Instant::now();
+        let result = self.server.get_json(url.clone());
+        let latency = start.elapsed();
+
+        match result {
+            Ok(json) => {
+                self.report.lock().unwrap().add_page(latency, json.dump().len());
+                Ok(json)
+            }
+            Err(err) => Err(From::from(format!("{}: {}", url, err))),
+        }
+    }
+
+    fn get_entrypoint(&self) -> Url {
+        self.server.get_entrypoint()
+    }
+}
+
+/// Converts a `Duration` to milliseconds
+pub fn as_millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + duration.subsec_nanos() as u64 / 1_000_000
+}
+
+/// Nearest-rank percentile of sorted values
+fn percentile(sorted: &[u64], percent: usize) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+
+    let rank = (percent * sorted.len() + 99) / 100;
+    sorted[rank.max(1) - 1]
+}
@@ -0,0 +1,523 @@
+use std::error::Error;
+use std::sync::{Arc, RwLock};
+
+use chrono::{DateTime, Duration, FixedOffset, TimeZone, Utc};
+use hyper::header::ContentType;
+use hyper::server::{Server as HttpServer, Listening, Request, Response};
+use hyper::status::StatusCode;
+use json::JsonValue;
+use reqwest::{IntoUrl, Url};
+
+use http::{request_url, respond, not_found};
+use server::Server;
+
+/// The path of the entrypoint on a synthetic server
+pub const ENTRYPOINT_PATH: &'static str = "/oparl/v1.0/";
+
+/// The external lists of a body
+const BODY_LISTS: [&'static str; 4] = ["organization", "person", "meeting", "paper"];
+
+/// The sizes of a generated dataset. The numbers of papers, meetings, persons and files are per
+/// body
+#[derive(Clone, Debug)]
+pub struct DatasetConfig {
+    /// The number of bodies
+    pub bodies: usize,
+    /// The number of papers of each body
+    pub papers: usize,
+    /// The number of meetings of each body
+    pub meetings: usize,
+    /// The number of persons of each body
+    pub persons: usize,
+    /// The number of files of each body, which are embedded into the papers
+    pub files: usize,
+    /// The number of agenda items embedded into each meeting
+    pub agenda_items: usize,
+    /// The number of objects on a list page
+    pub page_size: usize,
+}
+
+impl Default for DatasetConfig {
+    fn default() -> DatasetConfig {
+        DatasetConfig {
+            bodies: 1,
+            papers: 20,
+            meetings: 10,
+            persons: 10,
+            files: 20,
+            agenda_items: 3,
+            page_size: 5,
+        }
+    }
+}
+
+/// The changes `SyntheticDataset::mutate` applies to the papers of every body
+#[derive(Clone, Debug, Default)]
+pub struct Mutations {
+    /// The number of papers to add
+    pub added: usize,
+    /// The number of existing papers to rename
+    pub modified: usize,
+    /// The number of existing papers to mark as deleted
+    pub deleted: usize,
+}
+
+#[derive(Clone, Debug)]
+struct Item {
+    name: String,
+    created: DateTime<Utc>,
+    modified: DateTime<Utc>,
+    deleted: bool,
+}
+
+impl Item {
+    fn new(name: String, created: DateTime<Utc>) -> Item {
+        Item {
+            name: name,
+            created: created,
+            modified: created,
+            deleted: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct SyntheticBody {
+    item: Item,
+    papers: Vec<Item>,
+    meetings: Vec<Item>,
+    persons: Vec<Item>,
+    files: Vec<Item>,
+}
+
+/// A generated, spec-valid OParl 1.0 dataset that can be used as `Server` directly or served
+/// over http with `serve`
+///
+/// The lists support pagination as well as the `created_since`, `created_until`,
+/// `modified_since` and `modified_until` filters. Files are embedded into the papers and agenda
+/// items into the meetings
+#[derive(Clone, Debug)]
+pub struct SyntheticDataset {
+    base: Url,
+    config: DatasetConfig,
+    bodies: Vec<SyntheticBody>,
+}
+
+impl SyntheticDataset {
+    /// Generates a dataset with the sizes given in `config`. All objects are created at fixed
+    /// times in the past, so that only mutated objects match a `modified_since` filter
+    pub fn new(config: DatasetConfig) -> SyntheticDataset {
+        let start = Utc.ymd(2017, 1, 1).and_hms(0, 0, 0);
+        let items = |kind: &str, count: usize, offset: i64| -> Vec<Item> {
+            (0..count)
+                .map(|i| Item::new(format!("{} {}", kind, i + 1), start + Duration::minutes(offset + i as i64)))
+                .collect()
+        };
+
+        let bodies = (0..config.bodies)
+            .map(|i| {
+                SyntheticBody {
+                    item: Item::new(format!("Body {}", i + 1), start),
+                    papers: items("Paper", config.papers, 0),
+                    meetings: items("Meeting", config.meetings, 10000),
+                    persons: items("Person", config.persons, 20000),
+                    files: items("File", config.files, 30000),
+                }
+            })
+            .collect();
+
+        SyntheticDataset {
+            base: format!("http://localhost{}", ENTRYPOINT_PATH).into_url().unwrap(),
+            config: config,
+            bodies: bodies,
+        }
+    }
+
+    /// Sets the url all ids are relative to. It must end with a slash
+    pub fn set_base(&mut self, base: Url) {
+        self.base = base;
+    }
+
+    /// Adds, renames and deletes papers in every body, setting their modification time to now
+    pub fn mutate(&mut self, mutations: &Mutations) {
+        let now = Utc::now();
+        for body in &mut self.bodies {
+            for paper in body.papers.iter_mut().filter(|x| !x.deleted).take(mutations.modified) {
+                paper.name = format!("{} (modified)", paper.name);
+                paper.modified = now;
+            }
+
+            for paper in body.papers.iter_mut().rev().filter(|x| !x.deleted).take(mutations.deleted) {
+                paper.deleted = true;
+                paper.modified = now;
+            }
+
+            for _ in 0..mutations.added {
+                let name = format!("Paper {}", body.papers.len() + 1);
+                body.papers.push(Item::new(name, now));
+            }
+        }
+    }
+
+    /// Returns the response for `url`, `None` if there is no such object or list and an error
+    /// for invalid filters
+    pub fn response(&self, url: &Url) -> Result<Option<JsonValue>, Box<Error>> {
+        let base_path = self.base.path();
+        let path = if url.path() == base_path.trim_right_matches('/') {
+            ""
+        } else if url.path().starts_with(base_path) {
+            &url.path()[base_path.len()..]
+        } else {
+            return Ok(None);
+        };
+
+        let segments: Vec<&str> = path.split('/').filter(|x| !x.is_empty()).collect();
+        let segment = |x: usize| segments.get(x).map(|x| *x);
+
+        let body = match (segment(0), segment(1)) {
+            (None, _) => return Ok(Some(self.system())),
+            (Some("body"), None) => {
+                let bodies = (0..self.bodies.len()).map(|x| (&self.bodies[x].item, self.body(x))).collect();
+                return self.list(url, bodies).map(Some);
+            }
+            (Some("body"), Some(id)) => {
+                match parse_index(id, self.bodies.len()) {
+                    Some(body) => body,
+                    None => return Ok(None),
+                }
+            }
+            _ => return Ok(None),
+        };
+
+        let items = &self.bodies[body];
+        let response = match (segment(2), segment(3), segment(4), segment(5)) {
+            (None, ..) => Some(self.body(body)),
+            (Some("organization"), None, ..) => Some(self.list(url, vec![])?),
+            (Some("paper"), None, ..) => {
+                let papers = (0..items.papers.len()).map(|x| (&items.papers[x], self.paper(body, x))).collect();
+                Some(self.list(url, papers)?)
+            }
+            (Some("meeting"), None, ..) => {
+                let meetings = (0..items.meetings.len())
+                    .map(|x| (&items.meetings[x], self.meeting(body, x)))
+                    .collect();
+                Some(self.list(url, meetings)?)
+            }
+            (Some("person"), None, ..) => {
+                let persons = (0..items.persons.len()).map(|x| (&items.persons[x], self.person(body, x))).collect();
+                Some(self.list(url, persons)?)
+            }
+            (Some("paper"), Some(id), None, _) => {
+                parse_index(id, items.papers.len()).map(|x| self.paper(body, x))
+            }
+            (Some("meeting"), Some(id), None, _) => {
+                parse_index(id, items.meetings.len()).map(|x| self.meeting(body, x))
+            }
+            (Some("person"), Some(id), None, _) => {
+                parse_index(id, items.persons.len()).map(|x| self.person(body, x))
+            }
+            (Some("file"), Some(id), None, _) => {
+                parse_index(id, items.files.len()).map(|x| self.file(body, x))
+            }
+            (Some("meeting"), Some(id), Some("agendaItem"), Some(number)) => {
+                match (parse_index(id, items.meetings.len()), parse_index(number, self.config.agenda_items)) {
+                    (Some(meeting), Some(number)) => Some(self.agenda_item(body, meeting, number)),
+                    _ => None,
+                }
+            }
+            (Some("legislativeTerm"), Some("1"), None, _) => Some(self.legislative_term(body)),
+            _ => None,
+        };
+
+        Ok(response)
+    }
+
+    fn url(&self, path: &str) -> String {
+        self.base.join(path).unwrap().to_string()
+    }
+
+    fn body_url(&self, body: usize, path: &str) -> String {
+        self.url(&format!("body/{}{}", body + 1, path))
+    }
+
+    fn system(&self) -> JsonValue {
+        object!{
+            "id" => self.base.as_str(),
+            "type" => "https://schema.oparl.org/1.0/System",
+            "oparlVersion" => "https://schema.oparl.org/1.0/",
+            "name" => "Synthetic OParl Server",
+            "body" => self.url("body")
+        }
+    }
+
+    fn body(&self, body: usize) -> JsonValue {
+        let item = &self.bodies[body].item;
+        let mut json = object!{
+            "id" => self.body_url(body, ""),
+            "type" => "https://schema.oparl.org/1.0/Body",
+            "system" => self.base.as_str(),
+            "name" => item.name.as_str(),
+            "shortName" => format!("B{}", body + 1),
+            "legislativeTerm" => array![self.legislative_term(body)],
+            "created" => item.created.to_rfc3339(),
+            "modified" => item.modified.to_rfc3339()
+        };
+        for list in BODY_LISTS.iter() {
+            json[*list] = self.body_url(body, &format!("/{}", list)).into();
+        }
+        json
+    }
+
+    fn legislative_term(&self, body: usize) -> JsonValue {
+        let item = &self.bodies[body].item;
+        object!{
+            "id" => self.body_url(body, "/legislativeTerm/1"),
+            "type" => "https://schema.oparl.org/1.0/LegislativeTerm",
+            "body" => self.body_url(body, ""),
+            "name" => "2014 - 2020",
+            "startDate" => "2014-05-25",
+            "endDate" => "2020-05-24",
+            "created" => item.created.to_rfc3339(),
+            "modified" => item.modified.to_rfc3339()
+        }
+    }
+
+    fn paper(&self, body: usize, index: usize) -> JsonValue {
+        let item = &self.bodies[body].papers[index];
+        let id = self.body_url(body, &format!("/paper/{}", index + 1));
+        if item.deleted {
+            return deleted(id, "Paper", item);
+        }
+
+        // The files are distributed evenly over the original papers
+        let files = (0..self.bodies[body].files.len())
+            .filter(|x| x % self.config.papers.max(1) == index)
+            .map(|x| self.file(body, x))
+            .collect::<Vec<_>>();
+
+        object!{
+            "id" => id,
+            "type" => "https://schema.oparl.org/1.0/Paper",
+            "body" => self.body_url(body, ""),
+            "name" => item.name.as_str(),
+            "reference" => format!("{}/{}", item.created.format("%Y"), index + 1),
+            "date" => item.created.format("%Y-%m-%d").to_string(),
+            "paperType" => "Antrag",
+            "auxiliaryFile" => files,
+            "created" => item.created.to_rfc3339(),
+            "modified" => item.modified.to_rfc3339()
+        }
+    }
+
+    fn file(&self, body: usize, index: usize) -> JsonValue {
+        let item = &self.bodies[body].files[index];
+        let id = self.body_url(body, &format!("/file/{}", index + 1));
+        object!{
+            "id" => id.as_str(),
+            "type" => "https://schema.oparl.org/1.0/File",
+            "name" => item.name.as_str(),
+            "fileName" => format!("file-{}.pdf", index + 1),
+            "mimeType" => "application/pdf",
+            "accessUrl" => format!("{}/download", id),
+            "created" => item.created.to_rfc3339(),
+            "modified" => item.modified.to_rfc3339()
+        }
+    }
+
+    fn meeting(&self, body: usize, index: usize) -> JsonValue {
+        let item = &self.bodies[body].meetings[index];
+        let agenda_items = (0..self.config.agenda_items)
+            .map(|x| self.agenda_item(body, index, x))
+            .collect::<Vec<_>>();
+        object!{
+            "id" => self.body_url(body, &format!("/meeting/{}", index + 1)),
+            "type" => "https://schema.oparl.org/1.0/Meeting",
+            "name" => item.name.as_str(),
+            "start" => (item.created + Duration::days(7)).to_rfc3339(),
+            "end" => (item.created + Duration::days(7) + Duration::hours(2)).to_rfc3339(),
+            "agendaItem" => agenda_items,
+            "created" => item.created.to_rfc3339(),
+            "modified" => item.modified.to_rfc3339()
+        }
+    }
+
+    fn agenda_item(&self, body: usize, meeting: usize, index: usize) -> JsonValue {
+        let item = &self.bodies[body].meetings[meeting];
+        let meeting_id = self.body_url(body, &format!("/meeting/{}", meeting + 1));
+        object!{
+            "id" => format!("{}/agendaItem/{}", meeting_id, index + 1),
+            "type" => "https://schema.oparl.org/1.0/AgendaItem",
+            "meeting" => meeting_id.as_str(),
+            "number" => format!("{}", index + 1),
+            "order" => index,
+            "name" => format!("Agenda item {}", index + 1),
+            "public" => true,
+            "created" => item.created.to_rfc3339(),
+            "modified" => item.modified.to_rfc3339()
+        }
+    }
+
+    fn person(&self, body: usize, index: usize) -> JsonValue {
+        let item = &self.bodies[body].persons[index];
+        object!{
+            "id" => self.body_url(body, &format!("/person/{}", index + 1)),
+            "type" => "https://schema.oparl.org/1.0/Person",
+            "body" => self.body_url(body, ""),
+            "name" => item.name.as_str(),
+            "familyName" => format!("Person {}", index + 1),
+            "created" => item.created.to_rfc3339(),
+            "modified" => item.modified.to_rfc3339()
+        }
+    }
+
+    /// Renders a page of a list, applying the filters and the page given in the query of `url`
+    fn list(&self, url: &Url, items: Vec<(&Item, JsonValue)>) -> Result<JsonValue, Box<Error>> {
+        let mut page = 1;
+        let mut filters: Vec<(String, String)> = vec![];
+        let mut items = items;
+
+        for (key, value) in url.query_pairs() {
+            let matches = |x: &Item| -> Result<bool, Box<Error>> {
+                let time = parse_time(&value)?;
+                Ok(match key.as_ref() {
+                    "created_since" => x.created >= time,
+                    "created_until" => x.created <= time,
+                    "modified_since" => x.modified >= time,
+                    _ => x.modified <= time,
+                })
+            };
+
+            match key.as_ref() {
+                "page" => page = value.parse()?,
+                "created_since" | "created_until" | "modified_since" | "modified_until" => {
+                    let mut filtered = vec![];
+                    for (item, json) in items {
+                        if matches(item)? {
+                            filtered.push((item, json));
+                        }
+                    }
+                    items = filtered;
+                    filters.push((key.to_string(), value.to_string()));
+                }
+                _ => {}
+            }
+        }
+
+        let page_size = self.config.page_size.max(1);
+        let total_pages = ((items.len() + page_size - 1) / page_size).max(1);
+        if page < 1 || page > total_pages {
+            return Err(From::from(format!("Invalid page: {}", page)));
+        }
+
+        let page_url = |page: usize| -> String {
+            let mut page_url = url.clone();
+            page_url.set_query(None);
+            {
+                let mut query = page_url.query_pairs_mut();
+                for &(ref key, ref value) in &filters {
+                    query.append_pair(key, value);
+                }
+                query.append_pair("page", &page.to_string());
+            }
+            page_url.to_string()
+        };
+
+        let total_elements = items.len();
+        let data = items.into_iter()
+            .skip((page - 1) * page_size)
+            .take(page_size)
+            .map(|(_, json)| json)
+            .collect::<Vec<_>>();
+
+        let mut links = object!{
+            "first" => page_url(1),
+            "last" => page_url(total_pages),
+            "self" => page_url(page)
+        };
+        if page > 1 {
+            links["prev"] = page_url(page - 1).into();
+        }
+        if page < total_pages {
+            links["next"] = page_url(page + 1).into();
+        }
+
+        Ok(object!{
+            "data" => data,
+            "pagination" => object!{
+                "totalElements" => total_elements,
+                "elementsPerPage" => page_size,
+                "currentPage" => page,
+                "totalPages" => total_pages
+            },
+            "links" => links
+        })
+    }
+}
+
+impl Server for SyntheticDataset {
+    fn get_json(&self, url: Url) -> Result<JsonValue, Box<Error>> {
+        match self.response(&url)? {
+            Some(json) => Ok(json),
+            None => Err(From::from(format!("Bad status code returned for request: {}", StatusCode::NotFound))),
+        }
+    }
+
+    fn get_entrypoint(&self) -> Url {
+        self.base.clone()
+    }
+}
+
+/// The representation of a deleted object as required by the spec
+fn deleted(id: String, oparl_type: &str, item: &Item) -> JsonValue {
+    object!{
+        "id" => id,
+        "type" => format!("https://schema.oparl.org/1.0/{}", oparl_type),
+        "deleted" => true,
+        "created" => item.created.to_rfc3339(),
+        "modified" => item.modified.to_rfc3339()
+    }
+}
+
+/// Parses the 1-based number in an url into an index, if it's in range
+fn parse_index(number: &str, len: usize) -> Option<usize> {
+    match number.parse::<usize>() {
+        Ok(number) if number >= 1 && number <= len => Some(number - 1),
+        _ => None,
+    }
+}
+
+fn parse_time(time: &str) -> Result<DateTime<FixedOffset>, Box<Error>> {
+    DateTime::parse_from_rfc3339(time).map_err(|err| From::from(format!("Invalid time {}: {}", time, err)))
+}
+
+/// Serves `dataset` on `address` in background threads. The base url of the dataset is set to
+/// the bound address, so that the ids point to this server
+pub fn serve(address: &str, dataset: Arc<RwLock<SyntheticDataset>>) -> Result<Listening, Box<Error>> {
+    let handler_dataset = dataset.clone();
+    let handler = move |request: Request, response: Response| {
+        let dataset = handler_dataset.read().unwrap();
+        let mut url = match request_url(&request) {
+            Some(url) => url,
+            None => return not_found(response),
+        };
+        // The handler only sees the path, so the host is taken from the base url
+        let _ = url.set_host(dataset.base.host_str());
+        let _ = url.set_port(dataset.base.port());
+
+        match dataset.response(&url) {
+            Ok(Some(json)) => {
+                respond(response, StatusCode::Ok, ContentType::json(), json.dump().as_bytes());
+            }
+            Ok(None) => not_found(response),
+            Err(err) => {
+                let body = object!{ "error" => err.to_string() };
+                respond(response, StatusCode::BadRequest, ContentType::json(), body.dump().as_bytes());
+            }
+        }
+    };
+
+    let listening = HttpServer::http(address)?.handle(handler)?;
+    let base = format!("http://{}{}", listening.socket, ENTRYPOINT_PATH).into_url()?;
+    dataset.write().unwrap().set_base(base);
+    Ok(listening)
+}
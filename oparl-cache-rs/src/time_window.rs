@@ -0,0 +1,50 @@
+use reqwest::Url;
+
+/// The names of the four filter parameters defined by the OParl specification
+pub const FILTER_PARAMETERS: [&'static str; 4] =
+    ["created_since", "created_until", "modified_since", "modified_until"];
+
+/// A time window restricting the objects returned by an external list
+///
+/// Each bound is translated to the OParl filter parameter of the same name. Setting any of them
+/// turns a sync into a backfill: The objects in the window are merged into the existing list caches
+/// and the timestamps for incremental updates are left untouched.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct TimeWindow {
+    /// Only objects created at or after this time
+    pub created_since: Option<String>,
+    /// Only objects created at or before this time
+    pub created_until: Option<String>,
+    /// Only objects modified at or after this time
+    pub modified_since: Option<String>,
+    /// Only objects modified at or before this time
+    pub modified_until: Option<String>,
+}
+
+impl TimeWindow {
+    /// Returns true if no bound is set, i.e. the window contains all objects
+    pub fn is_empty(&self) -> bool {
+        self.created_since.is_none() && self.created_until.is_none() &&
+        self.modified_since.is_none() && self.modified_until.is_none()
+    }
+
+    /// Appends a query parameter for every bound that is set
+    pub fn apply_to(&self, url: &mut Url) {
+        if self.is_empty() {
+            return; // Don't leave a dangling `?` on the url
+        }
+
+        let bounds = [&self.created_since,
+                      &self.created_until,
+                      &self.modified_since,
+                      &self.modified_until];
+
+        let mut query = url.query_pairs_mut();
+        for (name, bound) in FILTER_PARAMETERS.iter().zip(bounds.iter()) {
+            if let Some(ref value) = **bound {
+                query.append_pair(name, value);
+            }
+        }
+        query.finish();
+    }
+}
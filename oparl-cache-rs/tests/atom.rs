@@ -0,0 +1,181 @@
+extern crate oparl_cache;
+#[macro_use]
+extern crate json;
+extern crate reqwest;
+
+mod common;
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+use json::JsonValue;
+use reqwest::{IntoUrl, StatusCode};
+
+use oparl_cache::{FileStorage, Storage};
+use oparl_cache::atom::{FeedSelection, export_feeds, paper_feed};
+use oparl_cache::cache_server::serve_cache;
+
+use common::*;
+
+fn write(storage: &FileStorage, object: JsonValue) {
+    let url = object["id"].as_str().unwrap().into_url().unwrap();
+    storage.write_to_cache(&url, &object).unwrap();
+}
+
+fn cache_papers(storage: &FileStorage) {
+    write(storage,
+          object!{
+              "id" => "http://example.com/oparl/body/1",
+              "type" => "https://schema.oparl.org/1.0/Body",
+              "name" => "Example City"
+          });
+    write(storage,
+          object!{
+              "id" => "http://example.com/oparl/paper/1",
+              "type" => "https://schema.oparl.org/1.0/Paper",
+              "body" => "http://example.com/oparl/body/1",
+              "name" => "Budget 2018",
+              "reference" => "2017/001",
+              "keyword" => array!["Budget"],
+              "mainFile" => "http://example.com/oparl/file/1",
+              "modified" => "2017-05-01T12:00:00+02:00"
+          });
+    write(storage,
+          object!{
+              "id" => "http://example.com/oparl/paper/2",
+              "type" => "https://schema.oparl.org/1.0/Paper",
+              "body" => "http://example.com/oparl/body/1",
+              "name" => "Bike lanes & parking",
+              "keyword" => array!["traffic"],
+              "modified" => "2017-06-01T12:00:00+02:00"
+          });
+    write(storage,
+          object!{
+              "id" => "http://example.com/oparl/file/1",
+              "type" => "https://schema.oparl.org/1.0/File",
+              "name" => "Draft",
+              "accessUrl" => "http://example.com/files/1.pdf",
+              "mimeType" => "application/pdf"
+          });
+    write(storage,
+          object!{
+              "id" => "http://example.com/oparl/consultation/1",
+              "type" => "https://schema.oparl.org/1.0/Consultation",
+              "paper" => "http://example.com/oparl/paper/1",
+              "organization" => array!["http://example.com/oparl/organization/1"]
+          });
+}
+
+/// Assert that the feeds select the papers by body, consulting organization and keyword
+#[test]
+fn paper_feeds() {
+    let storage = storage();
+    cache_papers(&storage);
+    let entrypoints = vec!["http://example.com/oparl".into_url().unwrap()];
+
+    let body = FeedSelection::Body("http://example.com/oparl/body/1".to_string());
+    let feed = paper_feed(&storage, &entrypoints, &body, 10).unwrap();
+    assert!(feed.contains("<title>Papers of Example City</title>"));
+    assert!(feed.contains("<updated>2017-06-01T10:00:00+00:00</updated>"));
+    // The newest paper comes first
+    let newest = feed.find("<title>Bike lanes &amp; parking</title>").unwrap();
+    let oldest = feed.find("<title>2017/001: Budget 2018</title>").unwrap();
+    assert!(newest < oldest);
+    assert!(feed.contains("<link rel=\"enclosure\" href=\"http://example.com/files/1.pdf\" \
+                           type=\"application/pdf\" title=\"Draft\"/>"));
+
+    let feed = paper_feed(&storage, &entrypoints, &body, 1).unwrap();
+    assert_eq!(feed.matches("<entry>").count(), 1);
+
+    let organization = FeedSelection::Organization("http://example.com/oparl/organization/1".to_string());
+    let feed = paper_feed(&storage, &entrypoints, &organization, 10).unwrap();
+    assert!(feed.contains("<id>http://example.com/oparl/paper/1</id>"));
+    assert!(!feed.contains("<id>http://example.com/oparl/paper/2</id>"));
+
+    let keyword = FeedSelection::Keyword("budget".to_string());
+    let feed = paper_feed(&storage, &entrypoints, &keyword, 10).unwrap();
+    assert_eq!(feed.matches("<entry>").count(), 1);
+    assert!(feed.contains("<id>urn:oparl-cache:keyword:budget</id>"));
+
+    let dir = storage.get_cache_dir().join("feeds");
+    assert_eq!(export_feeds(&storage, &entrypoints[0], &dir, 10).unwrap(), 3);
+    assert!(dir.join("body-oparl-body-1.xml").exists());
+    assert!(dir.join("keyword-traffic.xml").exists());
+
+    cleanup(&storage);
+}
+
+/// Assert that keywords with the same slug are written to different files
+#[test]
+fn export_colliding_keywords() {
+    let storage = storage();
+    write(&storage,
+          object!{
+              "id" => "http://example.com/oparl/paper/1",
+              "type" => "https://schema.oparl.org/1.0/Paper",
+              "name" => "Budget 2017",
+              "keyword" => array!["Haushalt 2017", "haushalt/2017", "traffic"],
+              "modified" => "2017-05-01T12:00:00+02:00"
+          });
+
+    let dir = storage.get_cache_dir().join("feeds");
+    let entrypoint = "http://example.com/oparl".into_url().unwrap();
+    assert_eq!(export_feeds(&storage, &entrypoint, &dir, 10).unwrap(), 3);
+    assert!(!dir.join("keyword-haushalt-2017.xml").exists());
+    let mut feed = String::new();
+    File::open(dir.join("keyword-haushalt-2017-a2af13fd.xml")).unwrap().read_to_string(&mut feed).unwrap();
+    assert!(feed.contains("<id>urn:oparl-cache:keyword:haushalt 2017</id>"));
+    feed.clear();
+    File::open(dir.join("keyword-haushalt-2017-da1c1588.xml")).unwrap().read_to_string(&mut feed).unwrap();
+    assert!(feed.contains("<id>urn:oparl-cache:keyword:haushalt/2017</id>"));
+    assert!(dir.join("keyword-traffic.xml").exists());
+
+    cleanup(&storage);
+}
+
+/// Assert that the feeds are served by the cache server and the papers are reloaded after a sync
+#[test]
+fn serve_feed() {
+    let storage = Arc::new(storage());
+    cache_papers(&storage);
+    let entrypoint = "http://example.com/oparl".into_url().unwrap();
+    let mut listening = serve_cache("127.0.0.1:0", storage.clone(), vec![entrypoint.clone()]).unwrap();
+
+    let socket = listening.socket;
+    let get = move |query: &str| {
+        let mut response = reqwest::get(&format!("http://{}/feed{}", socket, query)).unwrap();
+        let mut body = String::new();
+        response.read_to_string(&mut body).unwrap();
+        (*response.status(), body)
+    };
+
+    let (status, body) = get("?body=http://example.com/oparl/body/1");
+    assert_eq!(status, StatusCode::Ok);
+    assert!(body.contains("<title>Papers of Example City</title>"));
+    assert_eq!(body.matches("<entry>").count(), 2);
+
+    assert_eq!(get("").0, StatusCode::BadRequest);
+    assert_eq!(get("?keyword=budget&limit=many").0, StatusCode::BadRequest);
+
+    // An empty feed has a stable time
+    let (_, body) = get("?keyword=unknown");
+    assert!(body.contains("<updated>1970-01-01T00:00:00+00:00</updated>"));
+
+    write(&storage,
+          object!{
+              "id" => "http://example.com/oparl/paper/3",
+              "type" => "https://schema.oparl.org/1.0/Paper",
+              "body" => "http://example.com/oparl/body/1",
+              "name" => "Playgrounds"
+          });
+    assert_eq!(get("?body=http://example.com/oparl/body/1").1.matches("<entry>").count(), 2);
+
+    // Writing the cache status marks the end of a sync
+    let status = storage.url_to_path(&entrypoint, "").join(storage.get_cache_status_file());
+    File::create(&status).unwrap().write_all(b"{}").unwrap();
+    assert_eq!(get("?body=http://example.com/oparl/body/1").1.matches("<entry>").count(), 3);
+
+    listening.close().unwrap();
+    cleanup(&storage);
+}
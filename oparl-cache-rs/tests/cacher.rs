@@ -8,13 +8,17 @@ mod common;
 
 use reqwest::{Url, IntoUrl};
 
+use std::sync::{Arc, Mutex};
 use std::sync::mpsc::channel;
 
-use oparl_cache::Cacher;
+use oparl_cache::{Cacher, Progress};
 use oparl_cache::cacher::Message::{List, Done};
 use oparl_cache::file_storage::FILE_EXTENSION;
 use oparl_cache::FileStorage;
 use oparl_cache::Storage;
+use oparl_cache::TimeWindow;
+use oparl_cache::ObjectFilter;
+use oparl_cache::sync_report::{ChangedObject, ListReport};
 
 use common::*;
 
@@ -52,6 +56,44 @@ fn parse_object_and_extract_embedded_object() {
     assert_eq!(receive_list.recv().is_err(), true);
 }
 
+/// Assert that new and changed objects are reported with their embedded objects, while objects
+/// that equal their cached version aren't
+#[test]
+fn parse_object_changed_objects() {
+    let input = object! {
+        "id" => "http://localhost:8080/oparl/v1.0/paper/2",
+        "type" => "https://schema.oparl.org/1.0/Paper",
+        "auxiliaryFile" => array![
+            object!{
+              "id" => "http://localhost:8080/oparl/v1.0/file/2",
+              "type" => "https://schema.oparl.org/1.0/File",
+              "modified" => "2016-05-02T19:53:08+02:00"
+            }
+        ],
+        "modified" => "2016-05-02T00:00:00+02:00"
+    };
+
+    let storage = storage();
+    let ids = |changed: Vec<ChangedObject>| changed.into_iter().map(|x| x.id).collect::<Vec<_>>();
+
+    let changed = storage.parse_object(&mut input.clone(), channel().0).unwrap();
+    assert_eq!(ids(changed),
+               vec!["http://localhost:8080/oparl/v1.0/file/2", "http://localhost:8080/oparl/v1.0/paper/2"]);
+
+    assert!(storage.parse_object(&mut input.clone(), channel().0).unwrap().is_empty());
+
+    let mut modified = input.clone();
+    modified["auxiliaryFile"][0]["modified"] = "2017-01-01T00:00:00+01:00".into();
+    let changed = storage.parse_object(&mut modified, channel().0).unwrap();
+    assert_eq!(changed,
+               vec![ChangedObject {
+                        id: "http://localhost:8080/oparl/v1.0/file/2".to_string(),
+                        oparl_type: "File".to_string(),
+                    }]);
+
+    cleanup(&storage);
+}
+
 /// Assert that parse_object ignores embedded geojson objects
 #[test]
 fn parse_object_ignore_geojson() {
@@ -337,3 +379,333 @@ fn test_parse_external_list_incremental() {
     assert_eq!(get_key("https://example.com/4"), "new value");
     assert_eq!(get_key("https://example.com/5"), "new value");
 }
+
+/// Assert that a backfill merges the objects of the window into the list cache and keeps the
+/// timestamp of the last regular sync
+#[test]
+fn test_parse_external_list_backfill() {
+    let storage = storage();
+    let list_url = "https://example.com/list";
+    let since = "2016-01-01T00:00:00+01:00";
+
+    let server = mocking_server("https://example.com/".into_url().unwrap())
+        .with_response(
+            list_url.clone(),
+            object!{
+                "data" => array![
+                    object!{
+                        "id" => "https://example.com/0",
+                        "key" => "old value"
+                    },
+                    object!{
+                        "id" => "https://example.com/1",
+                        "key" => "old value"
+                    }
+                ],
+                "links" => object!{}
+            }
+        ).with_response(
+            Url::parse_with_params(list_url, &[("created_since", since)]).unwrap(),
+            object!{
+                "data" => array![
+                    object!{
+                        "id" => "https://example.com/1",
+                        "key" => "new value"
+                    },
+                    object!{
+                        "id" => "https://example.com/2",
+                        "key" => "new value"
+                    }
+                ],
+                "links" => object!{}
+            }
+        );
+
+    let (_, update) = storage.parse_external_list(list_url.into_url().unwrap(), None, &server, channel().0).unwrap();
+
+    let window = TimeWindow {
+        created_since: Some(since.to_string()),
+        ..TimeWindow::default()
+    };
+    let (_, backfill_update) = storage.parse_external_list_window(list_url.into_url().unwrap(),
+                                                                  update.clone(),
+                                                                  &window,
+                                                                  None,
+                                                                  &server,
+                                                                  channel().0,
+                                                                  &mut ListReport::default())
+        .unwrap();
+
+    let expected_list = vec![
+        "https://example.com/1",
+        "https://example.com/2",
+        "https://example.com/0"
+    ];
+
+    let url = list_url.into_url().unwrap();
+    let list = storage.get(&url).unwrap();
+
+    cleanup(&storage);
+
+    assert_eq!(backfill_update, update);
+    assert_eq!(list.members().map(|x| x.as_str().unwrap()).collect::<Vec<_>>(), expected_list);
+}
+
+/// Assert that lists excluded by the object filter aren't followed
+#[test]
+fn parse_object_filter_external_lists() {
+    let mut input = object! {
+        "id" => "http://localhost:8080/oparl/v1.0/body/0",
+        "type" => "https://schema.oparl.org/1.0/Body",
+        "organization" => "http://localhost:8080/oparl/v1.0/body/0/list/organization",
+        "person" => "http://localhost:8080/oparl/v1.0/body/0/list/person",
+        "meeting" => "http://localhost:8080/oparl/v1.0/body/0/list/meeting",
+        "paper" => "http://localhost:8080/oparl/v1.0/body/0/list/paper"
+    };
+
+    let mut storage = storage();
+    storage.set_object_filter(ObjectFilter {
+        include_types: vec!["Paper".to_string(), "Meeting".to_string()],
+        exclude_lists: vec!["meeting".to_string()],
+        ..ObjectFilter::default()
+    });
+    let (add_list, receive_list) = channel();
+
+    storage.parse_object(&mut input, add_list).unwrap();
+
+    cleanup(&storage);
+
+    let results: Vec<Url> = receive_list.iter()
+        .map(|url| match url {
+                 List(url) => url,
+                 Done => panic!(),
+             })
+        .collect();
+    assert_eq!(results, vec!["http://localhost:8080/oparl/v1.0/body/0/list/paper".into_url().unwrap()]);
+}
+
+/// Assert that the lists of other bodies are skipped
+#[test]
+fn object_filter_bodies() {
+    let filter = ObjectFilter {
+        include_bodies: vec!["https://example.com/body/1".to_string()],
+        ..ObjectFilter::default()
+    };
+
+    assert!(filter.allows_list("body", None));
+    assert!(filter.allows_list("paper", Some("https://example.com/body/1")));
+    assert!(!filter.allows_list("paper", Some("https://example.com/body/2")));
+}
+
+/// Assert that the body list of the System object is followed with a body filter, while only
+/// the lists of the included body are
+#[test]
+fn object_filter_bodies_sync() {
+    let url = "http://example.com/".into_url().unwrap();
+    let server = mocking_server(url.clone())
+        .with_response(url.clone(), object!{
+            "id" => url.as_str(),
+            "type" => "https://schema.oparl.org/1.0/System",
+            "body" => "http://example.com/bodies"
+        })
+        .with_response("http://example.com/bodies", object!{
+            "data" => array![object!{
+                "id" => "http://example.com/body/1",
+                "type" => "https://schema.oparl.org/1.0/Body",
+                "paper" => "http://example.com/body/1/papers"
+            }, object!{
+                "id" => "http://example.com/body/2",
+                "type" => "https://schema.oparl.org/1.0/Body",
+                "paper" => "http://example.com/body/2/papers"
+            }],
+            "links" => object!{}
+        })
+        .with_response("http://example.com/body/1/papers", object!{
+            "data" => array![object!{
+                "id" => "http://example.com/paper/1",
+                "type" => "https://schema.oparl.org/1.0/Paper",
+                "body" => "http://example.com/body/1"
+            }],
+            "links" => object!{}
+        });
+
+    let mut storage = storage();
+    storage.set_object_filter(ObjectFilter {
+        include_bodies: vec!["http://example.com/body/1".to_string()],
+        ..ObjectFilter::default()
+    });
+    storage.cache(server).unwrap();
+
+    assert!(storage.get(&"http://example.com/body/2".into_url().unwrap()).is_ok());
+    assert!(storage.get(&"http://example.com/paper/1".into_url().unwrap()).is_ok());
+    let report = storage.get_sync_reports(&url).unwrap().pop().unwrap();
+    let mut lists = report.lists.iter().map(|x| x.url.as_str()).collect::<Vec<_>>();
+    lists.sort();
+    assert_eq!(lists, vec!["http://example.com/bodies", "http://example.com/body/1/papers"]);
+
+    cleanup(&storage);
+}
+
+/// Assert that the lists added in OParl 1.1 are found
+#[test]
+fn parse_object_oparl_1_1_external_lists() {
+    let mut input = object! {
+        "id" => "http://localhost:8080/oparl/v1.1/body/0",
+        "type" => "https://schema.oparl.org/1.1/Body",
+        "membership" => "http://localhost:8080/oparl/v1.1/body/0/list/membership",
+        "locationList" => "http://localhost:8080/oparl/v1.1/body/0/list/location",
+        "legislativeTermList" => "http://localhost:8080/oparl/v1.1/body/0/list/legislativeterm"
+    };
+
+    let expected_lists =
+        vec!["http://localhost:8080/oparl/v1.1/body/0/list/membership".into_url().unwrap(),
+             "http://localhost:8080/oparl/v1.1/body/0/list/location".into_url().unwrap(),
+             "http://localhost:8080/oparl/v1.1/body/0/list/legislativeterm".into_url().unwrap()];
+
+    let storage = versioned_storage();
+    let mut versions = storage.get_schema().entries().map(|(x, _)| x.to_string()).collect::<Vec<_>>();
+    versions.sort();
+    assert_eq!(versions, vec!["1.0", "1.1"]);
+
+    let (add_list, receive_list) = channel();
+
+    storage.parse_object(&mut input, add_list).unwrap();
+
+    cleanup(&storage);
+
+    let results: Vec<Url> = receive_list.iter()
+        .map(|url| match url {
+                 List(url) => url,
+                 Done => panic!(),
+             })
+        .collect();
+    assert_eq!(results, expected_lists);
+}
+
+/// Assert that the schema is chosen by the version of the server and not by the type url, so
+/// servers announcing OParl 1.1 with 1.0 type urls get their 1.1 lists followed
+#[test]
+fn parse_object_server_version() {
+    let input = object! {
+        "id" => "http://localhost:8080/oparl/v1.1/body/0",
+        "type" => "https://schema.oparl.org/1.0/Body",
+        "membership" => "http://localhost:8080/oparl/v1.1/body/0/list/membership"
+    };
+
+    let storage = versioned_storage();
+
+    let (add_list, receive_list) = channel();
+    storage.parse_object(&mut input.clone(), add_list).unwrap();
+    assert_eq!(receive_list.iter().count(), 0);
+
+    let (add_list, receive_list) = channel();
+    storage.parse_object_as(&mut input.clone(), Some("1.1"), add_list).unwrap();
+    let results: Vec<Url> = receive_list.iter()
+        .map(|url| match url {
+                 List(url) => url,
+                 Done => panic!(),
+             })
+        .collect();
+    assert_eq!(results, vec!["http://localhost:8080/oparl/v1.1/body/0/list/membership".into_url().unwrap()]);
+
+    assert!(storage.parse_object_as(&mut input.clone(), Some("2.0"), channel().0).is_err());
+
+    cleanup(&storage);
+}
+
+/// Assert that objects of a version without a schema are rejected
+#[test]
+fn parse_object_unsupported_version() {
+    let mut input = object! {
+        "id" => "http://localhost:8080/oparl/v2.0/body/0",
+        "type" => "https://schema.oparl.org/2.0/Body"
+    };
+
+    let storage = storage();
+    let result = storage.parse_object(&mut input, channel().0);
+
+    cleanup(&storage);
+
+    assert!(result.is_err());
+}
+
+/// Assert that the progress callback is called for every list
+#[test]
+fn progress_callback() {
+    let url = "http://example.com/".into_url().unwrap();
+    let list_url = "http://example.com/bodies";
+    let server = mocking_server(url.clone())
+        .with_response(url.clone(), object!{
+            "id" => url.as_str(),
+            "type" => "https://schema.oparl.org/1.0/System",
+            "body" => list_url
+        })
+        .with_response(list_url, object!{
+            "data" => array![object!{
+                "id" => "http://example.com/body/1",
+                "type" => "https://schema.oparl.org/1.0/Body"
+            }],
+            "links" => object!{}
+        });
+
+    let events = Arc::new(Mutex::new(vec![]));
+    let mut storage = storage();
+    let recorder = events.clone();
+    storage.set_progress_callback(move |progress| {
+        let event = match progress {
+            Progress::ListStarted(url) => format!("started {}", url),
+            Progress::ListFinished(report) => format!("finished {} {}", report.url, report.objects),
+        };
+        recorder.lock().unwrap().push(event);
+    });
+    storage.cache(server).unwrap();
+
+    cleanup(&storage);
+
+    assert_eq!(*events.lock().unwrap(),
+               vec![format!("started {}", list_url), format!("finished {} 1", list_url)]);
+}
+
+/// Assert that all lists are loaded when only one worker may run at a time
+#[test]
+fn max_workers() {
+    let url = "http://example.com/".into_url().unwrap();
+    let server = mocking_server(url.clone())
+        .with_response(url.clone(), object!{
+            "id" => url.as_str(),
+            "type" => "https://schema.oparl.org/1.0/System",
+            "body" => "http://example.com/bodies"
+        })
+        .with_response("http://example.com/bodies", object!{
+            "data" => array![object!{
+                "id" => "http://example.com/body/1",
+                "type" => "https://schema.oparl.org/1.0/Body",
+                "paper" => "http://example.com/body/1/papers",
+                "person" => "http://example.com/body/1/persons"
+            }],
+            "links" => object!{}
+        })
+        .with_response("http://example.com/body/1/papers", object!{
+            "data" => array![object!{
+                "id" => "http://example.com/paper/1",
+                "type" => "https://schema.oparl.org/1.0/Paper"
+            }],
+            "links" => object!{}
+        })
+        .with_response("http://example.com/body/1/persons", object!{
+            "data" => array![object!{
+                "id" => "http://example.com/person/1",
+                "type" => "https://schema.oparl.org/1.0/Person"
+            }],
+            "links" => object!{}
+        });
+
+    let mut storage = storage();
+    storage.set_max_workers(Some(1));
+    storage.cache(server).unwrap();
+
+    assert!(storage.get(&"http://example.com/paper/1".into_url().unwrap()).is_ok());
+    assert!(storage.get(&"http://example.com/person/1".into_url().unwrap()).is_ok());
+
+    cleanup(&storage);
+}
@@ -0,0 +1,137 @@
+extern crate oparl_cache;
+#[macro_use]
+extern crate json;
+extern crate reqwest;
+
+mod common;
+
+use reqwest::IntoUrl;
+
+use oparl_cache::{Cacher, IdRewriter, Quirk, Storage};
+use oparl_cache::gc::collect_garbage;
+use oparl_cache::rdf::{RdfFormat, RdfMapping, export_rdf};
+
+use common::*;
+
+/// Assert that the rules are applied
+#[test]
+fn rewrite_ids() {
+    let mut rewriter = IdRewriter {
+        https_hosts: vec!["example.com".to_string()],
+        strip_trailing_slash: true,
+        quirks: vec![Quirk::CollapseSlashes,
+                     Quirk::StripSessionId,
+                     Quirk::RemoveQueryParameter("PHPSESSID".to_string())],
+        ..IdRewriter::default()
+    };
+    rewriter.host_aliases.insert("www.example.com".to_string(), "example.com".to_string());
+    rewriter.host_aliases.insert("10.0.0.1:8080".to_string(), "example.com".to_string());
+
+    assert_eq!(rewriter.rewrite("http://www.example.com/paper/1/"), "https://example.com/paper/1");
+    assert_eq!(rewriter.rewrite("http://10.0.0.1:8080/paper/1"), "https://example.com/paper/1");
+    assert_eq!(rewriter.rewrite("https://example.com/oparl//paper/1;jsessionid=0A1B?PHPSESSID=2&page=2"),
+               "https://example.com/oparl/paper/1?page=2");
+    // Other hosts keep their scheme and strings that aren't urls are left alone
+    assert_eq!(rewriter.rewrite("http://example.org/paper/1"), "http://example.org/paper/1");
+    assert_eq!(rewriter.rewrite("Paper 1"), "Paper 1");
+
+    assert_eq!(Quirk::parse("remove-query-parameter=sid").unwrap(),
+               Quirk::RemoveQueryParameter("sid".to_string()));
+    assert!(Quirk::parse("unknown").is_err());
+}
+
+/// Assert that a server with mixed hosts ends up in one tree and that the rewritten ids and the
+/// ids on other hosts are reported
+#[test]
+fn cache_with_rewritten_ids() {
+    let entrypoint = "https://example.com/".into_url().unwrap();
+    let server = MockingServer::new(entrypoint.clone())
+        .with_response("https://example.com/", object!{
+            "id" => "https://example.com/",
+            "type" => "https://schema.oparl.org/1.0/System",
+            "body" => "http://www.example.com/body"
+        })
+        .with_response("https://example.com/body", object!{
+            "data" => array![object!{
+                "id" => "http://www.example.com/body/1/",
+                "type" => "https://schema.oparl.org/1.0/Body",
+                "system" => "http://www.example.com/"
+            }, object!{
+                "id" => "https://mirror.example.org/body/2",
+                "type" => "https://schema.oparl.org/1.0/Body",
+                "system" => "https://example.com/"
+            }],
+            "links" => object!{}
+        });
+
+    let mut storage = storage();
+    let mut rewriter = IdRewriter {
+        https_hosts: vec!["example.com".to_string()],
+        strip_trailing_slash: true,
+        ..IdRewriter::default()
+    };
+    rewriter.host_aliases.insert("www.example.com".to_string(), "example.com".to_string());
+    storage.set_id_rewriter(rewriter.clone());
+
+    storage.cache(server).unwrap();
+
+    let body = storage.get(&"https://example.com/body/1".into_url().unwrap()).unwrap();
+    assert_eq!(body["id"], "https://example.com/body/1");
+    assert_eq!(body["system"], "https://example.com/");
+    assert!(!storage.get_cache_dir().join("http:www.example.com").exists());
+
+    let report = storage.get_sync_reports(&entrypoint).unwrap().pop().unwrap();
+    assert_eq!(report.rewritten_ids(), 1);
+    // No rule covers the mirror, so its id is reported
+    assert_eq!(report.id_mismatches(), 1);
+    assert!(report.lists[0].errors[0].contains("https://mirror.example.org/body/2"));
+
+    let status = storage.get_cache_status(&entrypoint).unwrap().unwrap();
+    assert_eq!(status.id_rewriter, rewriter);
+
+    cleanup(&storage);
+}
+
+/// Assert that a server cached with rewritten ids can be read, exported and collected through the
+/// entrypoint as given by the user
+#[test]
+fn rewritten_entrypoint() {
+    let entrypoint = "http://www.example.com/".into_url().unwrap();
+    let server = MockingServer::new(entrypoint.clone())
+        .with_response("http://www.example.com/", object!{
+            "id" => "http://www.example.com/",
+            "type" => "https://schema.oparl.org/1.0/System",
+            "body" => "http://www.example.com/body"
+        })
+        .with_response("https://example.com/body", object!{
+            "data" => array![object!{
+                "id" => "http://www.example.com/body/1",
+                "type" => "https://schema.oparl.org/1.0/Body",
+                "system" => "http://www.example.com/"
+            }],
+            "links" => object!{}
+        });
+
+    let mut storage = storage();
+    let mut rewriter = IdRewriter {
+        https_hosts: vec!["example.com".to_string()],
+        ..IdRewriter::default()
+    };
+    rewriter.host_aliases.insert("www.example.com".to_string(), "example.com".to_string());
+    storage.set_id_rewriter(rewriter);
+    storage.cache(server).unwrap();
+
+    assert!(!storage.get_cache_dir().join("http:www.example.com").exists());
+    assert!(storage.get_cache_status(&entrypoint).unwrap().is_some());
+    assert_eq!(storage.get(&entrypoint).unwrap()["id"], "https://example.com/");
+    assert_eq!(storage.iter_objects(&entrypoint, None).count(), 2);
+
+    let mut output = vec![];
+    let count = export_rdf(&storage, &entrypoint, &RdfMapping::default(), RdfFormat::NTriples, &mut output).unwrap();
+    assert_eq!(count, 2);
+
+    assert_eq!(collect_garbage(&storage, false).unwrap().files, 0);
+    assert!(storage.get(&"https://example.com/body/1".into_url().unwrap()).is_ok());
+
+    cleanup(&storage);
+}
@@ -23,6 +23,25 @@ pub fn storage<'a>() -> FileStorage<'a> {
             .unwrap()
 }
 
+/// A storage with the OParl 1.0 schema and the System and Body schema of OParl 1.1. The schema
+/// folder also contains a `.git` folder, which must be ignored
+#[allow(dead_code)]
+pub fn versioned_storage<'a>() -> FileStorage<'a> {
+    let cache_dir = storage().get_cache_dir();
+    let schema_dir = cache_dir.join("schema");
+
+    for &(version, source) in [("1.0", "oparl/schema"), ("1.1", "tests/common/schema-1.1")].iter() {
+        fs::create_dir_all(schema_dir.join(version)).unwrap();
+        for i in fs::read_dir(source).unwrap() {
+            let path = i.unwrap().path();
+            fs::copy(&path, schema_dir.join(version).join(path.file_name().unwrap())).unwrap();
+        }
+    }
+    fs::create_dir_all(schema_dir.join(".git").join("objects")).unwrap();
+
+    FileStorage::new(&schema_dir, cache_dir.clone()).unwrap()
+}
+
 /// Mocking Server with a stub System-object under `url`
 #[allow(dead_code)]
 pub fn mocking_server(url: Url) -> MockingServer {
@@ -30,7 +30,10 @@ impl MockingServer {
 
 impl Server for MockingServer {
     fn get_json(&self, url: Url) -> Result<JsonValue, Box<Error>> {
-        Ok(self.responses[&url].clone())
+        match self.responses.get(&url) {
+            Some(response) => Ok(response.clone()),
+            None => Err(From::from(format!("No response for {}", url))),
+        }
     }
 
     fn get_entrypoint(&self) -> Url {
@@ -0,0 +1,169 @@
+extern crate oparl_cache;
+extern crate rand;
+extern crate reqwest;
+
+use std::env;
+use std::fs::{File, remove_file};
+use std::io::Write;
+use std::path::PathBuf;
+
+use reqwest::IntoUrl;
+
+use oparl_cache::{Auth, Quirk};
+use oparl_cache::config::Config;
+use oparl_cache::daemon::Schedule;
+
+const CONFIG: &str = r#"
+cache_dir = "/var/cache/oparl"
+concurrency = 4
+rate_limit = 10.0
+
+[auth]
+kind = "basic"
+username = "cache"
+password = "secret"
+
+[[servers]]
+entrypoint = "https://example.com/oparl/v1.0"
+schedule = "daily@03:15"
+
+[[servers]]
+entrypoint = "https://example.org/oparl/v1.0"
+rate_limit = 1.0
+
+[servers.auth]
+kind = "bearer"
+token = "abc"
+"#;
+
+fn load(toml: &str) -> Config {
+    let path = env::temp_dir().join(format!("oparl-cache-config-{}.toml", rand::random::<u32>()));
+    File::create(&path).unwrap().write_all(toml.as_bytes()).unwrap();
+    let config = Config::load(&path);
+    remove_file(&path).unwrap();
+    config.unwrap()
+}
+
+/// Assert that the servers get their own settings and fall back to the global ones
+#[test]
+fn parse() {
+    let config = load(CONFIG);
+    assert_eq!(config.cache_dir, Some(PathBuf::from("/var/cache/oparl")));
+    assert_eq!(config.concurrency, Some(4));
+    assert_eq!(config.servers.len(), 2);
+
+    let first = "https://example.com/oparl/v1.0".into_url().unwrap();
+    let second = "https://example.org/oparl/v1.0".into_url().unwrap();
+    assert_eq!(config.rate_limit(&first), Some(10.0));
+    assert_eq!(config.rate_limit(&second), Some(1.0));
+    assert_eq!(config.auth(&second), Some(Auth::Bearer { token: "abc".to_string() }));
+    assert_eq!(config.auth(&first),
+               Some(Auth::Basic {
+                   username: "cache".to_string(),
+                   password: Some("secret".to_string()),
+               }));
+
+    let jobs = config.jobs(&Schedule::parse("60m").unwrap()).unwrap();
+    assert_eq!(jobs[0].schedule, Schedule::Daily(3, 15));
+    assert_eq!(jobs[1].schedule, Schedule::parse("60m").unwrap());
+    assert_eq!(jobs[1].rate_limit, Some(1.0));
+
+    assert!(Config::load(&env::temp_dir().join("oparl-cache-missing.toml")).is_err());
+}
+
+/// Assert that later layers override earlier ones and unset values are kept
+#[test]
+fn merge() {
+    let mut config = load(CONFIG);
+    let vars = vec![("OPARL_CACHE_DIR".to_string(), "/tmp/oparl".to_string()),
+                    ("OPARL_CACHE_CONCURRENCY".to_string(), "2".to_string()),
+                    ("OPARL_CACHE_TOKEN".to_string(), "xyz".to_string()),
+                    ("HOME".to_string(), "/root".to_string())];
+    config.merge(Config::from_env(vars).unwrap());
+
+    assert_eq!(config.cache_dir(), PathBuf::from("/tmp/oparl"));
+    assert_eq!(config.concurrency, Some(2));
+    assert_eq!(config.rate_limit, Some(10.0));
+    assert_eq!(config.auth, Some(Auth::Bearer { token: "xyz".to_string() }));
+    assert_eq!(config.servers.len(), 2);
+    assert_eq!(config.schema_dir(), PathBuf::from("oparl/schema"));
+
+    let invalid = vec![("OPARL_CACHE_RATE_LIMIT".to_string(), "fast".to_string())];
+    assert!(Config::from_env(invalid).is_err());
+}
+
+/// Assert that the printed config contains no secrets and can be read again
+#[test]
+fn redacted() {
+    let config = load(CONFIG);
+    let printed = config.redacted().to_toml().unwrap();
+    assert!(!printed.contains("secret"));
+    assert!(!printed.contains("abc"));
+    assert!(printed.contains("username = \"cache\""));
+
+    let reloaded = load(&printed);
+    assert_eq!(reloaded.servers.len(), 2);
+    assert_eq!(reloaded.concurrency, config.concurrency);
+    assert_eq!(reloaded.auth(&"https://example.org/oparl/v1.0".into_url().unwrap()),
+               Some(Auth::Bearer { token: "********".to_string() }));
+}
+
+/// Assert that the object filter, the id rewrite rules and the snapshot settings are read from the
+/// files and the environment
+#[test]
+fn filter_and_rewrite() {
+    let mut config = load(r#"
+keep_snapshots = true
+
+[object_filter]
+include_types = ["Paper", "Meeting"]
+exclude_lists = ["membership"]
+
+[id_rewrite]
+https_hosts = ["example.com"]
+quirks = ["collapse-slashes"]
+
+[id_rewrite.host_aliases]
+"www.example.com" = "example.com"
+"#);
+    let vars = vec![("OPARL_CACHE_INCLUDE_TYPE".to_string(), "Paper File".to_string()),
+                    ("OPARL_CACHE_QUIRK".to_string(), "remove-query-parameter=PHPSESSID".to_string()),
+                    ("OPARL_CACHE_STRIP_TRAILING_SLASH".to_string(), "true".to_string()),
+                    ("OPARL_CACHE_MAX_SNAPSHOTS".to_string(), "5".to_string())];
+    config.merge(Config::from_env(vars).unwrap());
+
+    assert_eq!(config.keep_snapshots, Some(true));
+    assert_eq!(config.max_snapshots, Some(5));
+    assert_eq!(config.object_filter.include_types, vec!["Paper".to_string(), "File".to_string()]);
+    assert_eq!(config.object_filter.exclude_lists, vec!["membership".to_string()]);
+
+    let rewriter = config.id_rewrite.id_rewriter().unwrap();
+    assert_eq!(rewriter.https_hosts, vec!["example.com".to_string()]);
+    assert!(rewriter.strip_trailing_slash);
+    assert_eq!(rewriter.quirks, vec![Quirk::RemoveQueryParameter("PHPSESSID".to_string())]);
+    assert_eq!(rewriter.rewrite("http://www.example.com/oparl/paper/1/?PHPSESSID=1"),
+               "https://example.com/oparl/paper/1");
+
+    let reloaded = load(&config.to_toml().unwrap());
+    assert_eq!(reloaded, config);
+
+    let invalid = vec![("OPARL_CACHE_KEEP_SNAPSHOTS".to_string(), "sometimes".to_string())];
+    assert!(Config::from_env(invalid).is_err());
+    let invalid = vec![("OPARL_CACHE_QUIRK".to_string(), "unknown".to_string())];
+    assert!(Config::from_env(invalid).is_err());
+}
+
+/// Assert that a password without a username is rejected
+#[test]
+fn password_without_username() {
+    let vars = vec![("OPARL_CACHE_PASSWORD".to_string(), "secret".to_string())];
+    assert!(Config::from_env(vars).is_err());
+
+    let vars = vec![("OPARL_CACHE_USERNAME".to_string(), "cache".to_string()),
+                    ("OPARL_CACHE_PASSWORD".to_string(), "secret".to_string())];
+    assert_eq!(Config::from_env(vars).unwrap().auth,
+               Some(Auth::Basic {
+                   username: "cache".to_string(),
+                   password: Some("secret".to_string()),
+               }));
+}
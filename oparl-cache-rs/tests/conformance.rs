@@ -0,0 +1,69 @@
+extern crate oparl_cache;
+#[macro_use]
+extern crate json;
+extern crate reqwest;
+
+mod common;
+
+use reqwest::IntoUrl;
+
+use oparl_cache::Storage;
+use oparl_cache::conformance::{CheckLimits, check_conformance};
+use oparl_cache::synthetic::{DatasetConfig, SyntheticDataset};
+
+use common::*;
+
+/// Assert that the synthetic server passes all checks
+#[test]
+fn conformance_synthetic_server() {
+    let dataset = SyntheticDataset::new(DatasetConfig { papers: 7, page_size: 3, ..DatasetConfig::default() });
+    let storage = storage();
+
+    let report = check_conformance(&dataset, storage.get_schema(), &CheckLimits::default());
+
+    for (name, check) in report.checks() {
+        assert_eq!((name, &check.failures), (name, &vec![]));
+        assert!(check.passed > 0, "{} wasn't checked", name);
+    }
+    assert_eq!(report.score(), 100.0);
+
+    cleanup(&storage);
+}
+
+/// Assert that looping pages and wrong ids are reported
+#[test]
+fn conformance_broken_server() {
+    let entrypoint = "http://example.com/".into_url().unwrap();
+    let server = MockingServer::new(entrypoint.clone())
+        .with_response("http://example.com/", object!{
+            "id" => "http://example.com/",
+            "type" => "https://schema.oparl.org/1.0/System",
+            "oparlVersion" => "https://schema.oparl.org/1.0/",
+            "body" => "http://example.com/body"
+        })
+        .with_response("http://example.com/body", object!{
+            "data" => array![object!{
+                "id" => "http://example.com/body/1",
+                "type" => "https://schema.oparl.org/1.0/Body"
+            }],
+            "links" => object!{
+                "next" => "http://example.com/body"
+            }
+        })
+        .with_response("http://example.com/body/1", object!{
+            "id" => "https://example.com/body/1",
+            "type" => "https://schema.oparl.org/1.0/Body"
+        });
+    let storage = storage();
+
+    let report = check_conformance(&server, storage.get_schema(), &CheckLimits::default());
+
+    assert_eq!(report.pagination.failed, 1);
+    assert_eq!(report.id_matches_url.failed, 1);
+    assert_eq!(report.id_matches_url.failures,
+               vec!["http://example.com/body/1 returned the id https://example.com/body/1"]);
+    assert_eq!(report.type_urls.failed, 0);
+    assert!(report.score() < 100.0);
+
+    cleanup(&storage);
+}
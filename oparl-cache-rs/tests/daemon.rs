@@ -0,0 +1,159 @@
+extern crate oparl_cache;
+extern crate chrono;
+#[macro_use]
+extern crate json;
+extern crate reqwest;
+
+mod common;
+
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{FixedOffset, Local, LocalResult, NaiveDate, NaiveDateTime, TimeZone};
+use reqwest::IntoUrl;
+
+use oparl_cache::daemon::{Daemon, Job, Schedule};
+use oparl_cache::metrics::{Metrics, serve_metrics};
+
+use common::*;
+
+/// Central European Time around the switch to summer time on 2017-03-26, when the clocks jump
+/// from 02:00 to 03:00
+#[derive(Clone, Copy, Debug)]
+struct SpringForward;
+
+impl TimeZone for SpringForward {
+    type Offset = FixedOffset;
+
+    fn from_offset(_: &FixedOffset) -> SpringForward {
+        SpringForward
+    }
+
+    fn offset_from_local_date(&self, local: &NaiveDate) -> LocalResult<FixedOffset> {
+        self.offset_from_local_datetime(&local.and_hms(12, 0, 0))
+    }
+
+    fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> LocalResult<FixedOffset> {
+        let switch = NaiveDate::from_ymd(2017, 3, 26);
+        if *local < switch.and_hms(2, 0, 0) {
+            LocalResult::Single(FixedOffset::east(3600))
+        } else if *local < switch.and_hms(3, 0, 0) {
+            LocalResult::None
+        } else {
+            LocalResult::Single(FixedOffset::east(7200))
+        }
+    }
+
+    fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+        self.offset_from_utc_datetime(&utc.and_hms(0, 0, 0))
+    }
+
+    fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+        if *utc < NaiveDate::from_ymd(2017, 3, 26).and_hms(1, 0, 0) {
+            FixedOffset::east(3600)
+        } else {
+            FixedOffset::east(7200)
+        }
+    }
+}
+
+/// Assert that intervals and daily times are parsed
+#[test]
+fn parse_schedule() {
+    assert_eq!(Schedule::parse("90s").unwrap(), Schedule::Every(Duration::from_secs(90)));
+    assert_eq!(Schedule::parse("30m").unwrap(), Schedule::Every(Duration::from_secs(30 * 60)));
+    assert_eq!(Schedule::parse("2h").unwrap(), Schedule::Every(Duration::from_secs(2 * 60 * 60)));
+    assert_eq!(Schedule::parse("daily@03:15").unwrap(), Schedule::Daily(3, 15));
+
+    assert!(Schedule::parse("0m").is_err());
+    assert!(Schedule::parse("30x").is_err());
+    assert!(Schedule::parse("daily@25:00").is_err());
+}
+
+/// Assert that daily runs happen on the same day if the time hasn't passed yet and on the next
+/// day otherwise
+#[test]
+fn daily_next_run() {
+    let schedule = Schedule::Daily(3, 15);
+
+    let before = Local.ymd(2017, 6, 1).and_hms(1, 0, 0);
+    assert_eq!(schedule.next_run(before), Local.ymd(2017, 6, 1).and_hms(3, 15, 0));
+
+    let after = Local.ymd(2017, 6, 1).and_hms(3, 15, 0);
+    assert_eq!(schedule.next_run(after), Local.ymd(2017, 6, 2).and_hms(3, 15, 0));
+}
+
+/// Assert that a daily time in the gap of the switch to summer time is skipped on that day
+#[test]
+fn daily_next_run_daylight_saving_time() {
+    let schedule = Schedule::Daily(2, 30);
+
+    let before = SpringForward.ymd(2017, 3, 25).and_hms(12, 0, 0);
+    assert_eq!(schedule.next_run(before), SpringForward.ymd(2017, 3, 27).and_hms(2, 30, 0));
+
+    let schedule = Schedule::Daily(3, 15);
+    assert_eq!(schedule.next_run(before), SpringForward.ymd(2017, 3, 26).and_hms(3, 15, 0));
+}
+
+/// Assert that the metrics are rendered in the Prometheus text format
+#[test]
+fn render_metrics() {
+    let entrypoint = "https://example.com/oparl/v1.0".into_url().unwrap();
+    let mut metrics = Metrics::new(&[entrypoint.clone()]);
+
+    let rendered = metrics.render();
+    assert!(rendered.contains("# TYPE oparl_cache_syncs_total counter\n"));
+    assert!(rendered.contains("oparl_cache_syncs_total{entrypoint=\"https://example.com/oparl/v1.0\"} 0\n"));
+    // There was no successful sync yet
+    assert!(!rendered.contains("oparl_cache_last_success_timestamp_seconds{"));
+
+    metrics.record(&entrypoint, true, None, Some(42), Duration::from_millis(1500));
+
+    let rendered = metrics.render();
+    assert!(rendered.contains("oparl_cache_objects_cached{entrypoint=\"https://example.com/oparl/v1.0\"} 42\n"));
+    assert!(rendered.contains("oparl_cache_syncs_total{entrypoint=\"https://example.com/oparl/v1.0\"} 1\n"));
+    assert!(rendered.contains("oparl_cache_sync_duration_seconds{entrypoint=\"https://example.com/oparl/v1.0\"} 1.5\n"));
+    assert!(rendered.contains("oparl_cache_last_success_timestamp_seconds{"));
+}
+
+/// Assert that a run which couldn't fetch the System object counts as failed and that the
+/// metrics and the health check are served over http
+#[test]
+fn serve_daemon_metrics() {
+    let storage = storage();
+    let cache_dir = storage.get_cache_dir();
+    // Nothing listens on this port, so the System object can't be fetched
+    let entrypoint = "http://127.0.0.1:1/oparl".into_url().unwrap();
+    let job = Job::new(entrypoint.clone(), Schedule::Every(Duration::from_secs(3600)));
+    let mut daemon = Daemon::new(storage, vec![job]);
+    let metrics: Arc<Mutex<Metrics>> = daemon.get_metrics();
+
+    daemon.run_next();
+    {
+        let metrics = metrics.lock().unwrap();
+        let server = metrics.get(&entrypoint).unwrap();
+        assert_eq!(server.syncs, 1);
+        assert_eq!(server.failed_syncs, 1);
+        assert_eq!(server.last_success, None);
+    }
+
+    let mut listening = serve_metrics("127.0.0.1:0", metrics).unwrap();
+
+    let mut response = reqwest::get(&format!("http://{}/metrics", listening.socket)).unwrap();
+    assert!(response.status().is_success());
+    let mut body = String::new();
+    response.read_to_string(&mut body).unwrap();
+    assert!(body.contains("oparl_cache_failed_syncs_total{entrypoint=\"http://127.0.0.1:1/oparl\"} 1\n"));
+
+    let mut response = reqwest::get(&format!("http://{}/healthz", listening.socket)).unwrap();
+    assert!(response.status().is_success());
+    let mut body = String::new();
+    response.read_to_string(&mut body).unwrap();
+    assert_eq!(body, "ok\n");
+
+    assert!(!reqwest::get(&format!("http://{}/other", listening.socket)).unwrap().status().is_success());
+
+    listening.close().unwrap();
+    std::fs::remove_dir_all(cache_dir).unwrap();
+}
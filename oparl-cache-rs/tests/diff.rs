@@ -0,0 +1,196 @@
+extern crate oparl_cache;
+#[macro_use]
+extern crate json;
+extern crate reqwest;
+
+mod common;
+
+use std::thread::sleep;
+use std::time::Duration;
+
+use json::JsonValue;
+use reqwest::IntoUrl;
+
+use oparl_cache::{Cacher, Storage};
+use oparl_cache::diff::{DiffOptions, DiffSide, diff};
+use oparl_cache::snapshot::{list_snapshots, prune_snapshots, read_snapshot};
+
+use common::*;
+
+fn side(entrypoint: &str, objects: Vec<JsonValue>) -> DiffSide {
+    DiffSide {
+        entrypoint: entrypoint.into_url().unwrap(),
+        objects: objects,
+    }
+}
+
+/// Assert that objects are matched by their keys and references are compared by the key of the
+/// referenced object, so the different ids of the two servers don't matter
+#[test]
+fn different_servers() {
+    let old = side("http://old.example.com/oparl",
+                   vec![object!{
+                            "id" => "http://old.example.com/oparl/meeting/7",
+                            "type" => "https://schema.oparl.org/1.0/Meeting",
+                            "name" => "Council",
+                            "start" => "2017-05-01T18:00:00+02:00"
+                        },
+                        object!{
+                            "id" => "http://old.example.com/oparl/paper/1",
+                            "type" => "https://schema.oparl.org/1.0/Paper",
+                            "reference" => "2017/001",
+                            "name" => "Budget",
+                            "modified" => "2017-05-01T12:00:00+02:00"
+                        },
+                        object!{
+                            "id" => "http://old.example.com/oparl/consultation/3",
+                            "type" => "https://schema.oparl.org/1.0/Consultation",
+                            "paper" => "http://old.example.com/oparl/paper/1",
+                            "meeting" => "http://old.example.com/oparl/meeting/7",
+                            "role" => "decision"
+                        },
+                        object!{
+                            "id" => "http://old.example.com/oparl/paper/2",
+                            "type" => "https://schema.oparl.org/1.0/Paper",
+                            "reference" => "2017/002"
+                        }]);
+    let new = side("https://new.example.com/api",
+                   vec![object!{
+                            "id" => "https://new.example.com/api/meetings/1",
+                            "type" => "https://schema.oparl.org/1.0/Meeting",
+                            "name" => "Council",
+                            "start" => "2017-05-01T18:00:00+02:00"
+                        },
+                        object!{
+                            "id" => "https://new.example.com/api/papers/1",
+                            "type" => "https://schema.oparl.org/1.0/Paper",
+                            "reference" => "2017/001",
+                            "name" => "Budget 2017",
+                            "modified" => "2018-01-01T12:00:00+01:00"
+                        },
+                        object!{
+                            "id" => "https://new.example.com/api/consultations/1",
+                            "type" => "https://schema.oparl.org/1.0/Consultation",
+                            "paper" => "https://new.example.com/api/papers/1",
+                            "meeting" => "https://new.example.com/api/meetings/1",
+                            "role" => "decision"
+                        },
+                        object!{
+                            "id" => "https://new.example.com/api/papers/3",
+                            "type" => "https://schema.oparl.org/1.0/Paper",
+                            "reference" => "2017/003"
+                        }]);
+
+    let report = diff(&old, &new, &DiffOptions::default());
+    assert_eq!(report.matched, 3);
+    assert_eq!(report.missing.len(), 1);
+    assert_eq!(report.missing[0].id, "http://old.example.com/oparl/paper/2");
+    assert_eq!(report.extra.len(), 1);
+    assert_eq!(report.extra[0].key, "Paper[reference=\"2017/003\"]");
+
+    // Only the name differs, `modified` is ignored by default
+    assert_eq!(report.changed.len(), 1);
+    assert_eq!(report.changed[0].right, "https://new.example.com/api/papers/1");
+    assert_eq!(report.changed[0].properties.len(), 1);
+    assert_eq!(report.changed[0].properties[0].property, "name");
+    assert_eq!(report.changed[0].properties[0].right, Some("\"Budget 2017\"".to_string()));
+
+    // With the name as key, the papers don't match anymore
+    let mut options = DiffOptions::default();
+    options.set_key("Paper=name").unwrap();
+    assert!(options.set_key("Paper").is_err());
+    let report = diff(&old, &new, &options);
+    assert_eq!(report.matched, 1);
+    assert_eq!(report.missing.len(), 3);
+    assert!(report.changed.is_empty());
+}
+
+/// Assert that a snapshot is written after every run and can be compared with the cache
+#[test]
+fn snapshots() {
+    let url = "http://example.com/".into_url().unwrap();
+    let list_url = "http://example.com/bodies";
+    let server = mocking_server(url.clone())
+        .with_response(url.clone(), object!{
+            "id" => url.as_str(),
+            "type" => "https://schema.oparl.org/1.0/System",
+            "body" => list_url
+        })
+        .with_response(list_url, object!{
+            "data" => array![object!{
+                "id" => "http://example.com/body/1",
+                "type" => "https://schema.oparl.org/1.0/Body",
+                "name" => "Example City"
+            }],
+            "links" => object!{}
+        });
+
+    let mut storage = storage();
+    storage.set_keep_snapshots(true);
+    // An object on the same host that no list contains isn't part of the server
+    let orphan = "http://example.com/paper/1".into_url().unwrap();
+    storage.write_to_cache(&orphan,
+                        &object!{
+                            "id" => orphan.as_str(),
+                            "type" => "https://schema.oparl.org/1.0/Paper"
+                        })
+        .unwrap();
+    storage.cache(server).unwrap();
+
+    let names = list_snapshots(&storage, &url).unwrap();
+    assert_eq!(names.len(), 1);
+    assert_eq!(read_snapshot(&storage, &url, &names[0]).unwrap().len(), 2);
+    assert!(read_snapshot(&storage, &url, "2000-01-01T00:00:00+00:00").is_err());
+
+    // The snapshots aren't returned as cached objects
+    assert_eq!(storage.iter_objects(&url, None).count(), 3);
+
+    let body = "http://example.com/body/1".into_url().unwrap();
+    storage.write_to_cache(&body,
+                        &object!{
+                            "id" => body.as_str(),
+                            "type" => "https://schema.oparl.org/1.0/Body",
+                            "name" => "Example Town"
+                        })
+        .unwrap();
+
+    let before = DiffSide::snapshot(&storage, &url, &names[0]).unwrap();
+    let after = DiffSide::cached(&storage, &url).unwrap();
+    assert_eq!(after.objects.len(), 2);
+    let report = diff(&before, &after, &DiffOptions::default());
+    assert_eq!(report.matched, 1);
+    assert_eq!(report.missing.len(), 1);
+    assert_eq!(report.extra.len(), 1);
+    assert_eq!(report.extra[0].key, "Body[name=\"Example Town\"]");
+
+    // Matched by the id, the name is a changed property
+    let mut options = DiffOptions::default();
+    options.keys.remove("Body");
+    let report = diff(&before, &after, &options);
+    assert_eq!(report.matched, 2);
+    assert_eq!(report.changed[0].properties[0].property, "name");
+
+    cleanup(&storage);
+}
+
+/// Assert that only the latest snapshots are kept
+#[test]
+fn pruned_snapshots() {
+    let url = "http://example.com/".into_url().unwrap();
+    let mut storage = storage();
+    storage.set_keep_snapshots(true);
+    storage.set_max_snapshots(2);
+    for _ in 0..3 {
+        storage.cache(mocking_server(url.clone())).unwrap();
+        // The snapshots are named after the start of the run
+        sleep(Duration::from_millis(10));
+    }
+
+    let names = list_snapshots(&storage, &url).unwrap();
+    assert_eq!(names.len(), 2);
+    assert_eq!(prune_snapshots(&storage, &url, 1).unwrap(), 1);
+    assert_eq!(list_snapshots(&storage, &url).unwrap(), vec![names[1].clone()]);
+    assert_eq!(prune_snapshots(&storage, &url, 0).unwrap(), 0);
+
+    cleanup(&storage);
+}
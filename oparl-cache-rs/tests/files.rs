@@ -0,0 +1,154 @@
+extern crate oparl_cache;
+#[macro_use]
+extern crate json;
+extern crate reqwest;
+
+mod common;
+
+use std::cell::Cell;
+use std::error::Error;
+
+use json::JsonValue;
+use reqwest::{IntoUrl, Url};
+
+use oparl_cache::{FileStorage, Storage};
+use oparl_cache::files::{Format, checksum, extract_texts, html_to_text, mirror_files, read_text};
+
+use common::*;
+
+fn write(storage: &FileStorage, object: &JsonValue) -> Url {
+    let url = object["id"].as_str().unwrap().into_url().unwrap();
+    storage.write_to_cache(&url, object).unwrap();
+    url
+}
+
+/// Assert that the tags, scripts and entities are removed from HTML
+#[test]
+fn html() {
+    let html = "<html><head><title>Budget</title><script>var x = '<p>';</script></head>\
+                <body><h1>Budget&nbsp;2018</h1><p>Income &amp; <b>expenses</b></p>\
+                <table><tr><td>1</td><td>2</td></tr></table></body></html>";
+    assert_eq!(html_to_text(html), "Budget\nBudget 2018\nIncome & expenses\n1 2");
+
+    assert_eq!(Format::detect(b"%PDF-1.4", None, None), Some(Format::Pdf));
+    assert_eq!(Format::detect(b"", Some("text/html; charset=utf-8"), None), Some(Format::Html));
+    assert_eq!(Format::detect(b"", None, Some("Protokoll.TXT")), Some(Format::Text));
+    assert_eq!(Format::detect(b"", Some("image/png"), Some("map.png")), None);
+}
+
+/// Assert that documents are mirrored and their text is only extracted again when the checksum
+/// changed, leaving the File object untouched
+#[test]
+fn extract() {
+    let storage = storage();
+    let entrypoint = "http://example.com/".into_url().unwrap();
+    let file = object!{
+        "id" => "http://example.com/file/1",
+        "type" => "https://schema.oparl.org/1.0/File",
+        "mimeType" => "text/html",
+        "accessUrl" => "http://example.com/files/1.html"
+    };
+    let url = write(&storage, &file);
+    write(&storage,
+          &object!{
+              "id" => "http://example.com/file/2",
+              "type" => "https://schema.oparl.org/1.0/File",
+              "fileName" => "scan.pdf",
+              "accessUrl" => "http://example.com/files/2.pdf"
+          });
+
+    let content = Cell::new("<p>First version</p>");
+    let downloads = Cell::new(0);
+    let download = |url: &Url| -> Result<Vec<u8>, Box<Error>> {
+        downloads.set(downloads.get() + 1);
+        if url.path().ends_with(".pdf") {
+            Ok(b"%PDF-1.4".to_vec())
+        } else {
+            Ok(content.get().as_bytes().to_vec())
+        }
+    };
+
+    let mirrored = mirror_files(&storage, &entrypoint, false, &download).unwrap();
+    assert_eq!(mirrored.downloaded, 2);
+    assert_eq!(mirror_files(&storage, &entrypoint, false, &download).unwrap().unchanged, 2);
+    assert_eq!(downloads.get(), 2);
+
+    let report = extract_texts(&storage, &entrypoint, false).unwrap();
+    // The PDF is invalid or, without the pdf feature, unsupported
+    assert_eq!(report.extracted, 1);
+    let text = read_text(&storage, &url).unwrap().unwrap();
+    assert_eq!(text.text, Some("First version".to_string()));
+    assert_eq!(text.checksum, checksum(b"<p>First version</p>"));
+    assert_eq!(storage.get(&url).unwrap(), file);
+
+    let report = extract_texts(&storage, &entrypoint, false).unwrap();
+    assert_eq!(report.extracted, 0);
+    assert_eq!(report.unchanged, if cfg!(feature = "pdf") { 2 } else { 1 });
+
+    content.set("<p>Second version</p>");
+    mirror_files(&storage, &entrypoint, true, &download).unwrap();
+    let report = extract_texts(&storage, &entrypoint, false).unwrap();
+    assert_eq!(report.extracted, 1);
+    assert_eq!(read_text(&storage, &url).unwrap().unwrap().text, Some("Second version".to_string()));
+
+    // The text files aren't returned as objects
+    assert_eq!(storage.iter_objects(&entrypoint, None).count(), 2);
+
+    cleanup(&storage);
+}
+
+/// Assert that documents without a checksum are downloaded again when the url or the modification
+/// date changed and that invalid urls don't stop the run
+#[test]
+fn mirror_versions() {
+    let storage = storage();
+    let entrypoint = "http://example.com/".into_url().unwrap();
+    let mut file = object!{
+        "id" => "http://example.com/file/1",
+        "type" => "https://schema.oparl.org/1.0/File",
+        "modified" => "2017-01-01T00:00:00+01:00",
+        "accessUrl" => "http://example.com/files/1.html"
+    };
+    write(&storage, &file);
+    write(&storage,
+          &object!{
+              "id" => "http://example.com/file/2",
+              "type" => "https://schema.oparl.org/1.0/File",
+              "accessUrl" => "not a url"
+          });
+    storage.write_to_cache(&"http://example.com/file/3".into_url().unwrap(),
+                        &object!{
+                            "id" => "not an id",
+                            "type" => "https://schema.oparl.org/1.0/File",
+                            "accessUrl" => "http://example.com/files/3.html"
+                        })
+        .unwrap();
+
+    let downloads = Cell::new(0);
+    let download = |_: &Url| -> Result<Vec<u8>, Box<Error>> {
+        downloads.set(downloads.get() + 1);
+        Ok(b"<p>Protokoll</p>".to_vec())
+    };
+
+    let report = mirror_files(&storage, &entrypoint, false, &download).unwrap();
+    assert_eq!((report.downloaded, report.failed), (1, 2));
+    assert_eq!(mirror_files(&storage, &entrypoint, false, &download).unwrap().unchanged, 1);
+
+    file["modified"] = "2017-02-01T00:00:00+01:00".into();
+    write(&storage, &file);
+    assert_eq!(mirror_files(&storage, &entrypoint, false, &download).unwrap().downloaded, 1);
+
+    file["downloadUrl"] = "http://example.com/files/1-download.html".into();
+    write(&storage, &file);
+    assert_eq!(mirror_files(&storage, &entrypoint, false, &download).unwrap().downloaded, 1);
+    assert_eq!(mirror_files(&storage, &entrypoint, false, &download).unwrap().unchanged, 1);
+    assert_eq!(downloads.get(), 3);
+
+    let report = extract_texts(&storage, &entrypoint, false).unwrap();
+    assert_eq!((report.extracted, report.failed), (1, 1));
+
+    // The records of the downloads aren't returned as objects
+    assert_eq!(storage.iter_objects(&entrypoint, None).count(), 3);
+
+    cleanup(&storage);
+}
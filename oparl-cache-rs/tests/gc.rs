@@ -0,0 +1,177 @@
+extern crate oparl_cache;
+#[macro_use]
+extern crate json;
+extern crate reqwest;
+
+mod common;
+
+use std::fs::{File, remove_file};
+use std::sync::mpsc::channel;
+
+use json::JsonValue;
+use reqwest::IntoUrl;
+
+use oparl_cache::{Cacher, FileStorage, ReferenceIndex, Storage};
+use oparl_cache::file_storage::{FILE_EXTENSION, GC_LOCK, LOCKS_DIR};
+use oparl_cache::gc::{collect_garbage, remove_server};
+
+use common::*;
+
+fn cache_server(storage: &FileStorage) {
+    cache_host(storage, "http://example.com/", array![]);
+}
+
+/// Caches a server with a single body whose `equivalent` are the given urls
+fn cache_host(storage: &FileStorage, url: &str, equivalent: JsonValue) {
+    let url = url.into_url().unwrap();
+    let list_url = url.join("bodies").unwrap();
+    let server = mocking_server(url.clone())
+        .with_response(url.clone(), object!{
+            "id" => url.as_str(),
+            "type" => "https://schema.oparl.org/1.0/System",
+            "body" => list_url.as_str()
+        })
+        .with_response(list_url, object!{
+            "data" => array![object!{
+                "id" => url.join("body/1").unwrap().as_str(),
+                "type" => "https://schema.oparl.org/1.0/Body",
+                "equivalent" => equivalent
+            }],
+            "links" => object!{}
+        });
+    storage.cache(server).unwrap();
+}
+
+/// Assert that only the objects that can't be reached from the System object are collected
+#[test]
+fn garbage_collection() {
+    let storage = storage();
+    cache_server(&storage);
+
+    // A paper that was removed from all lists, but still references the body
+    let (add_list, _receive_list) = channel();
+    let mut orphan = object!{
+        "id" => "http://example.com/paper/1",
+        "type" => "https://schema.oparl.org/1.0/Paper",
+        "body" => "http://example.com/body/1"
+    };
+    storage.parse_object(&mut orphan, add_list).unwrap();
+    let orphan = "http://example.com/paper/1".into_url().unwrap();
+    let body = "http://example.com/body/1".into_url().unwrap();
+    assert_eq!(storage.get_references(&body).unwrap().len(), 1);
+
+    let report = collect_garbage(&storage, true).unwrap();
+    assert!(storage.get(&orphan).is_ok());
+
+    assert_eq!(collect_garbage(&storage, false).unwrap(), report);
+    assert!(storage.get(&orphan).is_err());
+    assert!(storage.get(&body).is_ok());
+    assert!(storage.get_references(&body).unwrap().is_empty());
+    // The removed paper and the reference file of the body
+    assert_eq!(report.files, 2);
+    assert!(report.bytes > 0);
+
+    assert_eq!(collect_garbage(&storage, false).unwrap().files, 0);
+
+    cleanup(&storage);
+}
+
+/// Assert that removing a server deletes its folder and its entry in the cached servers
+#[test]
+fn remove() {
+    let storage = storage();
+    cache_server(&storage);
+    let url = "http://example.com/".into_url().unwrap();
+
+    let report = remove_server(&storage, &url, true).unwrap();
+    assert_eq!(storage.get_cached_servers().unwrap(), vec![url.clone()]);
+
+    assert_eq!(remove_server(&storage, &url, false).unwrap(), report);
+    assert!(storage.get_cached_servers().unwrap().is_empty());
+    assert!(!storage.get_cache_dir().join("http:example.com").exists());
+    assert!(remove_server(&storage, &url, false).is_err());
+
+    cleanup(&storage);
+}
+
+/// Assert that nothing is deleted if the remaining servers can't be marked
+#[test]
+fn remove_unmarkable() {
+    let storage = storage();
+    cache_host(&storage, "http://example.com/", array![]);
+    cache_host(&storage, "http://example.org/", array![]);
+    let url = "http://example.com/".into_url().unwrap();
+    let status_file = storage.url_to_path(&url, "").join(storage.get_cache_status_file());
+    assert!(status_file.is_file());
+
+    // Without the System object of the other server, marking fails
+    remove_file(storage.url_to_path(&"http://example.org/".into_url().unwrap(), FILE_EXTENSION)).unwrap();
+    assert!(remove_server(&storage, &url, false).is_err());
+    assert!(status_file.is_file());
+    assert_eq!(storage.get_cached_servers().unwrap().len(), 2);
+
+    cleanup(&storage);
+}
+
+/// Assert that objects referenced from a server on another host are kept
+#[test]
+fn cross_host_references() {
+    let storage = storage();
+    let shared = "http://example.org/organization/1".into_url().unwrap();
+    let orphan = "http://example.org/organization/2".into_url().unwrap();
+    cache_host(&storage, "http://example.com/", array![shared.as_str()]);
+    cache_host(&storage, "http://example.org/", array![]);
+    for id in &[&shared, &orphan] {
+        storage.write_to_cache(id,
+                            &object!{
+                                "id" => id.as_str(),
+                                "type" => "https://schema.oparl.org/1.0/Organization"
+                            })
+            .unwrap();
+    }
+
+    assert_eq!(collect_garbage(&storage, false).unwrap().files, 1);
+    assert!(storage.get(&shared).is_ok());
+    assert!(storage.get(&orphan).is_err());
+
+    // Once nothing references it anymore, it's deleted with the server
+    remove_server(&storage, &"http://example.com/".into_url().unwrap(), false).unwrap();
+    assert!(storage.get(&shared).is_err());
+    assert!(storage.get(&"http://example.org/body/1".into_url().unwrap()).is_ok());
+
+    cleanup(&storage);
+}
+
+/// Assert that the garbage collection refuses to run while the cache is in use
+#[test]
+fn locked_cache() {
+    let storage = storage();
+    cache_server(&storage);
+    let url = "http://example.com/".into_url().unwrap();
+
+    let lock = storage.create_lock("daemon").unwrap();
+    assert!(collect_garbage(&storage, true).is_err());
+    assert!(remove_server(&storage, &url, true).is_err());
+    drop(lock);
+
+    assert!(collect_garbage(&storage, true).is_ok());
+    assert!(storage.get_locks().unwrap().is_empty());
+
+    // The lock files are kept, but a lock that isn't held doesn't block anything
+    assert!(storage.get_cache_dir().join(LOCKS_DIR).join("daemon.lock").is_file());
+    File::create(storage.get_cache_dir().join(LOCKS_DIR).join("sync-crashed.lock")).unwrap();
+    assert!(storage.get_locks().unwrap().is_empty());
+    assert!(collect_garbage(&storage, true).is_ok());
+    let lock = storage.create_lock("daemon").unwrap();
+    assert!(storage.create_lock("daemon").is_err());
+    assert_eq!(storage.get_locks().unwrap(), vec!["daemon".to_string()]);
+    drop(lock);
+
+    // A sync refuses to run during the garbage collection
+    let gc_lock = storage.create_lock(GC_LOCK).unwrap();
+    let server = mocking_server(url.clone());
+    assert!(storage.cache(server).is_err());
+    drop(gc_lock);
+
+    cleanup(&storage);
+}
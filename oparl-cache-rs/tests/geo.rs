@@ -0,0 +1,84 @@
+extern crate oparl_cache;
+#[macro_use]
+extern crate json;
+extern crate reqwest;
+
+mod common;
+
+use std::sync::mpsc::channel;
+
+use reqwest::IntoUrl;
+
+use oparl_cache::Cacher;
+use oparl_cache::geo::{BoundingBox, export_geojson};
+
+use common::*;
+
+/// Assert that locations are exported with the papers referencing them and filtered by a bbox
+#[test]
+fn feature_collection() {
+    let storage = storage();
+    let (add_list, _receive_list) = channel();
+
+    let mut input = object!{
+        "id" => "http://example.com/paper/1",
+        "type" => "https://schema.oparl.org/1.0/Paper",
+        "name" => "New bike lanes",
+        "location" => array![object!{
+            "id" => "http://example.com/location/1",
+            "type" => "https://schema.oparl.org/1.0/Location",
+            "description" => "Town hall",
+            "geojson" => object!{
+                "type" => "Feature",
+                "geometry" => object!{
+                    "type" => "Point",
+                    "coordinates" => array![7.1, 50.7]
+                },
+                "properties" => object!{ "name" => "Town hall" }
+            }
+        }]
+    };
+    storage.parse_object(&mut input, add_list).unwrap();
+
+    let entrypoint = "http://example.com/oparl".into_url().unwrap();
+    let collection = export_geojson(&storage, &entrypoint, None).unwrap();
+
+    assert_eq!(collection["type"], "FeatureCollection");
+    assert_eq!(collection["features"].len(), 1);
+    let feature = &collection["features"][0];
+    assert_eq!(feature["geometry"]["coordinates"], array![7.1, 50.7]);
+    assert_eq!(feature["properties"]["name"], "Town hall");
+    assert_eq!(feature["properties"]["description"], "Town hall");
+    assert_eq!(feature["properties"]["referencedBy"],
+               array![object!{
+                   "id" => "http://example.com/paper/1",
+                   "type" => "Paper",
+                   "name" => "New bike lanes"
+               }]);
+
+    let inside = BoundingBox::parse("7,50,8,51").unwrap();
+    assert_eq!(export_geojson(&storage, &entrypoint, Some(&inside)).unwrap()["features"].len(), 1);
+    let outside = BoundingBox::parse("-1,50,1,51").unwrap();
+    assert_eq!(export_geojson(&storage, &entrypoint, Some(&outside)).unwrap()["features"].len(), 0);
+
+    cleanup(&storage);
+}
+
+#[test]
+fn bounding_box() {
+    assert!(BoundingBox::parse("1,2,3").is_err());
+    assert!(BoundingBox::parse("3,2,1,4").is_err());
+
+    let polygon = object!{
+        "type" => "Polygon",
+        "coordinates" => array![array![array![0, 0], array![2, 0], array![2, 1], array![0, 0]]]
+    };
+    assert_eq!(BoundingBox::of_geojson(&polygon),
+               Some(BoundingBox {
+                   min_lon: 0.0,
+                   min_lat: 0.0,
+                   max_lon: 2.0,
+                   max_lat: 1.0,
+               }));
+    assert!(BoundingBox::of_geojson(&object!{ "type" => "Point" }).is_none());
+}
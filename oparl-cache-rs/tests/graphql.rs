@@ -0,0 +1,182 @@
+extern crate oparl_cache;
+#[macro_use]
+extern crate json;
+extern crate reqwest;
+
+mod common;
+
+use std::sync::mpsc::channel;
+
+use json::JsonValue;
+use reqwest::IntoUrl;
+
+use oparl_cache::{Cacher, Storage};
+use oparl_cache::graphql::{execute, render_schema};
+
+use common::*;
+
+/// Assert that references and embedded objects are resolved in nested selections
+#[test]
+fn nested_query() {
+    let storage = storage();
+    let (add_list, _receive_list) = channel();
+
+    let mut input = object!{
+        "id" => "http://example.com/paper/1",
+        "type" => "https://schema.oparl.org/1.0/Paper",
+        "name" => "Budget",
+        "consultation" => array![object!{
+            "id" => "http://example.com/consultation/1",
+            "type" => "https://schema.oparl.org/1.0/Consultation",
+            "paper" => "http://example.com/paper/1",
+            "role" => "decision"
+        }]
+    };
+    storage.parse_object(&mut input, add_list).unwrap();
+
+    let query = r#"
+        query Papers($paper: String!) {
+            budget: paper(id: $paper) {
+                name
+                consultation { __typename role paper { name } }
+            }
+        }
+    "#;
+    let response = execute(&storage, query, &object!{ "paper" => "http://example.com/paper/1" });
+
+    assert_eq!(response,
+               object!{
+                   "data" => object!{
+                       "budget" => object!{
+                           "name" => "Budget",
+                           "consultation" => array![object!{
+                               "__typename" => "Consultation",
+                               "role" => "decision",
+                               "paper" => object!{ "name" => "Budget" }
+                           }]
+                       }
+                   }
+               });
+
+    // Without a selection only the id is returned
+    let response = execute(&storage,
+                           r#"{ consultation(id: "http://example.com/consultation/1") { paper } }"#,
+                           &JsonValue::Null);
+    assert_eq!(response["data"]["consultation"]["paper"], "http://example.com/paper/1");
+
+    cleanup(&storage);
+}
+
+/// Assert that external lists are paginated with `first` and `after`
+#[test]
+fn connection_pagination() {
+    let storage = storage();
+
+    let body = object!{
+        "id" => "http://example.com/body/1",
+        "type" => "https://schema.oparl.org/1.0/Body",
+        "paper" => "http://example.com/body/1/paper"
+    };
+    storage.write_to_cache(&"http://example.com/body/1".into_url().unwrap(), &body).unwrap();
+
+    let mut ids = JsonValue::new_array();
+    for i in 0..5 {
+        let id = format!("http://example.com/paper/{}", i);
+        let paper = object!{
+            "id" => id.as_str(),
+            "type" => "https://schema.oparl.org/1.0/Paper",
+            "name" => format!("Paper {}", i)
+        };
+        storage.write_to_cache(&id.as_str().into_url().unwrap(), &paper).unwrap();
+        ids.push(id).unwrap();
+    }
+    storage.write_to_cache(&"http://example.com/body/1/paper".into_url().unwrap(), &ids).unwrap();
+
+    let query = r#"{
+        body(id: "http://example.com/body/1") {
+            paper(first: 2, after: "2") { totalCount nodes { name } pageInfo { hasNextPage endCursor } }
+        }
+    }"#;
+    let response = execute(&storage, query, &JsonValue::Null);
+
+    assert_eq!(response["data"]["body"]["paper"],
+               object!{
+                   "totalCount" => 5,
+                   "nodes" => array![object!{ "name" => "Paper 2" }, object!{ "name" => "Paper 3" }],
+                   "pageInfo" => object!{ "hasNextPage" => true, "endCursor" => "4" }
+               });
+
+    // Cursors beyond the end return an empty page instead of overflowing
+    let query = r#"{
+        body(id: "http://example.com/body/1") {
+            paper(first: 100, after: "18446744073709551615") { nodes { name } pageInfo { hasNextPage endCursor } }
+        }
+    }"#;
+    let response = execute(&storage, query, &JsonValue::Null);
+    assert_eq!(response["data"]["body"]["paper"],
+               object!{
+                   "nodes" => array![],
+                   "pageInfo" => object!{ "hasNextPage" => false, "endCursor" => "5" }
+               });
+
+    cleanup(&storage);
+}
+
+/// Assert that unsupported operations, unknown fields and wrong types are reported as errors
+#[test]
+fn query_errors() {
+    let storage = storage();
+
+    let response = execute(&storage, "mutation { paper }", &JsonValue::Null);
+    assert!(response["data"].is_null());
+    assert_eq!(response["errors"].len(), 1);
+
+    let response = execute(&storage, "{ paper(id: \"http://example.com/missing\") { name } }", &JsonValue::Null);
+    assert!(response["data"]["paper"].is_null());
+    assert_eq!(response["errors"].len(), 1);
+
+    let response = execute(&storage, "{ unknown(id: \"x\") { name } }", &JsonValue::Null);
+    assert_eq!(response["errors"][0]["message"], "Unknown field unknown on Query");
+
+    let nested = format!("{{ paper(id: \"http://example.com/paper/1\") {}name{} }}",
+                         "{ consultation { paper ".repeat(6),
+                         " } }".repeat(6));
+    let response = execute(&storage, &nested, &JsonValue::Null);
+    assert!(response["data"].is_null());
+    assert_eq!(response["errors"][0]["message"], "The query is nested deeper than 10 levels");
+
+    cleanup(&storage);
+}
+
+/// Assert that deeply nested selections, values and types are rejected while parsing instead of
+/// overflowing the stack
+#[test]
+fn deeply_nested_query() {
+    let storage = storage();
+
+    let queries = vec!["{a".repeat(300000),
+                       format!("{{ paper(id: {}) {{ name }} }}", "[".repeat(300000)),
+                       format!("{{ paper(id: {{a: {}) {{ name }} }}", "{a: ".repeat(300000)),
+                       format!("query q($id: {}) {{ paper {{ name }} }}", "[".repeat(300000))];
+    for query in queries {
+        let response = execute(&storage, &query, &JsonValue::Null);
+        assert!(response["data"].is_null());
+        assert_eq!(response["errors"][0]["message"], "The query is nested deeper than 10 levels");
+    }
+
+    cleanup(&storage);
+}
+
+/// Assert that the types and connections are derived from the OParl schema
+#[test]
+fn schema_definition() {
+    let storage = storage();
+    let schema = render_schema(storage.get_schema());
+
+    assert!(schema.contains("type Query {"));
+    assert!(schema.contains("  paper(id: ID!): Paper\n"));
+    assert!(schema.contains("type PaperConnection {"));
+    assert!(schema.contains("  paper(first: Int, after: String): PaperConnection\n"));
+
+    cleanup(&storage);
+}
@@ -0,0 +1,134 @@
+extern crate oparl_cache;
+extern crate hyper;
+#[macro_use]
+extern crate json;
+extern crate rand;
+extern crate reqwest;
+
+use std::env;
+use std::fs;
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use hyper::server::{Server as HttpServer, Request, Response};
+use reqwest::{IntoUrl, Url};
+
+use oparl_cache::hooks::{Hook, HookAction, HookEvent, run_hooks};
+use oparl_cache::sync_report::{ChangedObject, ListReport, SyncReport};
+
+fn report() -> SyncReport {
+    let mut list = ListReport::default();
+    list.url = "http://example.com/paper".to_string();
+    list.changed = vec![
+        ChangedObject { id: "http://example.com/paper/1".to_string(), oparl_type: "Paper".to_string() },
+        ChangedObject { id: "http://example.com/file/1".to_string(), oparl_type: "File".to_string() },
+    ];
+
+    SyncReport {
+        entrypoint: "http://example.com/".into_url().unwrap(),
+        started: "2017-06-01T00:00:00+00:00".to_string(),
+        backfill: false,
+        duration_ms: 0,
+        lists: vec![list],
+    }
+}
+
+/// Assert that a webhook receives the event and the ids of the changed objects of the watched
+/// types
+#[test]
+fn webhook() {
+    let received = Arc::new(Mutex::new(vec![]));
+    let received_handler = received.clone();
+    let mut listening = HttpServer::http("127.0.0.1:0")
+        .unwrap()
+        .handle(move |mut request: Request, response: Response| {
+            let mut body = String::new();
+            request.read_to_string(&mut body).unwrap();
+            received_handler.lock().unwrap().push(body);
+            response.send(b"").unwrap();
+        })
+        .unwrap();
+
+    let url = Url::parse(&format!("http://{}/notify", listening.socket)).unwrap();
+    let hooks = vec![
+        Hook {
+            event: HookEvent::ObjectsChanged,
+            types: vec!["Paper".to_string()],
+            action: HookAction::Webhook { url: url.clone() },
+        },
+        // There are no changed meetings, so this hook must not be triggered
+        Hook {
+            event: HookEvent::ObjectsChanged,
+            types: vec!["Meeting".to_string()],
+            action: HookAction::Webhook { url: url.clone() },
+        },
+    ];
+
+    let errors = run_hooks(&hooks, &report());
+    listening.close().unwrap();
+    assert_eq!(errors, Vec::<String>::new());
+
+    let received = received.lock().unwrap();
+    assert_eq!(received.len(), 1);
+    let payload = json::parse(&received[0]).unwrap();
+    assert_eq!(payload["event"], "objects_changed");
+    assert_eq!(payload["entrypoint"], "http://example.com/");
+    assert_eq!(payload["ids"], array!["http://example.com/paper/1"]);
+}
+
+/// Assert that a command gets the ids on stdin and that failing commands are reported
+#[test]
+fn command() {
+    let output = env::temp_dir().join(format!("oparl-cache-hook-{}", rand::random::<u32>()));
+    let hooks = vec![
+        Hook {
+            event: HookEvent::SyncFinished,
+            types: vec![],
+            action: HookAction::Command {
+                program: "sh".to_string(),
+                args: vec!["-c".to_string(), format!("cat > {}", output.display())],
+                timeout_secs: None,
+            },
+        },
+        Hook {
+            event: HookEvent::SyncFinished,
+            types: vec![],
+            action: HookAction::Command {
+                program: "false".to_string(),
+                args: vec![],
+                timeout_secs: None,
+            },
+        },
+    ];
+
+    let errors = run_hooks(&hooks, &report());
+    assert_eq!(errors.len(), 1);
+
+    let mut ids = String::new();
+    fs::File::open(&output).unwrap().read_to_string(&mut ids).unwrap();
+    fs::remove_file(&output).unwrap();
+    assert_eq!(ids, "http://example.com/paper/1\nhttp://example.com/file/1\n");
+}
+
+/// Assert that a command is killed after its timeout
+#[test]
+fn command_timeout() {
+    let hook = |script: &str| {
+        Hook {
+            event: HookEvent::SyncFinished,
+            types: vec![],
+            action: HookAction::Command {
+                program: "sh".to_string(),
+                args: vec!["-c".to_string(), script.to_string()],
+                timeout_secs: Some(1),
+            },
+        }
+    };
+
+    let started = Instant::now();
+    let errors = run_hooks(&[hook("exec sleep 10")], &report());
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].contains("killed after 1 seconds"));
+}
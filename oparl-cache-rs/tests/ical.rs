@@ -0,0 +1,118 @@
+extern crate oparl_cache;
+#[macro_use]
+extern crate json;
+extern crate reqwest;
+
+mod common;
+
+use std::sync::mpsc::channel;
+
+use reqwest::IntoUrl;
+
+use oparl_cache::{Cacher, FileStorage, Storage};
+use oparl_cache::ical::{calendar, export_calendars, meeting_event};
+
+use common::*;
+
+fn cache_meeting(storage: &FileStorage) {
+    let (add_list, _receive_list) = channel();
+    let mut input = object!{
+        "id" => "http://example.com/meeting/1",
+        "type" => "https://schema.oparl.org/1.0/Meeting",
+        "name" => "Council, 3rd session",
+        "start" => "2017-03-01T17:00:00+01:00",
+        "end" => "2017-03-01T19:30:00+01:00",
+        "cancelled" => true,
+        "modified" => "2017-02-20T10:00:00+01:00",
+        "organization" => array!["http://example.com/organization/1"],
+        "location" => object!{
+            "id" => "http://example.com/location/1",
+            "type" => "https://schema.oparl.org/1.0/Location",
+            "room" => "Room 101",
+            "streetAddress" => "Main street 1"
+        },
+        "agendaItem" => array![object!{
+            "id" => "http://example.com/agendaitem/1",
+            "type" => "https://schema.oparl.org/1.0/AgendaItem",
+            "number" => "1.",
+            "name" => "Opening of the session by the mayor, determination of the quorum and approval of the minutes"
+        }]
+    };
+    storage.parse_object(&mut input, add_list).unwrap();
+}
+
+/// Assert that the events carry the times, the location, the cancellation and the agenda
+#[test]
+fn organization_calendar() {
+    let storage = storage();
+    cache_meeting(&storage);
+
+    let organization = object!{
+        "id" => "http://example.com/organization/1",
+        "type" => "https://schema.oparl.org/1.0/Organization",
+        "name" => "Council"
+    };
+    let output = calendar(&storage, &organization).unwrap();
+
+    assert!(output.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+    assert!(output.contains("X-WR-CALNAME:Council\r\n"));
+    assert!(output.contains("UID:http://example.com/meeting/1\r\n"));
+    assert!(output.contains("SUMMARY:Council\\, 3rd session\r\n"));
+    assert!(output.contains("DTSTART:20170301T160000Z\r\n"));
+    assert!(output.contains("DTEND:20170301T183000Z\r\n"));
+    assert!(output.contains("STATUS:CANCELLED\r\n"));
+    assert!(output.contains("DTSTAMP:20170220T090000Z\r\n"));
+    assert!(output.contains("LOCATION:Room 101\\, Main street 1\r\n"));
+    // The long agenda item is folded
+    assert!(output.contains("DESCRIPTION:1. Opening of the session by the mayor\\, determination of the q\r\n uorum"));
+    assert!(output.ends_with("END:VEVENT\r\nEND:VCALENDAR\r\n"));
+    assert!(output.lines().all(|x| x.len() <= 75));
+
+    cleanup(&storage);
+}
+
+/// Assert that the body calendar uses the meeting list and unchanged files aren't rewritten
+#[test]
+fn export_body_calendars() {
+    let storage = storage();
+    cache_meeting(&storage);
+
+    let body = object!{
+        "id" => "http://example.com/oparl/body/1",
+        "type" => "https://schema.oparl.org/1.0/Body",
+        "name" => "Example City",
+        "meeting" => "http://example.com/oparl/body/1/meeting"
+    };
+    storage.write_to_cache(&"http://example.com/oparl/body/1".into_url().unwrap(), &body).unwrap();
+    storage.write_to_cache(&"http://example.com/oparl/body/1/meeting".into_url().unwrap(),
+                        &array!["http://example.com/meeting/1"])
+        .unwrap();
+
+    let entrypoint = "http://example.com/oparl".into_url().unwrap();
+    let dir = storage.get_cache_dir().join("calendars");
+    assert_eq!(export_calendars(&storage, &entrypoint, &dir).unwrap(), (1, 1));
+    assert_eq!(export_calendars(&storage, &entrypoint, &dir).unwrap(), (1, 0));
+
+    let output = calendar(&storage, &body).unwrap();
+    assert!(output.contains("UID:http://example.com/meeting/1\r\n"));
+    assert!(dir.join("oparl-body-1.ics").exists());
+
+    cleanup(&storage);
+}
+
+/// Assert that meetings without `modified` and `created` get a stable `DTSTAMP`
+#[test]
+fn stable_stamp() {
+    let storage = storage();
+    let meeting = object!{
+        "id" => "http://example.com/meeting/2",
+        "type" => "https://schema.oparl.org/1.0/Meeting",
+        "start" => "2017-03-01T17:00:00+01:00"
+    };
+
+    let event = meeting_event(&storage, &meeting).unwrap();
+    assert!(event.contains("DTSTAMP:20170301T160000Z\r\n"));
+    assert_eq!(meeting_event(&storage, &meeting).unwrap(), event);
+
+    cleanup(&storage);
+}
@@ -0,0 +1,212 @@
+extern crate oparl_cache;
+#[macro_use]
+extern crate json;
+extern crate reqwest;
+
+mod common;
+
+use json::JsonValue;
+use reqwest::IntoUrl;
+
+use oparl_cache::Storage;
+use oparl_cache::linking::{LinkOptions, LinkReason, LinkTable, link_objects, link_servers, normalize_name,
+                           similarity};
+
+use common::*;
+
+fn objects() -> Vec<JsonValue> {
+    vec![object!{
+             "id" => "http://city.example.com/body/1",
+             "type" => "https://schema.oparl.org/1.0/Body",
+             "name" => "Stadt Beispielstadt",
+             "ags" => "05 315 000"
+         },
+         object!{
+             "id" => "http://ris.example.org/bodies/9",
+             "type" => "https://schema.oparl.org/1.0/Body",
+             "name" => "Beispielstadt",
+             "ags" => "05315000"
+         },
+         object!{
+             "id" => "http://city.example.com/organization/1",
+             "type" => "https://schema.oparl.org/1.0/Organization",
+             "body" => "http://city.example.com/body/1",
+             "name" => "Hauptausschuss",
+             "startDate" => "2014-06-01"
+         },
+         object!{
+             "id" => "http://ris.example.org/organizations/4",
+             "type" => "https://schema.oparl.org/1.0/Organization",
+             "body" => "http://ris.example.org/bodies/9",
+             "name" => "Hauptausschuß",
+             "startDate" => "2014-06-01"
+         },
+         object!{
+             "id" => "http://county.example.com/organization/1",
+             "type" => "https://schema.oparl.org/1.0/Organization",
+             "body" => "http://county.example.com/body/1",
+             "name" => "Hauptausschuss"
+         },
+         object!{
+             "id" => "http://city.example.com/person/1",
+             "type" => "https://schema.oparl.org/1.0/Person",
+             "name" => "Dr. Hans Müller",
+             "gender" => "male",
+             "equivalent" => array!["http://www.wikidata.org/entity/Q1"],
+             "membership" => array![object!{
+                 "id" => "http://city.example.com/membership/1",
+                 "type" => "https://schema.oparl.org/1.0/Membership",
+                 "organization" => "http://city.example.com/organization/1",
+                 "startDate" => "2014-06-01"
+             }]
+         },
+         object!{
+             "id" => "http://county.example.com/person/5",
+             "type" => "https://schema.oparl.org/1.0/Person",
+             "givenName" => "Hans",
+             "familyName" => "Mueller",
+             "gender" => "male",
+             "membership" => array!["http://county.example.com/membership/5"]
+         },
+         object!{
+             "id" => "http://county.example.com/membership/5",
+             "type" => "https://schema.oparl.org/1.0/Membership",
+             "organization" => "http://county.example.com/organization/1",
+             "startDate" => "2016-01-01",
+             "endDate" => "2020-12-31"
+         },
+         object!{
+             "id" => "http://ris.example.org/persons/7",
+             "type" => "https://schema.oparl.org/1.0/Person",
+             "name" => "Hans Müller",
+             "body" => "http://ris.example.org/bodies/9"
+         },
+         object!{
+             "id" => "http://other.example.com/person/8",
+             "type" => "https://schema.oparl.org/1.0/Person",
+             "givenName" => "Hans",
+             "familyName" => "Müller",
+             "gender" => "male",
+             "body" => "http://other.example.com/body/1",
+             "membership" => array![object!{
+                 "id" => "http://other.example.com/membership/8",
+                 "type" => "https://schema.oparl.org/1.0/Membership",
+                 "startDate" => "2001-01-01",
+                 "endDate" => "2005-12-31"
+             }]
+         },
+         object!{
+             "id" => "http://county.example.com/person/6",
+             "type" => "https://schema.oparl.org/1.0/Person",
+             "givenName" => "Hans",
+             "familyName" => "Müller",
+             "gender" => "female"
+         }]
+}
+
+/// Assert that names are normalized and compared
+#[test]
+fn names() {
+    assert_eq!(normalize_name("Dr. Müller, Hans"), "hans mueller");
+    assert_eq!(normalize_name("Hans  Mueller"), "hans mueller");
+    assert_eq!(similarity("hauptausschuss", "hauptausschuss"), 1.0);
+    assert!(similarity("hauptausschuss", "hauptauschuss") > 0.9);
+    assert!(similarity("hauptausschuss", "finanzausschuss") < 0.9);
+}
+
+/// Assert that bodies are linked by the AGS, organizations only within the same body and
+/// persons through `equivalent` or by name if they share a body or a time as members
+#[test]
+fn clusters() {
+    let table = link_objects(&objects(), &LinkOptions::default());
+
+    let body = table.cluster("http://city.example.com/body/1").unwrap();
+    assert_eq!(body.oparl_type, "Body");
+    assert_eq!(body.members,
+               vec!["http://city.example.com/body/1".to_string(), "http://ris.example.org/bodies/9".to_string()]);
+    assert_eq!(body.links[0].reason, LinkReason::Ags);
+
+    // The committee of the county has the same name, but belongs to another body
+    assert_eq!(table.linked("http://city.example.com/organization/1"),
+               vec!["http://ris.example.org/organizations/4".to_string()]);
+    assert!(table.cluster("http://county.example.com/organization/1").is_none());
+
+    // The county member overlaps in time, the person of the other server shares the linked body,
+    // while the person with the other gender and the namesake of another body and time aren't linked
+    let person = table.cluster("http://county.example.com/person/5").unwrap();
+    assert_eq!(person.members,
+               vec!["http://city.example.com/person/1".to_string(),
+                    "http://county.example.com/person/5".to_string(),
+                    "http://ris.example.org/persons/7".to_string(),
+                    "http://www.wikidata.org/entity/Q1".to_string()]);
+    assert!(person.links.iter().any(|x| x.reason == LinkReason::Name && x.similarity == Some(1.0)));
+    assert!(table.cluster("http://county.example.com/person/6").is_none());
+    assert!(table.cluster("http://other.example.com/person/8").is_none());
+
+    assert_eq!(table.clusters.len(), 3);
+
+    // Without linking by name only the `equivalent` links and the AGS are left
+    let options = LinkOptions { types: vec!["Body".to_string()], ..LinkOptions::default() };
+    let table = link_objects(&objects(), &options);
+    assert_eq!(table.clusters.len(), 2);
+    assert_eq!(table.linked("http://city.example.com/person/1"),
+               vec!["http://www.wikidata.org/entity/Q1".to_string()]);
+}
+
+/// Assert that the link table is stored in the cache and can be queried
+#[test]
+fn link_table() {
+    let storage = storage();
+    for object in objects() {
+        let url = object["id"].as_str().unwrap().into_url().unwrap();
+        storage.write_to_cache(&url, &object).unwrap();
+    }
+    assert!(LinkTable::load(&storage).unwrap().clusters.is_empty());
+
+    let servers = vec!["http://city.example.com/".into_url().unwrap(),
+                       "http://county.example.com/".into_url().unwrap(),
+                       "http://ris.example.org/".into_url().unwrap()];
+    let table = link_servers(&storage, &servers, &LinkOptions::default()).unwrap();
+    table.save(&storage).unwrap();
+
+    let loaded = LinkTable::load(&storage).unwrap();
+    assert_eq!(loaded, table);
+    assert_eq!(loaded.linked("http://ris.example.org/bodies/9"),
+               vec!["http://city.example.com/body/1".to_string()]);
+    assert!(loaded.clusters.iter().all(|x| x.servers.len() == 3));
+
+    cleanup(&storage);
+}
+
+/// Assert that saving a table only replaces the clusters of the same servers
+#[test]
+fn merged_link_table() {
+    let storage = storage();
+    for object in objects() {
+        let url = object["id"].as_str().unwrap().into_url().unwrap();
+        storage.write_to_cache(&url, &object).unwrap();
+    }
+
+    let city = "http://city.example.com/".into_url().unwrap();
+    let county = "http://county.example.com/".into_url().unwrap();
+    let ris = "http://ris.example.org/".into_url().unwrap();
+    let options = LinkOptions::default();
+    link_servers(&storage, &[city.clone(), ris.clone()], &options).unwrap().save(&storage).unwrap();
+    link_servers(&storage, &[county.clone(), city.clone()], &options).unwrap().save(&storage).unwrap();
+
+    let loaded = LinkTable::load(&storage).unwrap();
+    assert!(loaded.clusters.iter().any(|x| x.members.contains(&"http://ris.example.org/bodies/9".to_string())));
+    assert!(loaded.clusters.iter().any(|x| x.members.contains(&"http://county.example.com/person/5".to_string())));
+
+    // Linking the city and the other server again replaces their clusters
+    let options = LinkOptions { types: vec!["Body".to_string()], ..LinkOptions::default() };
+    link_servers(&storage, &[ris, city], &options).unwrap().save(&storage).unwrap();
+    let loaded = LinkTable::load(&storage).unwrap();
+    assert!(!loaded.clusters.iter().any(|x| x.members.contains(&"http://ris.example.org/persons/7".to_string())));
+    assert!(loaded.clusters.iter().any(|x| x.members.contains(&"http://county.example.com/person/5".to_string())));
+    assert!(!storage.get_cache_dir().read_dir().unwrap().any(|x| {
+        x.unwrap().file_name().to_string_lossy().ends_with(".tmp")
+    }));
+
+    cleanup(&storage);
+}
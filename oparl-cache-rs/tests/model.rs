@@ -0,0 +1,137 @@
+extern crate oparl_cache;
+#[macro_use]
+extern crate json;
+extern crate reqwest;
+
+mod common;
+
+use json::JsonValue;
+use reqwest::IntoUrl;
+
+use oparl_cache::{FileStorage, Storage};
+use oparl_cache::model::{AnyObject, Body, File, Paper, to_json};
+
+use common::*;
+
+fn write(storage: &FileStorage, object: &JsonValue) {
+    let url = object["id"].as_str().unwrap().into_url().unwrap();
+    storage.write_to_cache(&url, object).unwrap();
+}
+
+fn paper() -> JsonValue {
+    object!{
+        "id" => "http://example.com/oparl/paper/1",
+        "type" => "https://schema.oparl.org/1.0/Paper",
+        "body" => "http://example.com/oparl/body/1",
+        "name" => "Budget 2018",
+        "reference" => "2017/001",
+        "date" => "2017-04-28",
+        "keyword" => array!["budget"],
+        "mainFile" => "http://example.com/oparl/file/1",
+        "consultation" => array!["http://example.com/oparl/consultation/1"],
+        "modified" => "2017-05-01T12:00:00+02:00",
+        "vendor:priority" => object!{ "level" => 2 }
+    }
+}
+
+/// Assert that the typed objects have the properties, the references and the unknown fields
+#[test]
+fn get_typed() {
+    let storage = storage();
+    write(&storage, &paper());
+    write(&storage,
+          &object!{
+              "id" => "http://example.com/oparl/file/1",
+              "type" => "https://schema.oparl.org/1.0/File",
+              "accessUrl" => "http://example.com/files/1.pdf",
+              "size" => 1024
+          });
+    write(&storage,
+          &object!{
+              "id" => "http://example.com/oparl/body/1",
+              "type" => "https://schema.oparl.org/1.0/Body",
+              "name" => "Example City",
+              "paper" => "http://example.com/oparl/body/1/paper"
+          });
+    storage.write_to_cache(&"http://example.com/oparl/body/1/paper".into_url().unwrap(),
+                        &array!["http://example.com/oparl/paper/1"])
+        .unwrap();
+
+    let url = "http://example.com/oparl/paper/1".into_url().unwrap();
+    let paper = storage.get_typed::<Paper>(&url).unwrap();
+    assert_eq!(paper.reference, Some("2017/001".to_string()));
+    assert_eq!(paper.date.unwrap().to_string(), "2017-04-28");
+    assert_eq!(paper.modified.unwrap().to_rfc3339(), "2017-05-01T12:00:00+02:00");
+    assert_eq!(paper.keyword, vec!["budget".to_string()]);
+    assert_eq!(paper.consultation[0].id(), "http://example.com/oparl/consultation/1");
+    assert_eq!(paper.extra["vendor:priority"]["level"], 2);
+
+    let file: File = paper.main_file.as_ref().unwrap().resolve(&storage).unwrap();
+    assert_eq!(file.size, Some(1024));
+
+    let body: Body = paper.body.as_ref().unwrap().resolve(&storage).unwrap();
+    let papers = body.paper.as_ref().unwrap().resolve(&storage).unwrap();
+    assert_eq!(papers[0].resolve(&storage).unwrap(), paper);
+
+    // A paper isn't a body
+    assert!(storage.get_typed::<Body>(&url).is_err());
+
+    cleanup(&storage);
+}
+
+/// Assert that converting an object and back keeps all properties
+#[test]
+fn round_trip() {
+    let object = paper();
+    match AnyObject::from_json(&object).unwrap() {
+        AnyObject::Paper(paper) => assert_eq!(to_json(&paper).unwrap(), object),
+        other => panic!("Expected a paper, got {:?}", other),
+    }
+
+    // Empty lists and nulls are left out and timestamps are written with an offset
+    let mut object = paper();
+    object["modified"] = "2017-05-01T10:00:00Z".into();
+    object["auxiliaryFile"] = array![];
+    object["web"] = JsonValue::Null;
+    let paper = match AnyObject::from_json(&object).unwrap() {
+        AnyObject::Paper(paper) => to_json(&paper).unwrap(),
+        other => panic!("Expected a paper, got {:?}", other),
+    };
+    assert_eq!(paper["modified"], "2017-05-01T10:00:00+00:00");
+    assert!(paper["auxiliaryFile"].is_null());
+    assert!(paper["web"].is_null());
+
+    // Embedded objects are turned into references
+    let meeting = object!{
+        "id" => "http://example.com/oparl/meeting/1",
+        "type" => "https://schema.oparl.org/1.0/Meeting",
+        "location" => object!{
+            "id" => "http://example.com/oparl/location/1",
+            "type" => "https://schema.oparl.org/1.0/Location"
+        }
+    };
+    let meeting = AnyObject::from_json(&meeting).unwrap();
+    assert_eq!(meeting.id(), "http://example.com/oparl/meeting/1");
+    match meeting {
+        AnyObject::Meeting(meeting) => {
+            assert_eq!(meeting.location.unwrap().id(), "http://example.com/oparl/location/1")
+        }
+        other => panic!("Expected a meeting, got {:?}", other),
+    }
+}
+
+/// Assert that a malformed date doesn't fail the whole object
+#[test]
+fn malformed_dates() {
+    let mut object = paper();
+    object["modified"] = "2017-05-01".into();
+    object["date"] = "28.04.2017".into();
+    match AnyObject::from_json(&object).unwrap() {
+        AnyObject::Paper(paper) => {
+            assert_eq!(paper.modified, None);
+            assert_eq!(paper.date, None);
+            assert_eq!(paper.reference, Some("2017/001".to_string()));
+        }
+        other => panic!("Expected a paper, got {:?}", other),
+    }
+}
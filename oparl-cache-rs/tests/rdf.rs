@@ -0,0 +1,98 @@
+extern crate oparl_cache;
+#[macro_use]
+extern crate json;
+extern crate reqwest;
+
+mod common;
+
+use reqwest::IntoUrl;
+
+use oparl_cache::Storage;
+use oparl_cache::rdf::{RdfFormat, RdfMapping, Term, Triple, export_rdf, object_triples};
+
+use common::*;
+
+/// Assert that references become iris, dates typed literals and external lists are left out
+#[test]
+fn default_mapping() {
+    let storage = storage();
+
+    let paper = object!{
+        "id" => "http://example.com/paper/1",
+        "type" => "https://schema.oparl.org/1.0/Paper",
+        "name" => "Budget \"2017\"",
+        "date" => "2017-01-01",
+        "body" => "http://example.com/body/1",
+        "keyword" => array!["budget", "finance"]
+    };
+    let triples = object_triples(&paper, storage.get_schema(), &RdfMapping::default()).unwrap();
+    let triple = |predicate: &str, object: Term| {
+        Triple {
+            subject: "http://example.com/paper/1".to_string(),
+            predicate: predicate.to_string(),
+            object: object,
+        }
+    };
+
+    assert!(triples.contains(&triple("http://www.w3.org/1999/02/22-rdf-syntax-ns#type",
+                                     Term::Iri("https://schema.oparl.org/1.0/Paper".to_string()))));
+    assert!(triples.contains(&triple("https://schema.oparl.org/1.0/body",
+                                     Term::Iri("http://example.com/body/1".to_string()))));
+    assert!(triples.contains(&triple("https://schema.oparl.org/1.0/date",
+                                     Term::Literal("2017-01-01".to_string(),
+                                                   Some("http://www.w3.org/2001/XMLSchema#date".to_string())))));
+    assert!(triples.contains(&triple("https://schema.oparl.org/1.0/keyword",
+                                     Term::Literal("finance".to_string(), None))));
+    assert_eq!(triples.len(), 6);
+
+    let body = object!{
+        "id" => "http://example.com/body/1",
+        "type" => "https://schema.oparl.org/1.0/Body",
+        "paper" => "http://example.com/body/1/paper"
+    };
+    assert_eq!(object_triples(&body, storage.get_schema(), &RdfMapping::default()).unwrap().len(), 1);
+
+    cleanup(&storage);
+}
+
+/// Assert that the whole cache is exported with a custom mapping, skipping lists and reports
+#[test]
+fn export() {
+    let storage = storage();
+
+    let person = object!{
+        "id" => "http://example.com/person/1",
+        "type" => "https://schema.oparl.org/1.0/Person",
+        "name" => "Jane Doe",
+        "email" => array!["jane@example.com"]
+    };
+    storage.write_to_cache(&"http://example.com/person/1".into_url().unwrap(), &person).unwrap();
+    storage.write_to_cache(&"http://example.com/body/1/person".into_url().unwrap(),
+                        &array!["http://example.com/person/1"])
+        .unwrap();
+
+    let mut mapping = RdfMapping::default();
+    mapping.prefixes.insert("foaf".to_string(), "http://xmlns.com/foaf/0.1/".to_string());
+    mapping.classes.insert("Person".to_string(), "foaf:Person".to_string());
+    mapping.predicates.insert("name".to_string(), "http://purl.org/dc/terms/title".to_string());
+    mapping.predicates.insert("Person.name".to_string(), "foaf:name".to_string());
+    mapping.skip.push("email".to_string());
+
+    let entrypoint = "http://example.com/oparl".into_url().unwrap();
+
+    let mut output = vec![];
+    let count = export_rdf(&storage, &entrypoint, &mapping, RdfFormat::NTriples, &mut output).unwrap();
+    assert_eq!(count, 1);
+    assert_eq!(String::from_utf8(output).unwrap(),
+               "<http://example.com/person/1> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> \
+                <http://xmlns.com/foaf/0.1/Person> .\n\
+                <http://example.com/person/1> <http://xmlns.com/foaf/0.1/name> \"Jane Doe\" .\n");
+
+    let mut output = vec![];
+    export_rdf(&storage, &entrypoint, &mapping, RdfFormat::Turtle, &mut output).unwrap();
+    let turtle = String::from_utf8(output).unwrap();
+    assert!(turtle.starts_with("@prefix foaf: <http://xmlns.com/foaf/0.1/> .\n"));
+    assert!(turtle.contains("<http://example.com/person/1>\n    a foaf:Person ;\n    foaf:name \"Jane Doe\" .\n"));
+
+    cleanup(&storage);
+}
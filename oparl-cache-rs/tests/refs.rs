@@ -0,0 +1,96 @@
+extern crate oparl_cache;
+#[macro_use]
+extern crate json;
+extern crate reqwest;
+
+mod common;
+
+use std::sync::Arc;
+use std::sync::mpsc::channel;
+use std::thread;
+
+use reqwest::IntoUrl;
+
+use oparl_cache::{Cacher, Reference, ReferenceIndex, Storage};
+
+use common::*;
+
+/// Assert that references and embedded objects are indexed and that removed references leave
+/// the index again
+#[test]
+fn reference_index() {
+    let storage = storage();
+    let (add_list, _receive_list) = channel();
+
+    let paper = "http://example.com/paper/1".into_url().unwrap();
+    let body = "http://example.com/body/1".into_url().unwrap();
+
+    let mut input = object!{
+        "id" => "http://example.com/paper/1",
+        "type" => "https://schema.oparl.org/1.0/Paper",
+        "body" => "http://example.com/body/1",
+        "consultation" => array![object!{
+            "id" => "http://example.com/consultation/1",
+            "type" => "https://schema.oparl.org/1.0/Consultation",
+            "paper" => "http://example.com/paper/1"
+        }]
+    };
+    storage.parse_object(&mut input, add_list.clone()).unwrap();
+
+    assert_eq!(storage.get_references(&body).unwrap(),
+               vec![Reference {
+                        source: paper.to_string(),
+                        source_type: "Paper".to_string(),
+                        property: "body".to_string(),
+                    }]);
+    assert_eq!(storage.get_referencing(&paper, "Consultation").unwrap(),
+               vec!["http://example.com/consultation/1"]);
+    assert_eq!(storage.get_referencing(&"http://example.com/consultation/1".into_url().unwrap(), "Paper")
+                   .unwrap(),
+               vec!["http://example.com/paper/1"]);
+
+    // The paper doesn't belong to the body anymore
+    let mut input = object!{
+        "id" => "http://example.com/paper/1",
+        "type" => "https://schema.oparl.org/1.0/Paper"
+    };
+    storage.parse_object(&mut input, add_list).unwrap();
+
+    assert_eq!(storage.get_references(&body).unwrap(), vec![]);
+
+    cleanup(&storage);
+}
+
+/// Assert that the index matches the cached object when workers write the same object with
+/// different references at the same time
+#[test]
+fn concurrent_updates() {
+    let storage = Arc::new(storage());
+    let paper = "http://example.com/paper/1".into_url().unwrap();
+
+    let workers = (0..8)
+        .map(|i| {
+            let storage = storage.clone();
+            thread::spawn(move || for _ in 0..10 {
+                let mut input = object!{
+                    "id" => "http://example.com/paper/1",
+                    "type" => "https://schema.oparl.org/1.0/Paper",
+                    "body" => format!("http://example.com/body/{}", i)
+                };
+                storage.parse_object(&mut input, channel().0).unwrap();
+            })
+        })
+        .collect::<Vec<_>>();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    let cached = storage.get(&paper).unwrap();
+    for i in 0..8 {
+        let body = format!("http://example.com/body/{}", i).into_url().unwrap();
+        let referenced = !storage.get_references(&body).unwrap().is_empty();
+        assert_eq!(referenced, cached["body"] == body.as_str());
+    }
+
+    cleanup(&storage);
+}
@@ -0,0 +1,76 @@
+extern crate oparl_cache;
+#[macro_use]
+extern crate json;
+extern crate reqwest;
+
+mod common;
+
+use std::time::Duration;
+
+use reqwest::IntoUrl;
+
+use oparl_cache::{Cacher, Server, Storage};
+use oparl_cache::replay::{Fault, RecordingServer, ReplayServer};
+
+use common::*;
+
+/// Assert that the recorded exchanges are served again, including the failed ones
+#[test]
+fn record_and_replay() {
+    let entrypoint = "http://example.com/".into_url().unwrap();
+    let paper = "http://example.com/paper/1".into_url().unwrap();
+    let server = mocking_server(entrypoint.clone()).with_response(paper.clone(), object!{
+        "id" => paper.as_str(),
+        "type" => "https://schema.oparl.org/1.0/Paper",
+        "name" => "Ü"
+    });
+
+    let storage = storage();
+    let fixtures = storage.get_cache_dir().join("fixtures");
+
+    let recording = RecordingServer::new(server, fixtures.clone()).unwrap();
+    storage.cache(recording).unwrap();
+    let recording = RecordingServer::new(mocking_server(entrypoint.clone()), fixtures.clone()).unwrap();
+    recording.get_json(paper.clone()).unwrap_err();
+
+    let replay = ReplayServer::new(fixtures).unwrap();
+    assert_eq!(replay.get_entrypoint(), entrypoint);
+    assert_eq!(replay.get_json(entrypoint.clone()).unwrap(), storage.get(&entrypoint).unwrap());
+    // The second recording has overwritten the successful response
+    assert!(replay.get_json(paper).is_err());
+    assert!(replay.get_json("http://example.com/unknown".into_url().unwrap()).is_err());
+
+    cleanup(&storage);
+}
+
+/// Assert that injected faults replace the recorded responses
+#[test]
+fn replay_faults() {
+    let entrypoint = "http://example.com/".into_url().unwrap();
+    let storage = storage();
+    let fixtures = storage.get_cache_dir().join("fixtures");
+
+    let recording = RecordingServer::new(mocking_server(entrypoint.clone()), fixtures.clone()).unwrap();
+    recording.get_json(entrypoint.clone()).unwrap();
+
+    let mut replay = ReplayServer::new(fixtures).unwrap().with_fault(&entrypoint, Fault::Status(503));
+    let err = replay.get_json(entrypoint.clone()).unwrap_err();
+    assert!(err.to_string().contains("503"));
+
+    replay.add_fault(&entrypoint, Fault::MalformedJson);
+    assert!(replay.get_json(entrypoint.clone()).is_err());
+
+    replay.add_fault(&entrypoint, Fault::Timeout(Duration::from_millis(10)));
+    assert!(replay.get_json(entrypoint.clone()).is_err());
+
+    replay.clear_faults();
+    assert!(replay.get_json(entrypoint.clone()).is_ok());
+
+    // Syncing against a failing server records the error instead of panicking
+    replay.add_fault(&entrypoint, Fault::Status(500));
+    storage.cache(replay).unwrap();
+    let report = storage.get_sync_reports(&entrypoint).unwrap().pop().unwrap();
+    assert_eq!(report.failed_lists(), 1);
+
+    cleanup(&storage);
+}
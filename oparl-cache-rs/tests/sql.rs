@@ -0,0 +1,193 @@
+extern crate oparl_cache;
+#[macro_use]
+extern crate json;
+extern crate reqwest;
+
+mod common;
+
+use std::collections::HashMap;
+use std::error::Error;
+
+use reqwest::IntoUrl;
+
+use oparl_cache::{FileStorage, Storage};
+use oparl_cache::sql::{ColumnKind, Dialect, ImportReport, SqlBackend, SqlValue, import_sql, relational_schema};
+
+use common::*;
+
+/// Records the statements and answers the queries for `modified` with fixed rows
+#[derive(Default)]
+struct RecordingBackend {
+    statements: Vec<(String, Vec<SqlValue>)>,
+    modified: HashMap<String, Vec<(String, Option<String>)>>,
+}
+
+impl SqlBackend for RecordingBackend {
+    fn dialect(&self) -> Dialect {
+        Dialect::Postgres
+    }
+
+    fn batch(&mut self, sql: &str) -> Result<(), Box<Error>> {
+        self.statements.push((sql.to_string(), vec![]));
+        Ok(())
+    }
+
+    fn execute(&mut self, sql: &str, params: &[SqlValue]) -> Result<(), Box<Error>> {
+        self.statements.push((sql.to_string(), params.to_vec()));
+        Ok(())
+    }
+
+    fn query_pairs(&mut self, sql: &str) -> Result<Vec<(String, Option<String>)>, Box<Error>> {
+        Ok(self.modified.get(sql).cloned().unwrap_or_default())
+    }
+}
+
+fn cache_paper(storage: &FileStorage, modified: &str) {
+    let paper = object!{
+        "id" => "http://example.com/paper/1",
+        "type" => "https://schema.oparl.org/1.0/Paper",
+        "name" => "Budget",
+        "body" => "http://example.com/body/1",
+        "keyword" => array!["budget", "finance"],
+        "modified" => modified
+    };
+    storage.write_to_cache(&"http://example.com/paper/1".into_url().unwrap(), &paper).unwrap();
+}
+
+/// Returns a statement that drops all tables of the schema including the join tables
+#[cfg(feature = "postgresql")]
+fn drop_statement(storage: &FileStorage) -> String {
+    let mut names = vec![];
+    for table in relational_schema(storage.get_schema()) {
+        names.push(format!("\"{}\"", table.name));
+        names.extend(table.join_tables.iter().map(|x| format!("\"{}\"", x.name)));
+    }
+    format!("DROP TABLE IF EXISTS {}", names.join(", "))
+}
+
+/// Assert that scalars become columns and arrays and references join tables
+#[test]
+fn schema_tables() {
+    let storage = storage();
+    let tables = relational_schema(storage.get_schema());
+
+    let paper = tables.iter().find(|x| x.oparl_type == "Paper").unwrap();
+    assert_eq!(paper.name, "paper");
+    assert!(paper.columns.iter().any(|x| x.name == "name" && x.kind == ColumnKind::Text));
+    assert!(paper.columns.iter().any(|x| x.name == "reference"));
+    assert!(paper.join_tables.iter().any(|x| x.name == "paper_keyword"));
+    assert!(paper.join_tables.iter().any(|x| x.name == "paper_body"));
+    assert!(paper.join_tables.iter().any(|x| x.name == "paper_consultation"));
+    assert!(paper.create_statements()[1].contains("\"position\" BIGINT NOT NULL"));
+
+    let body = tables.iter().find(|x| x.oparl_type == "Body").unwrap();
+    assert!(!body.join_tables.iter().any(|x| x.property == "paper"));
+
+    let term = tables.iter().find(|x| x.oparl_type == "LegislativeTerm").unwrap();
+    assert_eq!(term.name, "legislative_term");
+    assert!(term.columns.iter().any(|x| x.name == "start_date"));
+    let create = &term.create_statements()[0];
+    assert!(create.starts_with("CREATE TABLE IF NOT EXISTS \"legislative_term\" (\"id\" TEXT PRIMARY KEY, "));
+    assert!(create.contains("\"deleted\" BOOLEAN"));
+
+    cleanup(&storage);
+}
+
+/// Assert that objects are upserted with their join rows and skipped while `modified` is unchanged
+#[test]
+fn incremental_import() {
+    let storage = storage();
+    let entrypoint = "http://example.com/oparl".into_url().unwrap();
+    cache_paper(&storage, "2017-01-01T00:00:00+01:00");
+
+    let mut backend = RecordingBackend::default();
+    let report = import_sql(&storage, &entrypoint, &mut backend).unwrap();
+    assert_eq!(report, ImportReport { upserted: 1, unchanged: 0, skipped: 0 });
+
+    let keywords = backend.statements
+        .iter()
+        .filter(|x| x.0.starts_with("INSERT INTO \"paper_keyword\""))
+        .map(|x| x.1[2].clone())
+        .collect::<Vec<_>>();
+    assert_eq!(keywords,
+               vec![SqlValue::Text(Some("budget".to_string())), SqlValue::Text(Some("finance".to_string()))]);
+    assert!(backend.statements
+        .iter()
+        .any(|x| x.0.starts_with("INSERT INTO \"paper\"") && x.0.contains("ON CONFLICT (\"id\") DO UPDATE")));
+
+    let mut backend = RecordingBackend::default();
+    backend.modified.insert("SELECT \"id\", \"modified\" FROM \"paper\"".to_string(),
+                            vec![("http://example.com/paper/1".to_string(),
+                                  Some("2017-01-01T00:00:00+01:00".to_string()))]);
+    let report = import_sql(&storage, &entrypoint, &mut backend).unwrap();
+    assert_eq!(report, ImportReport { upserted: 0, unchanged: 1, skipped: 0 });
+
+    cache_paper(&storage, "2017-02-01T00:00:00+01:00");
+    let report = import_sql(&storage, &entrypoint, &mut backend).unwrap();
+    assert_eq!(report, ImportReport { upserted: 1, unchanged: 0, skipped: 0 });
+
+    cleanup(&storage);
+}
+
+/// Assert that the import works with a real database and that a second run updates the rows
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_import() {
+    use oparl_cache::sql::SqliteBackend;
+
+    let storage = storage();
+    let entrypoint = "http://example.com/oparl".into_url().unwrap();
+    let mut backend = SqliteBackend::open_in_memory().unwrap();
+
+    cache_paper(&storage, "2017-01-01T00:00:00+01:00");
+    import_sql(&storage, &entrypoint, &mut backend).unwrap();
+    cache_paper(&storage, "2017-02-01T00:00:00+01:00");
+    import_sql(&storage, &entrypoint, &mut backend).unwrap();
+
+    let modified: String = backend.connection()
+        .query_row("SELECT \"modified\" FROM \"paper\"", &[], |row| row.get(0))
+        .unwrap();
+    assert_eq!(modified, "2017-02-01T00:00:00+01:00");
+    let keywords: i64 = backend.connection()
+        .query_row("SELECT COUNT(*) FROM \"paper_keyword\"", &[], |row| row.get(0))
+        .unwrap();
+    assert_eq!(keywords, 2);
+
+    cleanup(&storage);
+}
+
+/// Runs against the database in `OPARL_CACHE_TEST_POSTGRES`, e.g.
+/// `postgres://postgres@localhost/oparl_test`, and is skipped without it
+#[cfg(feature = "postgresql")]
+#[test]
+fn postgres_import() {
+    use oparl_cache::sql::PostgresBackend;
+
+    let url = match std::env::var("OPARL_CACHE_TEST_POSTGRES") {
+        Ok(url) => url,
+        Err(_) => return,
+    };
+
+    let storage = storage();
+    let entrypoint = "http://example.com/oparl".into_url().unwrap();
+    let mut backend = PostgresBackend::connect(&url).unwrap();
+    let drop = drop_statement(&storage);
+    backend.batch(&drop).unwrap();
+
+    cache_paper(&storage, "2017-01-01T00:00:00+01:00");
+    import_sql(&storage, &entrypoint, &mut backend).unwrap();
+    cache_paper(&storage, "2017-02-01T00:00:00+01:00");
+    let report = import_sql(&storage, &entrypoint, &mut backend).unwrap();
+    assert_eq!(report.upserted, 1);
+
+    let rows = backend.connection().query("SELECT \"modified\" FROM \"paper\"", &[]).unwrap();
+    assert_eq!(rows.get(0).get::<_, String>(0), "2017-02-01T00:00:00+01:00");
+    let rows = backend.connection()
+        .query("SELECT \"position\", \"value\" FROM \"paper_keyword\" ORDER BY \"position\"", &[])
+        .unwrap();
+    assert_eq!(rows.get(1).get::<_, i64>(0), 1);
+    assert_eq!(rows.get(1).get::<_, String>(1), "finance");
+
+    backend.batch(&drop).unwrap();
+    cleanup(&storage);
+}
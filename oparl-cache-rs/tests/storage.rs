@@ -7,9 +7,48 @@ mod common;
 
 use common::*;
 
-use oparl_cache::Cacher;
+use std::error::Error;
 
-use reqwest::IntoUrl;
+use json::JsonValue;
+use oparl_cache::{Cacher, FileStorage, ObjectFilter, Storage};
+
+use reqwest::{IntoUrl, Url};
+
+/// Caches a server whose body list contains the given bodies
+fn cache_bodies(storage: &FileStorage, entrypoint: &str, bodies: &[&str]) {
+    let url = entrypoint.into_url().unwrap();
+    let list_url = format!("{}/bodies", entrypoint);
+    let data = bodies.iter()
+        .map(|x| object!{ "id" => *x, "type" => "https://schema.oparl.org/1.0/Body" })
+        .collect::<Vec<_>>();
+    let server = mocking_server(url.clone())
+        .with_response(url.clone(), object!{
+            "id" => url.as_str(),
+            "type" => "https://schema.oparl.org/1.0/System",
+            "body" => list_url.as_str()
+        })
+        .with_response(list_url.as_str(), object!{ "data" => data, "links" => object!{} });
+    storage.cache(server).unwrap();
+}
+
+/// A storage that only implements the required methods
+struct MinimalStorage {
+    schema: JsonValue,
+}
+
+impl Storage for MinimalStorage {
+    fn write_to_cache(&self, _url: &Url, _object: &JsonValue) -> Result<(), Box<Error>> {
+        Ok(())
+    }
+
+    fn get(&self, url: &Url) -> Result<JsonValue, Box<Error>> {
+        Err(From::from(format!("{} isn't cached", url)))
+    }
+
+    fn get_schema(&self) -> &JsonValue {
+        &self.schema
+    }
+}
 
 /// Assert that the cached server status method returns the correct list
 #[test]
@@ -31,3 +70,180 @@ fn test_cached_server_list() {
 
     cleanup(&storage);
 }
+
+/// Assert that the cache status records the filter the cache was created with
+#[test]
+fn test_cache_status_records_filter() {
+    let url = "http://example.com/".into_url().unwrap();
+    let filter = ObjectFilter {
+        include_lists: vec!["paper".to_string()],
+        ..ObjectFilter::default()
+    };
+    let mut storage = storage();
+    storage.set_object_filter(filter.clone());
+
+    assert!(storage.get_cache_status(&url).unwrap().is_none());
+
+    storage.cache(mocking_server(url.clone())).unwrap();
+    let status = storage.get_cache_status(&url).unwrap().unwrap();
+
+    cleanup(&storage);
+
+    assert_eq!(status.filter, filter);
+}
+
+/// Assert that every sync run leaves a report with the counters of the lists
+#[test]
+fn test_sync_report() {
+    let url = "http://example.com/".into_url().unwrap();
+    let list_url = "http://example.com/bodies";
+    let server = mocking_server(url.clone())
+        .with_response(url.clone(), object!{
+            "id" => url.as_str(),
+            "type" => "https://schema.oparl.org/1.0/System",
+            "body" => list_url
+        })
+        .with_response(list_url, object!{
+            "data" => array![
+                object!{
+                    "id" => "http://example.com/body/0",
+                    "type" => "https://schema.oparl.org/1.0/Body"
+                },
+                object!{
+                    "id" => 0,
+                    "type" => "https://schema.oparl.org/1.0/Body"
+                }
+            ],
+            "links" => object!{}
+        });
+    let storage = storage();
+
+    assert!(storage.get_sync_reports(&url).unwrap().is_empty());
+
+    storage.cache(server).unwrap();
+    let reports = storage.get_sync_reports(&url).unwrap();
+
+    cleanup(&storage);
+
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].lists.len(), 1);
+
+    let list = &reports[0].lists[0];
+    assert_eq!(list.url, list_url);
+    assert!(!list.failed);
+    assert_eq!(list.pages, 1);
+    assert_eq!(list.objects, 2);
+    assert_eq!(list.invalid_objects, 1);
+    assert_eq!(list.errors.len(), 1);
+}
+
+/// Assert that a list whose page couldn't be fetched is recorded once with the url of the page
+#[test]
+fn test_sync_report_failed_list() {
+    let url = "http://example.com/".into_url().unwrap();
+    let server = mocking_server(url.clone())
+        .with_response(url.clone(), object!{
+            "id" => url.as_str(),
+            "type" => "https://schema.oparl.org/1.0/System",
+            "body" => "http://example.com/bodies"
+        });
+    let storage = storage();
+    storage.cache(server).unwrap();
+    let reports = storage.get_sync_reports(&url).unwrap();
+
+    cleanup(&storage);
+
+    let list = &reports[0].lists[0];
+    assert!(list.failed);
+    assert_eq!(list.pages, 0);
+    assert_eq!(list.errors.len(), 1);
+    assert!(list.errors[0].contains("http://example.com/bodies"));
+}
+
+/// Assert that the cached objects and lists can be enumerated
+#[test]
+fn test_iter_objects_and_lists() {
+    let url = "http://example.com/".into_url().unwrap();
+    let list_url = "http://example.com/bodies";
+    let server = mocking_server(url.clone())
+        .with_response(url.clone(), object!{
+            "id" => url.as_str(),
+            "type" => "https://schema.oparl.org/1.0/System",
+            "body" => list_url
+        })
+        .with_response(list_url, object!{
+            "data" => array![
+                object!{
+                    "id" => "http://example.com/body/1",
+                    "type" => "https://schema.oparl.org/1.0/Body"
+                },
+                object!{
+                    "id" => "http://example.com/body/2",
+                    "type" => "https://schema.oparl.org/1.0/Body"
+                }
+            ],
+            "links" => object!{}
+        });
+    let storage = storage();
+    storage.cache(server).unwrap();
+
+    let ids = storage.iter_objects(&url, None)
+        .map(|x| x.unwrap()["id"].to_string())
+        .collect::<Vec<_>>();
+    let bodies = storage.iter_objects(&url, Some("Body"))
+        .map(|x| x.unwrap()["id"].to_string())
+        .collect::<Vec<_>>();
+    let systems_and_bodies = storage.iter_objects_of(&url, &["System", "Body"])
+        .map(|x| x.unwrap()["id"].to_string())
+        .collect::<Vec<_>>();
+    let lists = storage.iter_lists(&url).map(|x| x.unwrap()).collect::<Vec<_>>();
+    let unknown = storage.iter_objects(&"http://example.org/".into_url().unwrap(), None).count();
+
+    cleanup(&storage);
+
+    assert_eq!(ids, vec!["http://example.com/", "http://example.com/body/1", "http://example.com/body/2"]);
+    assert_eq!(bodies, vec!["http://example.com/body/1", "http://example.com/body/2"]);
+    assert_eq!(systems_and_bodies, ids);
+    assert_eq!(lists.len(), 1);
+    assert_eq!(lists[0].0.as_str(), list_url);
+    assert_eq!(lists[0].1, array!["http://example.com/body/1", "http://example.com/body/2"]);
+    assert_eq!(unknown, 0);
+}
+
+/// Assert that storages without enumeration yield an error instead of silently returning nothing
+#[test]
+fn test_default_iteration() {
+    let storage = MinimalStorage { schema: JsonValue::new_object() };
+    let url = "http://example.com/".into_url().unwrap();
+
+    let objects = storage.iter_objects(&url, None).collect::<Vec<_>>();
+    assert_eq!(objects.len(), 1);
+    assert!(objects[0].is_err());
+    assert!(storage.iter_lists(&url).next().unwrap().is_err());
+    assert_eq!(storage.iter_objects_of(&url, &["Body", "Organization"]).filter(|x| x.is_err()).count(), 2);
+}
+
+/// Assert that the objects of other servers on the same host aren't returned, while the list
+/// elements outside of the folder of the entrypoint are
+#[test]
+fn test_iter_objects_per_server() {
+    let storage = storage();
+    cache_bodies(&storage,
+                 "http://example.com/a",
+                 &["http://example.com/a/body/1", "http://example.com/shared/body/9"]);
+    cache_bodies(&storage, "http://example.com/a/b", &["http://example.com/a/b/body/1"]);
+
+    let ids = |entrypoint: &str| {
+        storage.iter_objects(&entrypoint.into_url().unwrap(), None)
+            .map(|x| x.unwrap()["id"].to_string())
+            .collect::<Vec<_>>()
+    };
+    let outer = ids("http://example.com/a");
+    let inner = ids("http://example.com/a/b");
+
+    cleanup(&storage);
+
+    assert_eq!(outer,
+               vec!["http://example.com/a/body/1", "http://example.com/a", "http://example.com/shared/body/9"]);
+    assert_eq!(inner, vec!["http://example.com/a/b/body/1", "http://example.com/a/b"]);
+}
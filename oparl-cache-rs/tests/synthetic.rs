@@ -0,0 +1,85 @@
+extern crate oparl_cache;
+extern crate json;
+extern crate reqwest;
+
+mod common;
+
+use std::sync::{Arc, RwLock};
+
+use reqwest::Url;
+
+use oparl_cache::{Cacher, CommonServer, Server, Storage};
+use oparl_cache::synthetic::{DatasetConfig, Mutations, SyntheticDataset, serve};
+
+use common::*;
+
+fn config() -> DatasetConfig {
+    DatasetConfig {
+        papers: 7,
+        meetings: 2,
+        persons: 2,
+        files: 3,
+        page_size: 3,
+        ..DatasetConfig::default()
+    }
+}
+
+/// Assert that the lists are paginated and filtered
+#[test]
+fn synthetic_lists() {
+    let mut dataset = SyntheticDataset::new(config());
+    let papers = dataset.get_entrypoint().join("body/1/paper").unwrap();
+
+    let first_page = dataset.get_json(papers.clone()).unwrap();
+    assert_eq!(first_page["data"].len(), 3);
+    assert_eq!(first_page["pagination"]["totalElements"], 7);
+    // The files are embedded into the papers
+    assert_eq!(first_page["data"][0]["auxiliaryFile"][0]["type"], "https://schema.oparl.org/1.0/File");
+
+    let last_page = Url::parse(first_page["links"]["last"].as_str().unwrap()).unwrap();
+    let last_page = dataset.get_json(last_page).unwrap();
+    assert_eq!(last_page["data"].len(), 1);
+    assert!(last_page["links"]["next"].is_null());
+
+    dataset.mutate(&Mutations { added: 1, modified: 1, deleted: 1 });
+
+    let mut filtered = papers.clone();
+    filtered.query_pairs_mut().append_pair("modified_since", "2017-06-01T00:00:00+02:00");
+    let changed = dataset.get_json(filtered).unwrap();
+    assert_eq!(changed["data"].len(), 3);
+    assert_eq!(changed["data"][0]["name"], "Paper 1 (modified)");
+    assert_eq!(changed["data"][1]["deleted"], true);
+
+    let mut invalid = papers.clone();
+    invalid.query_pairs_mut().append_pair("modified_since", "yesterday");
+    assert!(dataset.get_json(invalid).is_err());
+    assert!(dataset.get_json(dataset.get_entrypoint().join("body/2").unwrap()).is_err());
+}
+
+/// Assert that a synthetic server can be cached over http, including an incremental update
+#[test]
+fn cache_synthetic_server() {
+    let dataset = Arc::new(RwLock::new(SyntheticDataset::new(config())));
+    let mut listening = serve("127.0.0.1:0", dataset.clone()).unwrap();
+    let entrypoint = dataset.read().unwrap().get_entrypoint();
+    let papers = entrypoint.join("body/1/paper").unwrap();
+
+    let storage = storage();
+    storage.cache(CommonServer::new(entrypoint.clone())).unwrap();
+    assert_eq!(storage.get(&papers).unwrap().len(), 7);
+    let meeting = storage.get(&entrypoint.join("body/1/meeting/1").unwrap()).unwrap();
+    assert_eq!(meeting["agendaItem"][0], entrypoint.join("body/1/meeting/1/agendaItem/1").unwrap().as_str());
+
+    dataset.write().unwrap().mutate(&Mutations { added: 2, modified: 1, deleted: 0 });
+    storage.cache(CommonServer::new(entrypoint.clone())).unwrap();
+
+    assert_eq!(storage.get(&papers).unwrap().len(), 9);
+    let paper = storage.get(&entrypoint.join("body/1/paper/1").unwrap()).unwrap();
+    assert_eq!(paper["name"], "Paper 1 (modified)");
+
+    let report = storage.get_sync_reports(&entrypoint).unwrap().pop().unwrap();
+    assert_eq!(report.failed_lists(), 0);
+
+    listening.close().unwrap();
+    cleanup(&storage);
+}